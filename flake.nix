@@ -65,6 +65,8 @@
 
             cargoLock.lockFile = ./Cargo.lock;
 
+            buildFeatures = [ "tinycc" ];
+
             nativeBuildInputs = [
               pkgs.installShellFiles
             ];
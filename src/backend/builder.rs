@@ -2,15 +2,21 @@
 
 use anyhow::{bail, Result};
 
-use crate::frontend::ast;
+use crate::{frontend::ast, utils::Span};
 
 use super::{
-    AgentId, AgentMeta, Function, FunctionMeta, Local, NetInitializer, NetInstruction, Program,
-    Rule, RuleInitializer, RuleInstruction,
+    native, AgentId, AgentMeta, Function, FunctionMeta, Local, NativeRule, NetInitializer,
+    NetInstruction, Program, Rule, RuleInitializer, RuleInstruction, Sugar,
 };
 
 struct Name(pub(crate) String);
 
+/// 新建的项
+enum NewTerm {
+    Agent(AgentId),
+    Int(i64),
+}
+
 enum ArgSlot {
     Left(usize),
     Right(usize),
@@ -22,6 +28,8 @@ pub struct RuntimeBuilder {
     global: GlobalBuilder,
     rules: RulesBuilder,
     functions: FunctionsBuilder,
+    /// 语法糖指令中的交互器
+    sugars: Vec<(ast::SugarKind, Vec<String>)>,
 }
 
 impl RuntimeBuilder {
@@ -32,6 +40,15 @@ impl RuntimeBuilder {
 
     /// 向运行时添加一个 `Program`。
     pub fn module(&mut self, module: ast::Module) -> Result<&mut Self> {
+        let has_top_level = module.has_top_level();
+        for sugar in module.sugars {
+            let agents = sugar.agents.iter().map(|agent| agent.to_string()).collect();
+            self.sugars.push((sugar.kind, agents));
+        }
+        for declaration in module.declarations {
+            self.global
+                .add_or_get_agent(&declaration.agent, declaration.ports.len())?;
+        }
         for rule in module.rules {
             self.rules.rule(&mut self.global, rule.into_inner())?;
         }
@@ -39,13 +56,19 @@ impl RuntimeBuilder {
             self.functions
                 .function(&mut self.global, net.into_inner())?;
         }
+        if has_top_level {
+            self.functions
+                .top_level(&mut self.global, module.statements, module.outputs)?;
+        }
 
         Ok(self)
     }
 
     /// 构建运行时。
-    pub fn build(self) -> Result<Program> {
-        let agents = self.global.build();
+    pub fn build(mut self) -> Result<Program> {
+        self.rules.builtin_rules(&mut self.global)?;
+        let sugar = self.sugar()?;
+        let (agents, variables) = self.global.build();
         let (rules, rule_map) = self.rules.build();
         let (functions, function_meta, entry_point) = self.functions.build()?;
 
@@ -53,12 +76,54 @@ impl RuntimeBuilder {
             agents,
             rules,
             rule_map,
+            native_rules: NativeRule::all(),
             functions,
             function_meta,
             entry_point,
+            variables,
+            sugar,
         })
     }
 
+    /// 语法糖指令中的交互器，程序中没有用到的交互器不会出现在结果中，忽略即可
+    fn sugar(&self) -> Result<Sugar> {
+        let mut sugar = Sugar::default();
+        for (kind, agents) in &self.sugars {
+            let find = |name: &str, arity: usize| match self.global.find_agent(name) {
+                Some((_, a)) if a != arity => bail!(
+                    "agent `{}` has arity {}, but `sugar {}` expects {}",
+                    name,
+                    a,
+                    kind,
+                    arity
+                ),
+                found => Ok(found.map(|(id, _)| id)),
+            };
+            match kind {
+                ast::SugarKind::List => {
+                    sugar.cons = find(&agents[0], 2)?;
+                    sugar.nil = find(&agents[1], 0)?;
+                }
+                ast::SugarKind::Number => {
+                    sugar.succ = find(&agents[0], 1)?;
+                    sugar.zero = find(&agents[1], 0)?;
+                }
+                ast::SugarKind::Tuple => {
+                    let found = self.global.find_agent(&agents[0]);
+                    if let Some((_, a)) = found.filter(|&(_, a)| a < 2) {
+                        bail!(
+                            "agent `{}` has arity {}, but `sugar tuple` expects at least 2",
+                            agents[0],
+                            a
+                        );
+                    }
+                    sugar.tuple = found.map(|(id, _)| id);
+                }
+            }
+        }
+        Ok(sugar)
+    }
+
     /// 从 `Program` 构建运行时。
     pub fn build_runtime(program: ast::Module) -> Result<Program> {
         let mut builder = Self::new();
@@ -69,12 +134,17 @@ impl RuntimeBuilder {
 
 struct GlobalBuilder {
     agents: Vec<AgentMeta>,
+    variables: Vec<String>,
 }
 
 impl Default for GlobalBuilder {
     fn default() -> Self {
         Self {
-            agents: vec![AgentMeta::new("$", 1)],
+            agents: native::builtin_agents()
+                .into_iter()
+                .map(|agent| AgentMeta::new(agent.name.to_string(), agent.ports.len()))
+                .collect(),
+            variables: vec![],
         }
     }
 }
@@ -99,8 +169,39 @@ impl GlobalBuilder {
         }
     }
 
-    pub(crate) fn build(self) -> Vec<AgentMeta> {
+    /// 已有的交互器及其元数
+    pub(crate) fn find_agent(&self, name: &str) -> Option<(AgentId, usize)> {
         self.agents
+            .iter()
+            .position(|meta| meta.name == name)
+            .map(|id| (AgentId(id), self.agents[id].arity))
+    }
+
+    /// 是否为行为由编译器决定的内置交互器
+    ///
+    /// 运算交互器、等待第二个操作数的交互器以及 `True` 与 `False` 虽然是内置交互器，
+    /// 但和普通的交互器一样生成 `Dup` 与 `Erase` 规则。
+    pub(crate) fn is_builtin(id: AgentId) -> bool {
+        id.0 < native::FIRST_OP
+    }
+
+    /// 变量名称在 `Program::variables` 中的下标，展开模式时生成的变量以数字开头，没有源代码中的名称
+    pub(crate) fn add_or_get_variable(&mut self, name: &str) -> Option<usize> {
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+        let id = match self.variables.iter().position(|n| n == name) {
+            Some(id) => id,
+            None => {
+                self.variables.push(name.to_string());
+                self.variables.len() - 1
+            }
+        };
+        Some(id)
+    }
+
+    pub(crate) fn build(self) -> (Vec<AgentMeta>, Vec<String>) {
+        (self.agents, self.variables)
     }
 }
 
@@ -108,7 +209,7 @@ impl GlobalBuilder {
 struct RuleBuilder {
     arguments: Vec<(Name, ArgSlot)>,
     names: Vec<Name>,
-    terms: Vec<AgentId>,
+    terms: Vec<NewTerm>,
     instructions: Vec<RuleInstruction>,
 }
 
@@ -118,6 +219,26 @@ impl RuleBuilder {
         self
     }
 
+    /// 规则左侧交互器的端口：变量绑定到端口，通配符端口上的值交给 `Erase`
+    fn ports(&mut self, term: &ast::RuleTerm, slot: fn(usize) -> ArgSlot) {
+        for (i, pattern) in term.body.iter().enumerate() {
+            match pattern {
+                ast::Pattern::Name(name) => {
+                    self.slot(name.as_name().to_string(), slot(i + 1));
+                }
+                ast::Pattern::Wildcard(_) => {
+                    // 通配符没有名称，不会与变量混淆
+                    self.arguments.push((Name(String::new()), slot(i + 1)));
+                    let value = Local::Slot(self.arguments.len() - 1);
+                    let erase = self.add_term(AgentId::ERASE);
+                    self.push_equation(value, erase, "_ -> Erase".to_string());
+                }
+                // 嵌套模式在检查之前已经展开
+                ast::Pattern::Agent(_) => unreachable!(),
+            }
+        }
+    }
+
     fn add_or_get_name(&mut self, name: &str) -> Local {
         if let Some(id) = self
             .arguments
@@ -145,10 +266,32 @@ impl RuleBuilder {
 
     fn add_term(&mut self, agent_id: AgentId) -> Local {
         let id = self.terms.len();
-        self.terms.push(agent_id);
+        self.terms.push(NewTerm::Agent(agent_id));
+        Local::Agent(id)
+    }
+
+    fn add_int(&mut self, value: i64) -> Local {
+        let id = self.terms.len();
+        self.terms.push(NewTerm::Int(value));
         Local::Agent(id)
     }
 
+    fn set_slot(&mut self, target: Local, slot: usize, value: Local) {
+        self.instructions.push(RuleInstruction::SetSlot {
+            target,
+            slot,
+            value,
+        })
+    }
+
+    fn push_equation(&mut self, left: Local, right: Local, description: String) {
+        self.instructions.push(RuleInstruction::PushEquation {
+            left,
+            right,
+            description,
+        });
+    }
+
     pub(crate) fn term(&mut self, global: &mut GlobalBuilder, term: ast::Term) -> Result<Local> {
         use ast::*;
         match term {
@@ -156,6 +299,7 @@ impl RuleBuilder {
                 let term_name = self.add_or_get_name(name.as_name());
                 Ok(term_name)
             }
+            Term::Int(value) => Ok(self.add_int(*value)),
             Term::Agent(agent) => {
                 let Agent { name, body } = agent.into_inner();
                 let agent_id = global.add_or_get_agent(&name, body.len())?;
@@ -163,15 +307,13 @@ impl RuleBuilder {
 
                 for (i, term) in body.into_iter().enumerate() {
                     let sub_name = self.term(global, term)?;
-                    self.instructions.push(RuleInstruction::SetSlot {
-                        target: term_name,
-                        slot: i + 1,
-                        value: sub_name,
-                    })
+                    self.set_slot(term_name, i + 1, sub_name);
                 }
 
                 Ok(term_name)
             }
+            // 字面量在检查之前已经展开
+            Term::Literal(_) => unreachable!(),
         }
     }
 
@@ -184,15 +326,14 @@ impl RuleBuilder {
         let ast::Equation { left, right } = equation;
         let left_name = self.term(global, left)?;
         let right_name = self.term(global, right)?;
-        self.instructions.push(RuleInstruction::PushEquation {
-            left: left_name,
-            right: right_name,
-            description,
-        });
+        self.push_equation(left_name, right_name, description);
         Ok(self)
     }
 
-    pub(crate) fn build(self) -> Result<(Vec<RuleInitializer>, Vec<RuleInstruction>)> {
+    pub(crate) fn build(
+        self,
+        global: &mut GlobalBuilder,
+    ) -> Result<(Vec<RuleInitializer>, Vec<RuleInstruction>)> {
         let arguments =
             self.arguments
                 .into_iter()
@@ -205,12 +346,18 @@ impl RuleBuilder {
             .names
             .into_iter()
             .enumerate()
-            .map(|(index, _)| RuleInitializer::Name { index });
+            .map(|(index, Name(name))| RuleInitializer::Name {
+                index,
+                variable: global.add_or_get_variable(&name),
+            });
         let terms = self
             .terms
             .into_iter()
             .enumerate()
-            .map(|(index, id)| RuleInitializer::Agent { index, id });
+            .map(|(index, term)| match term {
+                NewTerm::Agent(id) => RuleInitializer::Agent { index, id },
+                NewTerm::Int(value) => RuleInitializer::Int { index, value },
+            });
         let initailizers = arguments.chain(names).chain(terms).collect::<Vec<_>>();
         let mut instructions = self.instructions;
         instructions.push(RuleInstruction::FreeLeft);
@@ -254,18 +401,26 @@ impl RulesBuilder {
             (term2, term1)
         };
 
-        for (i, name) in term_left.into_inner().body.into_iter().enumerate() {
-            body.slot(name.as_name().to_string(), ArgSlot::Left(i + 1));
-        }
-        for (i, name) in term_right.into_inner().body.into_iter().enumerate() {
-            body.slot(name.as_name().to_string(), ArgSlot::Right(i + 1));
-        }
+        body.ports(&term_left, ArgSlot::Left);
+        body.ports(&term_right, ArgSlot::Right);
 
         for equation in equations {
             body.equation(global, equation.into_inner())?;
         }
 
-        let (initializers, instructions) = body.build()?;
+        self.push_rule(global, a_left, a_right, description, body)?;
+        Ok(self)
+    }
+
+    fn push_rule(
+        &mut self,
+        global: &mut GlobalBuilder,
+        a_left: AgentId,
+        a_right: AgentId,
+        description: String,
+        body: RuleBuilder,
+    ) -> Result<()> {
+        let (initializers, instructions) = body.build(global)?;
         let index = self.rules.len();
         self.rules.push(Rule {
             index,
@@ -274,10 +429,153 @@ impl RulesBuilder {
             instructions,
         });
         self.rule_map.push((a_left, a_right, index));
+        Ok(())
+    }
+
+    fn has_rule(&self, a_left: AgentId, a_right: AgentId) -> bool {
+        self.rule_map
+            .iter()
+            .any(|&(l, r, _)| (l, r) == (a_left, a_right))
+    }
+
+    /// 生成内置的 `Dup` 与 `Erase` 规则，已由用户定义的规则不会被覆盖。
+    pub(crate) fn builtin_rules(&mut self, global: &mut GlobalBuilder) -> Result<&mut Self> {
+        if !self.has_rule(AgentId::DUP, AgentId::DUP) {
+            let (description, body) = Self::dup_dup_rule();
+            self.push_rule(global, AgentId::DUP, AgentId::DUP, description, body)?;
+        }
+        if !self.has_rule(AgentId::ERASE, AgentId::ERASE) {
+            let description = "Erase >> Erase => _".to_string();
+            self.push_rule(
+                global,
+                AgentId::ERASE,
+                AgentId::ERASE,
+                description,
+                RuleBuilder::default(),
+            )?;
+        }
+
+        for id in 0..global.agents.len() {
+            let (id, meta) = (AgentId(id), global.agents[id].clone());
+            if GlobalBuilder::is_builtin(id) {
+                continue;
+            }
+            if !self.has_rule(AgentId::DUP, id) {
+                let (description, body) = Self::dup_rule(id, &meta);
+                self.push_rule(global, AgentId::DUP, id, description, body)?;
+            }
+            if !self.has_rule(AgentId::ERASE, id) {
+                let (description, body) = Self::erase_rule(&meta);
+                self.push_rule(global, AgentId::ERASE, id, description, body)?;
+            }
+        }
 
         Ok(self)
     }
 
+    /// `Dup(@a, @b) >> Dup(@c, @d) => @a -> @c, @b -> @d`
+    fn dup_dup_rule() -> (String, RuleBuilder) {
+        let mut body = RuleBuilder::default();
+        body.slot("a".to_string(), ArgSlot::Left(1))
+            .slot("b".to_string(), ArgSlot::Left(2))
+            .slot("c".to_string(), ArgSlot::Right(1))
+            .slot("d".to_string(), ArgSlot::Right(2));
+
+        let [a, b, c, d] = ["a", "b", "c", "d"].map(|name| body.add_or_get_name(name));
+        body.push_equation(a, c, "@a -> @c".to_string());
+        body.push_equation(b, d, "@b -> @d".to_string());
+
+        let description = "Dup(@a, @b) >> Dup(@c, @d) => @a -> @c, @b -> @d".to_string();
+        (description, body)
+    }
+
+    /// `A(#x0, ...) >> Dup(@a, @b) => #x0 -> Dup(@u0, @v0), ..., A(#u0, ...) -> @a, A(#v0, ...) -> @b`
+    fn dup_rule(id: AgentId, meta: &AgentMeta) -> (String, RuleBuilder) {
+        let mut body = RuleBuilder::default();
+        body.slot("a".to_string(), ArgSlot::Left(1))
+            .slot("b".to_string(), ArgSlot::Left(2));
+        for i in 0..meta.arity {
+            body.slot(format!("x{i}"), ArgSlot::Right(i + 1));
+        }
+
+        let a = body.add_or_get_name("a");
+        let b = body.add_or_get_name("b");
+        let copy_a = body.add_term(id);
+        let copy_b = body.add_term(id);
+
+        let mut equations = vec![];
+        for i in 0..meta.arity {
+            let x = body.add_or_get_name(&format!("x{i}"));
+            let u = body.add_or_get_name(&format!("u{i}"));
+            let v = body.add_or_get_name(&format!("v{i}"));
+            let dup = body.add_term(AgentId::DUP);
+            body.set_slot(dup, 1, u);
+            body.set_slot(dup, 2, v);
+            body.set_slot(copy_a, i + 1, u);
+            body.set_slot(copy_b, i + 1, v);
+
+            let equation = format!("#x{i} -> Dup(@u{i}, @v{i})");
+            body.push_equation(x, dup, equation.clone());
+            equations.push(equation);
+        }
+
+        let copy_a_desc = Self::describe_agent(&meta.name, "#u", meta.arity);
+        let copy_b_desc = Self::describe_agent(&meta.name, "#v", meta.arity);
+        let equation = format!("{copy_a_desc} -> @a");
+        body.push_equation(copy_a, a, equation.clone());
+        equations.push(equation);
+        let equation = format!("{copy_b_desc} -> @b");
+        body.push_equation(copy_b, b, equation.clone());
+        equations.push(equation);
+
+        let description = format!(
+            "{} >> Dup(@a, @b) => {}",
+            Self::describe_agent(&meta.name, "#x", meta.arity),
+            equations.join(", ")
+        );
+        (description, body)
+    }
+
+    /// `A(#x0, ...) >> Erase => #x0 -> Erase, ...`
+    fn erase_rule(meta: &AgentMeta) -> (String, RuleBuilder) {
+        let mut body = RuleBuilder::default();
+        for i in 0..meta.arity {
+            body.slot(format!("x{i}"), ArgSlot::Right(i + 1));
+        }
+
+        let mut equations = vec![];
+        for i in 0..meta.arity {
+            let x = body.add_or_get_name(&format!("x{i}"));
+            let erase = body.add_term(AgentId::ERASE);
+            let equation = format!("#x{i} -> Erase");
+            body.push_equation(x, erase, equation.clone());
+            equations.push(equation);
+        }
+
+        let description = format!(
+            "{} >> Erase => {}",
+            Self::describe_agent(&meta.name, "#x", meta.arity),
+            if equations.is_empty() {
+                "_".to_string()
+            } else {
+                equations.join(", ")
+            }
+        );
+        (description, body)
+    }
+
+    fn describe_agent(name: &str, prefix: &str, arity: usize) -> String {
+        if arity == 0 {
+            name.to_string()
+        } else {
+            let ports = (0..arity)
+                .map(|i| format!("{prefix}{i}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{name}({ports})")
+        }
+    }
+
     pub(crate) fn build(self) -> (Vec<Rule>, Vec<(AgentId, AgentId, usize)>) {
         (self.rules, self.rule_map)
     }
@@ -286,7 +584,7 @@ impl RulesBuilder {
 #[derive(Default)]
 struct FunctionBuilder {
     names: Vec<Name>,
-    terms: Vec<AgentId>,
+    terms: Vec<NewTerm>,
     instructions: Vec<NetInstruction>,
 }
 
@@ -310,7 +608,13 @@ impl FunctionBuilder {
 
     fn add_term(&mut self, agent_id: AgentId) -> Local {
         let id = self.terms.len();
-        self.terms.push(agent_id);
+        self.terms.push(NewTerm::Agent(agent_id));
+        Local::Agent(id)
+    }
+
+    fn add_int(&mut self, value: i64) -> Local {
+        let id = self.terms.len();
+        self.terms.push(NewTerm::Int(value));
         Local::Agent(id)
     }
 
@@ -321,6 +625,7 @@ impl FunctionBuilder {
                 let term_name = self.add_or_get_name(name.as_name());
                 Ok(term_name)
             }
+            Term::Int(value) => Ok(self.add_int(*value)),
             Term::Agent(agent) => {
                 let Agent { name, body } = agent.into_inner();
                 let agent_id = global.add_or_get_agent(&name, body.len())?;
@@ -337,6 +642,8 @@ impl FunctionBuilder {
 
                 Ok(term_name)
             }
+            // 字面量在检查之前已经展开
+            Term::Literal(_) => unreachable!(),
         }
     }
 
@@ -357,17 +664,26 @@ impl FunctionBuilder {
         Ok(self)
     }
 
-    pub(crate) fn build(self) -> Result<(Vec<NetInitializer>, Vec<NetInstruction>)> {
+    pub(crate) fn build(
+        self,
+        global: &mut GlobalBuilder,
+    ) -> Result<(Vec<NetInitializer>, Vec<NetInstruction>)> {
         let names = self
             .names
             .into_iter()
             .enumerate()
-            .map(|(index, _)| NetInitializer::Name { index });
+            .map(|(index, Name(name))| NetInitializer::Name {
+                index,
+                variable: global.add_or_get_variable(&name),
+            });
         let terms = self
             .terms
             .into_iter()
             .enumerate()
-            .map(|(index, id)| NetInitializer::Agent { index, id });
+            .map(|(index, term)| match term {
+                NewTerm::Agent(id) => NetInitializer::Agent { index, id },
+                NewTerm::Int(value) => NetInitializer::Int { index, value },
+            });
         let initailizers = names.chain(terms).collect::<Vec<_>>();
         let instructions = self.instructions;
 
@@ -396,24 +712,45 @@ impl FunctionsBuilder {
         global: &mut GlobalBuilder,
         function: ast::Net,
     ) -> Result<&mut Self> {
-        if *function.name == "Main" {
+        let name = function.name.as_ref().to_string();
+        self.push_function(global, name, function.equations, function.interfaces)
+    }
+
+    /// 顶级语句与输出构成入口函数 `Main`
+    pub(crate) fn top_level(
+        &mut self,
+        global: &mut GlobalBuilder,
+        statements: Vec<Span<ast::Equation>>,
+        outputs: Vec<ast::Term>,
+    ) -> Result<&mut Self> {
+        self.push_function(global, "Main".to_string(), statements, outputs)
+    }
+
+    fn push_function(
+        &mut self,
+        global: &mut GlobalBuilder,
+        name: String,
+        equations: Vec<Span<ast::Equation>>,
+        interfaces: Vec<ast::Term>,
+    ) -> Result<&mut Self> {
+        if name == "Main" {
             self.entry_point(self.functions.len())?;
         }
 
         let mut body = FunctionBuilder::default();
         let mut outputs = vec![];
-        let output_count = function.interfaces.len();
+        let output_count = interfaces.len();
         outputs.reserve(output_count);
 
-        for equation in function.equations {
+        for equation in equations {
             body.equation(global, equation.into_inner())?;
         }
-        for interface in function.interfaces {
+        for interface in interfaces {
             let term = body.term(global, interface)?;
             outputs.push(term);
         }
 
-        let (initializers, instructions) = body.build()?;
+        let (initializers, instructions) = body.build(global)?;
         let index = self.functions.len();
         self.functions.push(Function {
             index,
@@ -421,10 +758,7 @@ impl FunctionsBuilder {
             instructions,
             outputs,
         });
-        self.function_meta.push(FunctionMeta {
-            name: function.name.as_ref().to_string(),
-            output_count,
-        });
+        self.function_meta.push(FunctionMeta { name, output_count });
         Ok(self)
     }
 
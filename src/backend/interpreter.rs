@@ -0,0 +1,1242 @@
+//! 解释器。
+//!
+//! 在 Rust 中直接执行 IR，行为与 C 目标的运行时一致：相同的方程栈顺序、
+//! 相同的变量编号与相同的规约计数，便于逐步调试。
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use super::{
+    super::options::ResultFormat, AgentId, Local, NativeRule, NetInitializer, NetInstruction,
+    Program, RuleInitializer, RuleInstruction,
+};
+
+/// 堆中的地址
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Addr(pub usize);
+
+/// 堆中的项
+#[derive(Clone, Debug)]
+pub enum Cell {
+    /// 交互器
+    Agent {
+        /// 交互器编号
+        id: AgentId,
+        /// 辅助端口
+        ports: Vec<Addr>,
+    },
+    /// 整数
+    Int(i64),
+    /// 尚未连接的变量
+    Name {
+        /// 变量编号
+        id: usize,
+        /// 源代码中的变量名称在 `Program::variables` 中的下标
+        variable: Option<usize>,
+    },
+    /// 已连接的变量，指向另一侧的项
+    Link(Addr),
+    /// 已释放
+    Free,
+}
+
+/// 规则
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RuleRef {
+    /// 程序中的规则，即 `Program::rules` 的下标
+    Rule(usize),
+    /// 原生规则
+    Native(NativeRule),
+}
+
+/// 规约的统计信息，与 C 目标的 `--profile` 一致
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// 每条规则应用的次数，依次为 `Program::rules` 与 `Program::native_rules` 中的规则
+    pub counts: Vec<usize>,
+    /// 每条规则花费的时间，仅在同时计时时统计
+    pub times: Option<Vec<Duration>>,
+    /// 方程栈的最大深度
+    pub peak_stack: usize,
+    /// 同时存活的交互器数量的最大值
+    pub peak_agents: usize,
+}
+
+impl Profile {
+    fn new(program: &Program, timing: bool) -> Self {
+        let rules = program.rules.len() + program.native_rules.len();
+        Self {
+            counts: vec![0; rules],
+            times: timing.then(|| vec![Duration::ZERO; rules]),
+            peak_stack: 0,
+            peak_agents: 0,
+        }
+    }
+
+    /// 以与 C 目标相同的格式输出报告，规则按应用次数从多到少排列，省略未应用过的规则
+    pub fn report(&self, program: &Program) -> String {
+        let descriptions = program
+            .rules
+            .iter()
+            .map(|rule| rule.description.clone())
+            .chain(program.native_rules.iter().map(|rule| rule.description()))
+            .collect::<Vec<_>>();
+        let mut slots = (0..self.counts.len())
+            .filter(|&slot| self.counts[slot] > 0)
+            .collect::<Vec<_>>();
+        slots.sort_by_key(|&slot| std::cmp::Reverse(self.counts[slot]));
+
+        let mut report = String::from("\n[Profile]\n");
+        match &self.times {
+            Some(_) => report.push_str(&format!("{:>10}  {:>10}  rule\n", "count", "time (s)")),
+            None => report.push_str(&format!("{:>10}  rule\n", "count")),
+        }
+        for slot in slots {
+            match &self.times {
+                Some(times) => report.push_str(&format!(
+                    "{:>10}  {:>10.6}  {}\n",
+                    self.counts[slot],
+                    times[slot].as_secs_f64(),
+                    descriptions[slot]
+                )),
+                None => report.push_str(&format!(
+                    "{:>10}  {}\n",
+                    self.counts[slot], descriptions[slot]
+                )),
+            }
+        }
+        report.push_str(&format!(
+            "[Peak stack depth: {}, peak live agents: {}]\n",
+            self.peak_stack, self.peak_agents
+        ));
+        report
+    }
+}
+
+/// 规则的编号，依次为 `Program::rules` 与 `Program::native_rules` 中的规则，与 C 目标一致
+fn rule_slot(program: &Program, rule: RuleRef) -> usize {
+    match rule {
+        RuleRef::Rule(index) => index,
+        RuleRef::Native(rule) => {
+            let index = program.native_rules.iter().position(|&r| r == rule);
+            program.rules.len() + index.expect("native rule in program")
+        }
+    }
+}
+
+/// JSON 跟踪中的一行，对应一次相互作用
+#[derive(Serialize)]
+struct TraceRecord<'a> {
+    /// 包括这次相互作用在内的规约次数
+    step: usize,
+    left: &'a str,
+    right: &'a str,
+    /// 规则的编号
+    rule: usize,
+    /// 规则压入的方程
+    pushed: Vec<(String, String)>,
+}
+
+/// 栈顶的活跃对
+///
+/// 存在规则时，`left` 的交互器编号不大于 `right`，与规则中的顺序一致。
+#[derive(Clone, Copy, Debug)]
+pub struct ActivePair {
+    /// 左侧交互器
+    pub left: Addr,
+    /// 右侧交互器
+    pub right: Addr,
+    /// 适用的规则，不存在时为 `None`
+    pub rule: Option<RuleRef>,
+}
+
+/// 运行时错误
+#[derive(Debug, Error)]
+#[allow(missing_docs)]
+pub enum RuntimeError {
+    #[error("no rule for {left} and {right}")]
+    NoRule { left: String, right: String },
+
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("stack overflow")]
+    StackOverflow,
+
+    #[error("reduction limit of {0} exceeded")]
+    ReductionLimit(usize),
+
+    #[error("agent limit of {0} exceeded")]
+    AgentLimit(usize),
+}
+
+impl RuntimeError {
+    /// 是否是因超出规约次数或交互器数量的上限而停止，此时网络仍然完整，可以显示部分结果
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            RuntimeError::ReductionLimit(_) | RuntimeError::AgentLimit(_)
+        )
+    }
+
+    /// 以 C 目标的格式输出错误信息与提示
+    pub fn report(&self, mut f: impl Write) -> std::io::Result<()> {
+        writeln!(f, "\x1b[31merror\x1b[0m: {}", self)?;
+        let hint = match self {
+            RuntimeError::StackOverflow => {
+                Some("try to increase the stack limit with `--max-stack-size`")
+            }
+            RuntimeError::ReductionLimit(_) => {
+                Some("try to increase the limit with `--max-reductions`")
+            }
+            RuntimeError::AgentLimit(_) => Some("try to increase the limit with `--max-agents`"),
+            _ => None,
+        };
+        if let Some(hint) = hint {
+            writeln!(f, "\x1b[33mhint\x1b[0m: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// 解释器
+pub struct Machine {
+    program: Rc<Program>,
+    rules: HashMap<(AgentId, AgentId), RuleRef>,
+    heap: Vec<Cell>,
+    free: Vec<usize>,
+    stack: Vec<(Addr, Addr)>,
+    stack_limit: Option<usize>,
+    reduction_limit: Option<usize>,
+    agent_limit: Option<usize>,
+    trace: Option<Box<dyn Write>>,
+    json_trace: Option<Box<dyn Write>>,
+    name_counter: usize,
+    reductions: usize,
+    agents: usize,
+    profile: Option<Profile>,
+    outputs: Vec<Addr>,
+}
+
+impl Machine {
+    /// 创建解释器，并执行入口网络
+    pub fn new(program: Program) -> Self {
+        Self::with_stack_limit(program, None).expect("unbounded stack never overflows")
+    }
+
+    /// 创建方程栈大小受限的解释器，并执行入口网络
+    ///
+    /// 与 C 目标的 `--max-stack-size` 一致，方程栈超过 `stack_limit` 时返回 [`RuntimeError::StackOverflow`]。
+    pub fn with_stack_limit(
+        program: Program,
+        stack_limit: Option<usize>,
+    ) -> Result<Self, RuntimeError> {
+        let mut rules = HashMap::new();
+        for &(left, right, index) in &program.rule_map {
+            rules.insert((left, right), RuleRef::Rule(index));
+        }
+        for &rule in &program.native_rules {
+            rules.insert(rule.agents(), RuleRef::Native(rule));
+        }
+
+        let mut machine = Self {
+            name_counter: program.agents.len(),
+            program: Rc::new(program),
+            rules,
+            heap: vec![],
+            free: vec![],
+            stack: vec![],
+            stack_limit,
+            reduction_limit: None,
+            agent_limit: None,
+            trace: None,
+            json_trace: None,
+            reductions: 0,
+            agents: 0,
+            profile: None,
+            outputs: vec![],
+        };
+        machine.outputs = machine.call(machine.program.entry_point)?;
+        Ok(machine)
+    }
+
+    /// 将每个出栈的方程以与 C 目标 `--trace` 相同的格式写入 `trace`
+    pub fn set_trace(&mut self, trace: impl Write + 'static) {
+        self.trace = Some(Box::new(trace));
+    }
+
+    /// 设置规约次数与存活交互器数量的上限，与 C 目标的 `--max-reductions` 与 `--max-agents` 一致
+    ///
+    /// 每次应用规则前检查，超出时返回 [`RuntimeError::ReductionLimit`] 或 [`RuntimeError::AgentLimit`]，
+    /// 活跃对仍保留在栈顶。
+    pub fn set_limits(&mut self, reductions: Option<usize>, agents: Option<usize>) {
+        self.reduction_limit = reductions;
+        self.agent_limit = agents;
+    }
+
+    /// 将每次相互作用以与 C 目标 `--trace-file` 相同的 JSON 行格式写入 `trace`
+    pub fn set_json_trace(&mut self, trace: impl Write + 'static) {
+        self.json_trace = Some(Box::new(trace));
+    }
+
+    /// 统计每条规则的应用次数以及方程栈与交互器数量的峰值，`timing` 时同时统计每条规则花费的时间
+    ///
+    /// 在创建解释器之后开始统计，入口网络创建的交互器与方程计入峰值。
+    pub fn set_profile(&mut self, timing: bool) {
+        let mut profile = Profile::new(&self.program, timing);
+        profile.peak_stack = self.stack.len();
+        profile.peak_agents = self.agents;
+        self.profile = Some(profile);
+    }
+
+    /// 统计信息，未调用 [`Machine::set_profile`] 时为 `None`
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// 程序
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// 已完成的规约次数
+    pub fn reductions(&self) -> usize {
+        self.reductions
+    }
+
+    /// 存活的交互器数量，包括整数
+    pub fn agents(&self) -> usize {
+        self.agents
+    }
+
+    /// 方程栈，栈顶在最后
+    pub fn stack(&self) -> &[(Addr, Addr)] {
+        &self.stack
+    }
+
+    /// 入口网络的输出
+    pub fn outputs(&self) -> &[Addr] {
+        &self.outputs
+    }
+
+    /// 堆中的项，地址无效时返回 `None`
+    pub fn cell(&self, addr: Addr) -> Option<&Cell> {
+        match self.heap.get(addr.0) {
+            Some(Cell::Free) | None => None,
+            cell => cell,
+        }
+    }
+
+    fn alloc(&mut self, cell: Cell) -> Addr {
+        if is_agent(&cell) {
+            self.agents += 1;
+            if let Some(profile) = &mut self.profile {
+                profile.peak_agents = profile.peak_agents.max(self.agents);
+            }
+        }
+        if let Some(index) = self.free.pop() {
+            self.heap[index] = cell;
+            Addr(index)
+        } else {
+            self.heap.push(cell);
+            Addr(self.heap.len() - 1)
+        }
+    }
+
+    fn dealloc(&mut self, addr: Addr) {
+        if is_agent(&self.heap[addr.0]) {
+            self.agents -= 1;
+        }
+        self.heap[addr.0] = Cell::Free;
+        self.free.push(addr.0);
+    }
+
+    fn new_agent(&mut self, id: AgentId) -> Addr {
+        let arity = self.program.agents[id.0].arity;
+        self.alloc(Cell::Agent {
+            id,
+            ports: vec![Addr(usize::MAX); arity],
+        })
+    }
+
+    fn push_equation(&mut self, left: Addr, right: Addr) -> Result<(), RuntimeError> {
+        if self
+            .stack_limit
+            .is_some_and(|limit| self.stack.len() >= limit)
+        {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.stack.push((left, right));
+        if let Some(profile) = &mut self.profile {
+            profile.peak_stack = profile.peak_stack.max(self.stack.len());
+        }
+        Ok(())
+    }
+
+    fn trace_equation(&mut self, left: Addr, right: Addr) {
+        if self.trace.is_none() {
+            return;
+        }
+        let line = format!(
+            "\x1b[90m{} = {}\x1b[0m",
+            self.display(left, 3),
+            self.display(right, 3)
+        );
+        if let Some(trace) = &mut self.trace {
+            // 与 C 运行时一样，跟踪输出失败不影响规约
+            let _ = writeln!(trace, "{}", line);
+        }
+    }
+
+    fn new_name(&mut self, variable: Option<usize>) -> Addr {
+        let id = self.name_counter;
+        self.name_counter += 1;
+        self.alloc(Cell::Name { id, variable })
+    }
+
+    fn set_slot(&mut self, target: Addr, slot: usize, value: Addr) {
+        match &mut self.heap[target.0] {
+            Cell::Agent { ports, .. } => ports[slot - 1] = value,
+            cell => unreachable!("set slot of {:?}", cell),
+        }
+    }
+
+    fn port(&self, addr: Addr, slot: usize) -> Addr {
+        match &self.heap[addr.0] {
+            Cell::Agent { ports, .. } => ports[slot - 1],
+            cell => unreachable!("get slot of {:?}", cell),
+        }
+    }
+
+    /// 项的交互器编号，变量与间接返回 `None`
+    pub fn agent_id(&self, addr: Addr) -> Option<AgentId> {
+        match &self.heap[addr.0] {
+            Cell::Agent { id, .. } => Some(*id),
+            Cell::Int(_) => Some(AgentId::INT),
+            _ => None,
+        }
+    }
+
+    /// 执行网络，返回其输出
+    fn call(&mut self, function: usize) -> Result<Vec<Addr>, RuntimeError> {
+        let program = self.program.clone();
+        let function = &program.functions[function];
+        let mut locals = HashMap::new();
+        for initializer in &function.initializers {
+            let (local, addr) = match *initializer {
+                NetInitializer::Name { index, variable } => {
+                    (Local::Name(index), self.new_name(variable))
+                }
+                NetInitializer::Agent { index, id } => (Local::Agent(index), self.new_agent(id)),
+                NetInitializer::Int { index, value } => {
+                    (Local::Agent(index), self.alloc(Cell::Int(value)))
+                }
+            };
+            locals.insert(local, addr);
+        }
+        for instruction in &function.instructions {
+            match *instruction {
+                NetInstruction::SetSlot {
+                    target,
+                    slot,
+                    value,
+                } => self.set_slot(locals[&target], slot, locals[&value]),
+                NetInstruction::PushEquation { left, right, .. } => {
+                    self.push_equation(locals[&left], locals[&right])?
+                }
+            }
+        }
+        Ok(function
+            .outputs
+            .iter()
+            .map(|output| locals[output])
+            .collect())
+    }
+
+    /// 处理栈顶的间接与变量连接，直到栈顶为活跃对
+    ///
+    /// 活跃对仍保留在栈顶，由 [`Machine::reduce`] 规约；栈为空时返回 `None`。
+    pub fn next_active_pair(&mut self) -> Option<ActivePair> {
+        loop {
+            let &(left, right) = self.stack.last()?;
+            if !(is_agent(&self.heap[left.0]) && is_agent(&self.heap[right.0])) {
+                self.trace_equation(left, right);
+            }
+
+            // 间接
+            if let Cell::Link(target) = self.heap[left.0] {
+                self.stack.pop();
+                self.reductions += 1;
+                self.stack.push((target, right));
+                self.dealloc(left);
+                continue;
+            }
+            if let Cell::Link(target) = self.heap[right.0] {
+                self.stack.pop();
+                self.reductions += 1;
+                self.stack.push((left, target));
+                self.dealloc(right);
+                continue;
+            }
+
+            // 变量
+            if let Cell::Name { .. } = self.heap[left.0] {
+                self.stack.pop();
+                self.reductions += 1;
+                self.heap[left.0] = Cell::Link(right);
+                continue;
+            }
+            if let Cell::Name { .. } = self.heap[right.0] {
+                self.stack.pop();
+                self.reductions += 1;
+                self.heap[right.0] = Cell::Link(left);
+                continue;
+            }
+
+            // 相互作用
+            let a_left = self.agent_id(left).unwrap();
+            let a_right = self.agent_id(right).unwrap();
+            let rule = self
+                .rules
+                .get(&(a_left.min(a_right), a_left.max(a_right)))
+                .copied();
+            let (left, right) = if a_left <= a_right || rule.is_none() {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            return Some(ActivePair { left, right, rule });
+        }
+    }
+
+    /// 规约下一个活跃对，返回被规约的活跃对；栈为空时返回 `None`
+    pub fn reduce(&mut self) -> Result<Option<ActivePair>, RuntimeError> {
+        let Some(pair) = self.next_active_pair() else {
+            return Ok(None);
+        };
+        let &(left, right) = self.stack.last().unwrap();
+        self.trace_equation(left, right);
+        if let Some(limit) = self
+            .reduction_limit
+            .filter(|&limit| self.reductions >= limit)
+        {
+            return Err(RuntimeError::ReductionLimit(limit));
+        }
+        if let Some(limit) = self.agent_limit.filter(|&limit| self.agents > limit) {
+            return Err(RuntimeError::AgentLimit(limit));
+        }
+        let Some(rule) = pair.rule else {
+            return Err(RuntimeError::NoRule {
+                left: self.display(pair.left, 3),
+                right: self.display(pair.right, 3),
+            });
+        };
+
+        self.stack.pop();
+        self.reductions += 1;
+        // 规则可能复用或释放活跃对，先记下跟踪所需的交互器
+        let stack_size = self.stack.len();
+        let agents = self
+            .json_trace
+            .is_some()
+            .then(|| [pair.left, pair.right].map(|addr| self.agent_id(addr).unwrap()));
+        let start = self
+            .profile
+            .as_ref()
+            .and_then(|profile| profile.times.as_ref())
+            .map(|_| Instant::now());
+        match rule {
+            RuleRef::Rule(index) => self.apply_rule(index, pair.left, pair.right)?,
+            RuleRef::Native(rule) => self.apply_native(rule, pair.left, pair.right)?,
+        }
+        if let Some(profile) = &mut self.profile {
+            let slot = rule_slot(&self.program, rule);
+            profile.counts[slot] += 1;
+            if let (Some(times), Some(start)) = (&mut profile.times, start) {
+                times[slot] += start.elapsed();
+            }
+        }
+        if let Some(agents) = agents {
+            self.write_json_trace(agents, rule, stack_size);
+        }
+        Ok(Some(pair))
+    }
+
+    /// 写入一次相互作用，规则压入的方程为栈中 `stack_size` 之后的部分
+    fn write_json_trace(&mut self, agents: [AgentId; 2], rule: RuleRef, stack_size: usize) {
+        let program = self.program.clone();
+        let [left, right] = agents.map(|id| program.agents[id.0].name.as_str());
+        let record = TraceRecord {
+            step: self.reductions,
+            left,
+            right,
+            rule: rule_slot(&program, rule),
+            pushed: self.stack[stack_size..]
+                .iter()
+                .map(|&(left, right)| (self.display(left, 3), self.display(right, 3)))
+                .collect(),
+        };
+        let line = serde_json::to_string(&record).expect("trace records are always serializable");
+        if let Some(trace) = &mut self.json_trace {
+            // 与 `--trace` 一样，跟踪输出失败不影响规约
+            let _ = writeln!(trace, "{}", line);
+        }
+    }
+
+    /// 规约直到栈为空
+    pub fn run(&mut self) -> Result<(), RuntimeError> {
+        while self.reduce()?.is_some() {}
+        Ok(())
+    }
+
+    fn apply_rule(&mut self, index: usize, left: Addr, right: Addr) -> Result<(), RuntimeError> {
+        let program = self.program.clone();
+        let rule = &program.rules[index];
+
+        let mut locals = HashMap::new();
+        for initializer in &rule.initializers {
+            let (local, addr) = match *initializer {
+                RuleInitializer::Name { index, variable } => {
+                    (Local::Name(index), self.new_name(variable))
+                }
+                RuleInitializer::Agent { index, id } => (Local::Agent(index), self.new_agent(id)),
+                RuleInitializer::Int { index, value } => {
+                    (Local::Agent(index), self.alloc(Cell::Int(value)))
+                }
+                RuleInitializer::SlotFromLeft { index, slot } => {
+                    (Local::Slot(index), self.port(left, slot))
+                }
+                RuleInitializer::SlotFromRight { index, slot } => {
+                    (Local::Slot(index), self.port(right, slot))
+                }
+                RuleInitializer::ReuseLeft { index } => (Local::Agent(index), left),
+                RuleInitializer::ReuseRight { index } => (Local::Agent(index), right),
+            };
+            locals.insert(local, addr);
+        }
+        for instruction in &rule.instructions {
+            match *instruction {
+                RuleInstruction::SetSlot {
+                    target,
+                    slot,
+                    value,
+                } => self.set_slot(locals[&target], slot, locals[&value]),
+                RuleInstruction::PushEquation { left, right, .. } => {
+                    self.push_equation(locals[&left], locals[&right])?
+                }
+                RuleInstruction::FreeLeft => self.dealloc(left),
+                RuleInstruction::FreeRight => self.dealloc(right),
+            }
+        }
+        Ok(())
+    }
+
+    fn int_value(&self, addr: Addr) -> i64 {
+        match self.heap[addr.0] {
+            Cell::Int(value) => value,
+            ref cell => unreachable!("integer value of {:?}", cell),
+        }
+    }
+
+    fn apply_native(
+        &mut self,
+        rule: NativeRule,
+        left: Addr,
+        right: Addr,
+    ) -> Result<(), RuntimeError> {
+        match rule {
+            NativeRule::IntDup => {
+                let copy = self.alloc(Cell::Int(self.int_value(right)));
+                self.push_equation(right, self.port(left, 1))?;
+                self.push_equation(copy, self.port(left, 2))?;
+                self.dealloc(left);
+            }
+            NativeRule::IntErase => {
+                self.dealloc(left);
+                self.dealloc(right);
+            }
+            NativeRule::Apply(op) => {
+                let y = self.port(right, 1);
+                let out = self.port(right, 2);
+                self.heap[right.0] = Cell::Agent {
+                    id: op.partial(),
+                    ports: vec![left, out],
+                };
+                self.push_equation(y, right)?;
+            }
+            NativeRule::Compute(op) => {
+                let n = self.port(right, 1);
+                let out = self.port(right, 2);
+                let (x, y) = (self.int_value(n), self.int_value(left));
+                let value = op.apply(x, y).ok_or(RuntimeError::DivisionByZero)?;
+                if op.is_comparison() {
+                    let id = if value != 0 {
+                        AgentId::TRUE
+                    } else {
+                        AgentId::FALSE
+                    };
+                    let result = self.new_agent(id);
+                    self.push_equation(result, out)?;
+                    self.dealloc(n);
+                } else {
+                    self.heap[n.0] = Cell::Int(value);
+                    self.push_equation(n, out)?;
+                }
+                self.dealloc(left);
+                self.dealloc(right);
+            }
+        }
+        Ok(())
+    }
+
+    /// 规则的描述
+    pub fn rule_description(&self, rule: RuleRef) -> String {
+        match rule {
+            RuleRef::Rule(index) => self.program.rules[index].description.clone(),
+            RuleRef::Native(rule) => rule.description(),
+        }
+    }
+
+    /// 项的交互器名称，变量与间接返回 `None`
+    pub fn agent_name(&self, addr: Addr) -> Option<&str> {
+        self.agent_id(addr)
+            .map(|id| self.program.agents[id.0].name.as_str())
+    }
+
+    /// 变量的名称，有源代码中的名称时为 `名称_编号`，否则为 `x编号`
+    fn name(&self, id: usize, variable: Option<usize>) -> String {
+        match variable {
+            Some(variable) => format!("{}_{}", self.program.variables[variable], id),
+            None => format!("x{}", id),
+        }
+    }
+
+    /// 沿着已连接的变量找到项
+    fn resolve(&self, mut addr: Addr) -> Addr {
+        while let Cell::Link(target) = self.heap[addr.0] {
+            addr = target;
+        }
+        addr
+    }
+
+    /// 以给定的格式显示结果，与 C 目标输出的结果一致
+    ///
+    /// 被多次引用的子项与环只显示一次，之后以标号引用，因此不会无限递归。
+    pub fn display_result(&self, addr: Addr, format: ResultFormat, max_depth: usize) -> String {
+        let mut labels = HashMap::new();
+        self.find_shared(&mut labels, addr, max_depth);
+        let mut printer = ResultPrinter {
+            machine: self,
+            format,
+            labels,
+            count: 0,
+            output: String::new(),
+        };
+        printer.term(addr, max_depth);
+        printer.output
+    }
+
+    /// 按显示的顺序遍历，找出被多次引用的交互器
+    fn find_shared(&self, labels: &mut HashMap<Addr, Label>, addr: Addr, max_depth: usize) {
+        let addr = self.resolve(addr);
+        let Cell::Agent { ports, .. } = &self.heap[addr.0] else {
+            return;
+        };
+        if ports.is_empty() {
+            return;
+        }
+        if let Some(label) = labels.get_mut(&addr) {
+            *label = Label::Shared;
+            return;
+        }
+        labels.insert(addr, Label::Once);
+        if max_depth > 0 {
+            for &port in ports {
+                self.find_shared(labels, port, max_depth - 1);
+            }
+        }
+    }
+
+    /// 以与 C 目标相同的格式显示项，超过 `max_depth` 层的部分显示为 `...`
+    pub fn display(&self, addr: Addr, max_depth: usize) -> String {
+        let mut output = String::new();
+        self.write_term(&mut output, addr, max_depth);
+        output
+    }
+
+    fn write_term(&self, f: &mut String, addr: Addr, max_depth: usize) {
+        match &self.heap[addr.0] {
+            Cell::Link(target) => self.write_term(f, *target, max_depth),
+            &Cell::Name { id, variable } => f.push_str(&self.name(id, variable)),
+            Cell::Int(value) => f.push_str(&value.to_string()),
+            Cell::Free => f.push_str("<freed>"),
+            Cell::Agent { id, ports } => {
+                f.push_str(&self.program.agents[id.0].name);
+                if ports.is_empty() {
+                    return;
+                }
+                f.push('(');
+                if max_depth > 0 {
+                    for (i, port) in ports.iter().enumerate() {
+                        if i != 0 {
+                            f.push_str(", ");
+                        }
+                        self.write_term(f, *port, max_depth - 1);
+                    }
+                } else {
+                    f.push_str("...");
+                }
+                f.push(')');
+            }
+        }
+    }
+}
+
+/// 项是否是交互器，包括整数
+fn is_agent(cell: &Cell) -> bool {
+    matches!(cell, Cell::Agent { .. } | Cell::Int(_))
+}
+
+/// 结果中的交互器的标号
+enum Label {
+    /// 只被引用一次
+    Once,
+    /// 被多次引用，尚未显示
+    Shared,
+    /// 已经以给定的标号显示
+    Printed(usize),
+}
+
+/// 显示结果，被多次引用的交互器第一次显示为 `#1=A(...)`，之后显示为 `#1#`
+struct ResultPrinter<'m> {
+    machine: &'m Machine,
+    format: ResultFormat,
+    labels: HashMap<Addr, Label>,
+    count: usize,
+    output: String,
+}
+
+impl<'m> ResultPrinter<'m> {
+    fn term(&mut self, addr: Addr, max_depth: usize) {
+        let machine = self.machine;
+        let addr = machine.resolve(addr);
+        let (id, ports) = match &machine.heap[addr.0] {
+            &Cell::Name { id, variable } => {
+                let name = machine.name(id, variable);
+                match self.format {
+                    ResultFormat::Json => self.push(&format!("{{\"name\":\"{}\"}}", name)),
+                    ResultFormat::Text | ResultFormat::Sexp => self.push(&name),
+                }
+                return;
+            }
+            Cell::Int(value) => return self.push(&value.to_string()),
+            Cell::Free => return self.push("<freed>"),
+            Cell::Link(_) => unreachable!("resolved"),
+            Cell::Agent { id, ports } => (*id, ports),
+        };
+        let name = &machine.program.agents[id.0].name;
+
+        let mut label = None;
+        match self.labels.get_mut(&addr) {
+            Some(entry @ Label::Shared) => {
+                self.count += 1;
+                *entry = Label::Printed(self.count);
+                label = Some(self.count);
+            }
+            Some(&mut Label::Printed(label)) => {
+                return match self.format {
+                    ResultFormat::Json => self.push(&format!("{{\"ref\":{}}}", label)),
+                    ResultFormat::Text | ResultFormat::Sexp => self.push(&format!("#{}#", label)),
+                };
+            }
+            Some(Label::Once) | None => {}
+        }
+
+        match self.format {
+            ResultFormat::Json => {
+                self.push(&format!("{{\"agent\":\"{}\"", name));
+                if let Some(label) = label {
+                    self.push(&format!(",\"label\":{}", label));
+                }
+                if !ports.is_empty() && max_depth == 0 {
+                    return self.push(",\"truncated\":true}");
+                }
+                self.push(",\"ports\":[");
+                for (i, &port) in ports.iter().enumerate() {
+                    if i != 0 {
+                        self.push(",");
+                    }
+                    self.term(port, max_depth - 1);
+                }
+                self.push("]}");
+            }
+            ResultFormat::Sexp => {
+                if let Some(label) = label {
+                    self.push(&format!("#{}=", label));
+                }
+                if ports.is_empty() {
+                    return self.push(name);
+                }
+                self.push(&format!("({}", name));
+                if max_depth > 0 {
+                    for &port in ports {
+                        self.push(" ");
+                        self.term(port, max_depth - 1);
+                    }
+                } else {
+                    self.push(" ...");
+                }
+                self.push(")");
+            }
+            ResultFormat::Text => {
+                if let Some(label) = label {
+                    self.push(&format!("#{}=", label));
+                }
+                if self.sugar(id, ports, max_depth) {
+                    return;
+                }
+                if ports.is_empty() {
+                    return self.push(name);
+                }
+                self.push(&format!("{}(", name));
+                if max_depth > 0 {
+                    for (i, &port) in ports.iter().enumerate() {
+                        if i != 0 {
+                            self.push(", ");
+                        }
+                        self.term(port, max_depth - 1);
+                    }
+                } else {
+                    self.push("...");
+                }
+                self.push(")");
+            }
+        }
+    }
+
+    /// 以字面量显示语法糖指令中的交互器，不能以字面量显示时返回 `false`
+    fn sugar(&mut self, id: AgentId, ports: &'m [Addr], max_depth: usize) -> bool {
+        let machine = self.machine;
+        let sugar = &machine.program.sugar;
+        let id = Some(id);
+        if id == sugar.nil {
+            self.push("[]");
+        } else if id == sugar.zero {
+            self.push("0");
+        } else if id == sugar.succ {
+            match self.number(ports, max_depth) {
+                Some(n) => self.push(&n.to_string()),
+                None => return false,
+            }
+        } else if id == sugar.cons {
+            self.list(ports, max_depth);
+        } else if id == sugar.tuple {
+            self.push("(");
+            if max_depth > 0 {
+                for (i, &port) in ports.iter().enumerate() {
+                    if i != 0 {
+                        self.push(", ");
+                    }
+                    self.term(port, max_depth - 1);
+                }
+            } else {
+                self.push("...");
+            }
+            self.push(")");
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// `S(S(Zero))` 中 `S` 的个数，不以 `Zero` 结尾、中间有被多次引用的交互器或者超过 `max_depth` 层时为 `None`
+    fn number(&self, mut ports: &'m [Addr], mut max_depth: usize) -> Option<usize> {
+        let machine = self.machine;
+        let sugar = &machine.program.sugar;
+        let mut n = 1;
+        while max_depth > 0 {
+            let addr = machine.resolve(ports[0]);
+            match &machine.heap[addr.0] {
+                Cell::Agent { id, .. } if Some(*id) == sugar.zero => return Some(n),
+                Cell::Agent { id, ports: next }
+                    if Some(*id) == sugar.succ && !self.shared(addr) =>
+                {
+                    n += 1;
+                    ports = next;
+                    max_depth -= 1;
+                }
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// `[a, b]`，不以 `Nil` 结尾时显示为 `[a, b | t]`
+    fn list(&mut self, mut ports: &'m [Addr], mut max_depth: usize) {
+        let machine = self.machine;
+        let sugar = &machine.program.sugar;
+        self.push("[");
+        loop {
+            if max_depth == 0 {
+                self.push("...");
+                break;
+            }
+            self.term(ports[0], max_depth - 1);
+            let tail = machine.resolve(ports[1]);
+            match &machine.heap[tail.0] {
+                Cell::Agent { id, .. } if Some(*id) == sugar.nil => break,
+                Cell::Agent { id, ports: next }
+                    if Some(*id) == sugar.cons && !self.shared(tail) =>
+                {
+                    self.push(", ");
+                    ports = next;
+                    max_depth -= 1;
+                }
+                _ => {
+                    self.push(" | ");
+                    self.term(tail, max_depth - 1);
+                    break;
+                }
+            }
+        }
+        self.push("]");
+    }
+
+    /// 是否被多次引用，需要带着标号显示
+    fn shared(&self, addr: Addr) -> bool {
+        matches!(
+            self.labels.get(&addr),
+            Some(Label::Shared | Label::Printed(_))
+        )
+    }
+
+    fn push(&mut self, s: &str) {
+        self.output.push_str(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::{Options, ResultFormat};
+    use crate::testing::{self, backends, Golden};
+
+    fn run(source: &str) -> backends::Output {
+        backends::run(source, Options::default())
+    }
+
+    #[test]
+    fn examples() {
+        let files = testing::discover(&["examples".into()]).unwrap();
+        assert!(!files.is_empty());
+        for file in files {
+            let golden = Golden::load(&file).unwrap();
+            let source = std::fs::read_to_string(&file).unwrap();
+            let output = run(&source);
+            assert_eq!(output.status, 0, "{}", file.display());
+            assert_eq!(
+                Some(output.stdout),
+                golden.expected().unwrap(),
+                "{}",
+                file.display()
+            );
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let output = run("Main <| #a, #b, #c |>
+    7 -> Sub(10, @x),
+    #x -> Mul(-4, @a),
+    9223372036854775807 -> Add(1, @b),
+    -7 -> Div(2, @y),
+    #y -> Lt(0, @c)");
+        assert_eq!(output.stdout, "12\n-9223372036854775808\nTrue\n");
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let output = run("Main <| #r |> 1 -> Div(0, @r)");
+        assert_eq!(output.status, 1);
+        assert_eq!(output.stdout, "");
+        assert!(output.stderr.contains("division by zero"));
+    }
+
+    #[test]
+    fn no_rule() {
+        let output = run("Main <| #r |> Zero -> Inc(@r)");
+        assert_eq!(output.status, 1);
+        assert!(output.stderr.contains("no rule for Zero and Inc(r_"));
+    }
+
+    /// 不会结束的程序，结果中的 `Pair` 先于计数得到
+    const COUNT: &str = "Zero >> Count(@r) => Zero -> Count(@r'), @r <- S(#r')
+Main <| #r |> Zero -> Count(@a), 1 -> Add(2, @b), @r <- Pair(#a, #b)";
+
+    #[test]
+    fn reduction_limit() {
+        let options = Options {
+            max_reductions: Some(5),
+            ..Default::default()
+        };
+        let output = backends::run(COUNT, options);
+        assert_eq!(output.status, 3);
+        assert_eq!(output.stdout, "Pair(S(r'_35), 3)\n");
+        assert!(output
+            .stderr
+            .starts_with("\x1b[31merror\x1b[0m: reduction limit of 5 exceeded\n"));
+    }
+
+    #[test]
+    fn agent_limit() {
+        let options = Options {
+            max_agents: Some(8),
+            ..Default::default()
+        };
+        let output = backends::run(COUNT, options);
+        assert_eq!(output.status, 3);
+        assert_eq!(output.stdout, "Pair(S(S(S(S(S(r'_39))))), 3)\n");
+        assert!(output.stderr.contains("agent limit of 8 exceeded"));
+    }
+
+    #[test]
+    fn limits_not_reached() {
+        let options = Options {
+            max_reductions: Some(100),
+            max_agents: Some(100),
+            max_stack_size: Some(100),
+            ..Default::default()
+        };
+        let output = backends::run("Main <| #r |> 1 -> Add(2, @x), #x -> Mul(3, @r)", options);
+        assert_eq!(output.status, 0);
+        assert_eq!(output.stdout, "9\n");
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let options = Options {
+            max_stack_size: Some(1),
+            ..Default::default()
+        };
+        let output = backends::run(COUNT, options);
+        assert_eq!(output.status, 1);
+        assert_eq!(output.stdout, "");
+        assert!(output.stderr.contains("stack overflow"));
+    }
+
+    #[test]
+    fn profile() {
+        let options = Options {
+            profile: true,
+            ..Default::default()
+        };
+        let source = std::fs::read_to_string("examples/unary.zz").unwrap();
+        let output = backends::run(&source, options);
+        assert_eq!(output.stdout, "S(S(S(S(S(S(S(S(S(Zero)))))))))\n");
+        let report = output.stderr.lines().collect::<Vec<_>>();
+        assert_eq!(report[..3], ["", "[Profile]", "     count  rule"]);
+        assert_eq!(
+            report[3],
+            "        12  S(#x0) >> Dup(@a, @b) => #x0 -> Dup(@u0, @v0), S(#u0) -> @a, S(#v0) -> @b"
+        );
+        assert_eq!(
+            report.last(),
+            Some(&"[Peak stack depth: 6, peak live agents: 22]")
+        );
+    }
+
+    #[test]
+    fn profile_after_limit() {
+        let options = Options {
+            profile: true,
+            max_reductions: Some(5),
+            ..Default::default()
+        };
+        let output = backends::run(COUNT, options);
+        assert_eq!(output.status, 3);
+        assert!(output.stderr.contains(
+            "         1  m >> Add#(n, @out) => n Add m -> @out\n[Peak stack depth: 3, peak live agents: 6]\n"
+        ));
+    }
+
+    #[test]
+    fn trace_file() {
+        let options = Options {
+            trace_file: Some("trace.jsonl".into()),
+            ..Default::default()
+        };
+        let source = std::fs::read_to_string("examples/simple.zz").unwrap();
+        let output = backends::run(&source, options);
+        let trace = output.trace_file.unwrap();
+        let lines = trace.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[0],
+            r#"{"step":3,"left":"Add","right":"S","rule":0,"pushed":[["S(Zero)","Add(S(S(Zero)), z_32)"],["S(z_32)","result_31"]]}"#
+        );
+        for line in lines {
+            serde_json::from_str::<serde_json::Value>(line).unwrap();
+        }
+    }
+
+    #[test]
+    fn trace_file_with_native_rules_and_limit() {
+        let options = Options {
+            trace: true,
+            trace_file: Some("trace.jsonl".into()),
+            max_reductions: Some(5),
+            ..Default::default()
+        };
+        let output = backends::run(COUNT, options);
+        assert_eq!(output.status, 3);
+        let trace = output.trace_file.unwrap();
+        assert_eq!(trace.lines().count(), 3);
+        assert!(trace.contains(r#""left":"<int>","right":"Add#""#));
+        assert!(output.stderr.contains(" = "));
+    }
+
+    #[test]
+    fn result_formats() {
+        let source = std::fs::read_to_string("examples/shared.zz").unwrap();
+        let sexp = Options {
+            result_format: ResultFormat::Sexp,
+            ..Default::default()
+        };
+        let output = backends::run(&source, sexp);
+        assert_eq!(output.stdout.lines().next(), Some("(P (Q y_35) y_35)"));
+
+        let json = Options {
+            result_format: ResultFormat::Json,
+            ..Default::default()
+        };
+        let output = backends::run(&source, json);
+        assert_eq!(
+            output.stdout.lines().nth(1),
+            Some(r#"{"agent":"Loop","ports":[{"name":"z_37"},{"name":"z_37"}]}"#)
+        );
+    }
+
+    #[test]
+    fn max_depth() {
+        let source = std::fs::read_to_string("examples/unary.zz").unwrap();
+        for (format, expected) in [
+            (ResultFormat::Text, "S(S(...))\n"),
+            (ResultFormat::Sexp, "(S (S ...))\n"),
+            (
+                ResultFormat::Json,
+                "{\"agent\":\"S\",\"ports\":[{\"agent\":\"S\",\"truncated\":true}]}\n",
+            ),
+        ] {
+            let options = Options {
+                result_format: format,
+                max_depth: 1,
+                ..Default::default()
+            };
+            assert_eq!(backends::run(&source, options).stdout, expected);
+        }
+    }
+}
@@ -3,15 +3,33 @@
 use std::fmt::Display;
 
 pub mod builder;
+pub mod interpreter;
+pub mod native;
 pub mod optimize;
 pub mod target;
 
 pub use builder::RuntimeBuilder;
+pub use native::{NativeOp, NativeRule};
 
 /// Agent ID
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct AgentId(pub usize);
 
+impl AgentId {
+    /// The `$` agent, also used as the indirection marker at runtime
+    pub const ROOT: AgentId = AgentId(0);
+    /// The built-in `Dup(@a, @b)` agent
+    pub const DUP: AgentId = AgentId(1);
+    /// The built-in `Erase` agent
+    pub const ERASE: AgentId = AgentId(2);
+    /// The boxed integer, whose slot holds the value instead of a pointer
+    pub const INT: AgentId = AgentId(3);
+    /// The built-in `True` agent, result of integer comparisons
+    pub const TRUE: AgentId = AgentId(native::FIRST_OP + 2 * NativeOp::ALL.len());
+    /// The built-in `False` agent, result of integer comparisons
+    pub const FALSE: AgentId = AgentId(AgentId::TRUE.0 + 1);
+}
+
 impl Display for AgentId {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.fmt(f)
@@ -39,14 +57,31 @@ impl Display for Local {
     }
 }
 
+/// Source variable of a name, displayed as its index or `null`
+struct Variable(Option<usize>);
+
+impl Display for Variable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(index) => write!(f, "{}", index),
+            None => write!(f, "null"),
+        }
+    }
+}
+
 /// Initializer
 #[allow(missing_docs)]
 #[derive(Clone, Debug)]
 pub enum RuleInitializer {
-    /// Name
-    Name { index: usize },
+    /// Name, with the index of its source variable name in `Program::variables`
+    Name {
+        index: usize,
+        variable: Option<usize>,
+    },
     /// Agent
     Agent { index: usize, id: AgentId },
+    /// Boxed integer
+    Int { index: usize, value: i64 },
     /// Slot value from left argument
     SlotFromLeft { index: usize, slot: usize },
     /// Slot value from right argument
@@ -60,10 +95,15 @@ pub enum RuleInitializer {
 impl Display for RuleInitializer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RuleInitializer::Name { index } => write!(f, "let x{} = new_name();", index),
+            RuleInitializer::Name { index, variable } => {
+                write!(f, "let x{} = new_name({});", index, Variable(*variable))
+            }
             RuleInitializer::Agent { index, id } => {
                 write!(f, "let a{} = new_agent({});", index, id)
             }
+            RuleInitializer::Int { index, value } => {
+                write!(f, "let a{} = new_int({});", index, value)
+            }
             RuleInitializer::SlotFromLeft { index, slot } => {
                 write!(f, "let s{} = left[{}];", index, slot)
             }
@@ -80,19 +120,29 @@ impl Display for RuleInitializer {
 #[allow(missing_docs)]
 #[derive(Clone, Debug)]
 pub enum NetInitializer {
-    /// Name
-    Name { index: usize },
+    /// Name, with the index of its source variable name in `Program::variables`
+    Name {
+        index: usize,
+        variable: Option<usize>,
+    },
     /// Agent
     Agent { index: usize, id: AgentId },
+    /// Boxed integer
+    Int { index: usize, value: i64 },
 }
 
 impl Display for NetInitializer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            NetInitializer::Name { index } => write!(f, "let x{} = new_name();", index),
+            NetInitializer::Name { index, variable } => {
+                write!(f, "let x{} = new_name({});", index, Variable(*variable))
+            }
             NetInitializer::Agent { index, id } => {
                 write!(f, "let a{} = new_agent({});", index, id)
             }
+            NetInitializer::Int { index, value } => {
+                write!(f, "let a{} = new_int({});", index, value)
+            }
         }
     }
 }
@@ -287,12 +337,33 @@ pub struct Program {
     pub rules: Vec<Rule>,
     /// Rule map (left, right, rule_id)
     pub rule_map: Vec<(AgentId, AgentId, usize)>,
+    /// Rules implemented natively by the target
+    pub native_rules: Vec<NativeRule>,
     /// Functions
     pub functions: Vec<Function>,
     /// Function metadata
     pub function_meta: Vec<FunctionMeta>,
     /// Entry point
     pub entry_point: usize,
+    /// Source variable names of names, for printing unresolved names
+    pub variables: Vec<String>,
+    /// Agents printed as literals in results
+    pub sugar: Sugar,
+}
+
+/// Agents named by `sugar` directives, printed as literals in text results
+#[derive(Clone, Debug, Default)]
+pub struct Sugar {
+    /// Non-empty list, `Cons(a, Cons(b, Nil))` is printed as `[a, b]`
+    pub cons: Option<AgentId>,
+    /// Empty list, printed as `[]`
+    pub nil: Option<AgentId>,
+    /// Successor, `S(S(Zero))` is printed as `2`
+    pub succ: Option<AgentId>,
+    /// Zero, printed as `0`
+    pub zero: Option<AgentId>,
+    /// Tuple, `Tuple(a, b)` is printed as `(a, b)`
+    pub tuple: Option<AgentId>,
 }
 
 impl Display for Program {
@@ -312,6 +383,16 @@ impl Display for Program {
         for (left, right, rule_id) in &self.rule_map {
             writeln!(f, "    rules[{}][{}] = rule_{};", left.0, right.0, rule_id)?;
         }
+        for rule in &self.native_rules {
+            let (left, right) = rule.agents();
+            writeln!(
+                f,
+                "    rules[{}][{}] = native; // {}",
+                left.0,
+                right.0,
+                rule.description()
+            )?;
+        }
         writeln!(f, "}}")?;
         writeln!(f)?;
 
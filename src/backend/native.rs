@@ -0,0 +1,239 @@
+//! 内置交互器与原生规则。
+
+use std::fmt::Display;
+
+use super::AgentId;
+use crate::frontend::semantic::Polarity;
+
+/// 整数运算
+#[allow(missing_docs)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum NativeOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl NativeOp {
+    /// 全部运算，顺序与交互器编号一致
+    pub const ALL: [NativeOp; 11] = [
+        NativeOp::Add,
+        NativeOp::Sub,
+        NativeOp::Mul,
+        NativeOp::Div,
+        NativeOp::Mod,
+        NativeOp::Lt,
+        NativeOp::Le,
+        NativeOp::Gt,
+        NativeOp::Ge,
+        NativeOp::Eq,
+        NativeOp::Ne,
+    ];
+
+    /// 运算的名称，同时也是对应交互器的名称
+    pub fn name(self) -> &'static str {
+        match self {
+            NativeOp::Add => "Add",
+            NativeOp::Sub => "Sub",
+            NativeOp::Mul => "Mul",
+            NativeOp::Div => "Div",
+            NativeOp::Mod => "Mod",
+            NativeOp::Lt => "Lt",
+            NativeOp::Le => "Le",
+            NativeOp::Gt => "Gt",
+            NativeOp::Ge => "Ge",
+            NativeOp::Eq => "Eq",
+            NativeOp::Ne => "Ne",
+        }
+    }
+
+    /// 已经得到第一个操作数的交互器名称
+    pub fn partial_name(self) -> &'static str {
+        match self {
+            NativeOp::Add => "Add#",
+            NativeOp::Sub => "Sub#",
+            NativeOp::Mul => "Mul#",
+            NativeOp::Div => "Div#",
+            NativeOp::Mod => "Mod#",
+            NativeOp::Lt => "Lt#",
+            NativeOp::Le => "Le#",
+            NativeOp::Gt => "Gt#",
+            NativeOp::Ge => "Ge#",
+            NativeOp::Eq => "Eq#",
+            NativeOp::Ne => "Ne#",
+        }
+    }
+
+    /// 根据名称查找运算
+    pub fn from_name(name: &str) -> Option<NativeOp> {
+        NativeOp::ALL.into_iter().find(|op| op.name() == name)
+    }
+
+    /// 根据部分应用的交互器名称查找运算
+    pub fn from_partial_name(name: &str) -> Option<NativeOp> {
+        NativeOp::ALL
+            .into_iter()
+            .find(|op| op.partial_name() == name)
+    }
+
+    fn index(self) -> usize {
+        NativeOp::ALL.iter().position(|&op| op == self).unwrap()
+    }
+
+    /// 运算对应的交互器 `Op(#y, @out)`
+    pub fn agent(self) -> AgentId {
+        AgentId(FIRST_OP + self.index())
+    }
+
+    /// 已经得到第一个操作数的交互器 `Op#(n, @out)`
+    pub fn partial(self) -> AgentId {
+        AgentId(FIRST_OP + NativeOp::ALL.len() + self.index())
+    }
+
+    /// 根据交互器查找运算
+    pub fn from_agent(id: AgentId) -> Option<NativeOp> {
+        NativeOp::ALL.into_iter().find(|op| op.agent() == id)
+    }
+
+    /// 根据部分应用的交互器查找运算
+    pub fn from_partial(id: AgentId) -> Option<NativeOp> {
+        NativeOp::ALL.into_iter().find(|op| op.partial() == id)
+    }
+
+    /// 是否为比较运算
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            NativeOp::Lt | NativeOp::Le | NativeOp::Gt | NativeOp::Ge | NativeOp::Eq | NativeOp::Ne
+        )
+    }
+
+    /// 计算 `x op y`，除数为零时返回 `None`
+    ///
+    /// 算术运算按补码回绕；比较运算返回 0 或 1。
+    pub fn apply(self, x: i64, y: i64) -> Option<i64> {
+        Some(match self {
+            NativeOp::Add => x.wrapping_add(y),
+            NativeOp::Sub => x.wrapping_sub(y),
+            NativeOp::Mul => x.wrapping_mul(y),
+            NativeOp::Div if y == 0 => return None,
+            NativeOp::Div => x.wrapping_div(y),
+            NativeOp::Mod if y == 0 => return None,
+            NativeOp::Mod => x.wrapping_rem(y),
+            NativeOp::Lt => (x < y) as i64,
+            NativeOp::Le => (x <= y) as i64,
+            NativeOp::Gt => (x > y) as i64,
+            NativeOp::Ge => (x >= y) as i64,
+            NativeOp::Eq => (x == y) as i64,
+            NativeOp::Ne => (x != y) as i64,
+        })
+    }
+}
+
+impl Display for NativeOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.name().fmt(f)
+    }
+}
+
+/// 整数交互器的名称
+pub const INT: &str = "<int>";
+
+/// 第一个运算交互器的编号
+pub(crate) const FIRST_OP: usize = AgentId::INT.0 + 1;
+
+/// 内置交互器
+#[derive(Clone, Debug)]
+pub struct BuiltinAgent {
+    /// 名称
+    pub name: &'static str,
+    /// 主端口的极性
+    pub principal: Polarity,
+    /// 各个辅助端口的极性
+    pub ports: &'static [Polarity],
+}
+
+impl BuiltinAgent {
+    const fn new(name: &'static str, principal: Polarity, ports: &'static [Polarity]) -> Self {
+        Self {
+            name,
+            principal,
+            ports,
+        }
+    }
+}
+
+/// 内置交互器的名称与端口的极性，顺序与交互器编号一致
+///
+/// 运算交互器与已经得到第一个操作数的交互器都是 `Op(#y, @out)` 的形式。
+pub fn builtin_agents() -> Vec<BuiltinAgent> {
+    use Polarity::{In, Out};
+    let mut agents = vec![
+        BuiltinAgent::new("$", Out, &[In]),
+        BuiltinAgent::new("Dup", Out, &[Out, Out]),
+        BuiltinAgent::new("Erase", Out, &[]),
+        BuiltinAgent::new(INT, In, &[In]),
+    ];
+    for op in NativeOp::ALL {
+        agents.push(BuiltinAgent::new(op.name(), Out, &[In, Out]));
+    }
+    for op in NativeOp::ALL {
+        agents.push(BuiltinAgent::new(op.partial_name(), Out, &[In, Out]));
+    }
+    agents.push(BuiltinAgent::new("True", In, &[]));
+    agents.push(BuiltinAgent::new("False", In, &[]));
+    debug_assert_eq!(agents[AgentId::TRUE.0].name, "True");
+    debug_assert_eq!(agents[AgentId::FALSE.0].name, "False");
+    agents
+}
+
+/// 原生规则，由编译目标直接实现
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NativeRule {
+    /// `Dup(@a, @b) >> n`：复制整数
+    IntDup,
+    /// `Erase >> n`：删除整数
+    IntErase,
+    /// `n >> Op(#y, @out)`：记录第一个操作数，等待 `#y`
+    Apply(NativeOp),
+    /// `m >> Op#(n, @out)`：计算 `n op m`
+    Compute(NativeOp),
+}
+
+impl NativeRule {
+    /// 规则的描述
+    pub fn description(self) -> String {
+        match self {
+            NativeRule::IntDup => "n >> Dup(@a, @b) => n -> @a, n -> @b".to_string(),
+            NativeRule::IntErase => "n >> Erase => _".to_string(),
+            NativeRule::Apply(op) => format!("n >> {op}(#y, @out) => #y -> {op}#(n, @out)"),
+            NativeRule::Compute(op) => format!("m >> {op}#(n, @out) => n {op} m -> @out"),
+        }
+    }
+
+    /// 规则作用的两个交互器，较小的编号在左侧
+    pub fn agents(self) -> (AgentId, AgentId) {
+        match self {
+            NativeRule::IntDup => (AgentId::DUP, AgentId::INT),
+            NativeRule::IntErase => (AgentId::ERASE, AgentId::INT),
+            NativeRule::Apply(op) => (AgentId::INT, op.agent()),
+            NativeRule::Compute(op) => (AgentId::INT, op.partial()),
+        }
+    }
+
+    /// 全部原生规则
+    pub fn all() -> Vec<NativeRule> {
+        let mut rules = vec![NativeRule::IntDup, NativeRule::IntErase];
+        rules.extend(NativeOp::ALL.into_iter().map(NativeRule::Apply));
+        rules.extend(NativeOp::ALL.into_iter().map(NativeRule::Compute));
+        rules
+    }
+}
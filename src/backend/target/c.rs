@@ -0,0 +1,1262 @@
+//! 编译到 C 语言的运行时
+
+use crate::{
+    backend::{
+        AgentId, AgentMeta, Function, FunctionMeta, NativeOp, NativeRule, NetInitializer,
+        NetInstruction, Program, Rule, RuleInitializer, RuleInstruction, Sugar,
+    },
+    options::{Allocator, Options, ResultFormat},
+};
+
+/// 编译到 C 语言的运行时
+pub struct C;
+
+impl super::Target for C {
+    fn write(
+        mut f: impl std::io::Write,
+        program: Program,
+        options: &Options,
+    ) -> Result<(), super::Error> {
+        Self::write_includes(&mut f, options)?;
+        Self::write_prelude(&mut f, options)?;
+        Self::write_global(&mut f, program.agents, &program.variables, &program.sugar)?;
+        Self::write_runtime(&mut f)?;
+
+        let descriptions = program
+            .rules
+            .iter()
+            .map(|rule| rule.description.clone())
+            .chain(program.native_rules.iter().map(|rule| rule.description()))
+            .collect::<Vec<_>>();
+        for rule in program.rules {
+            Self::write_rule(&mut f, rule)?;
+        }
+        for (index, rule) in program.native_rules.iter().enumerate() {
+            Self::write_native_rule(&mut f, index, *rule)?;
+        }
+        Self::write_instrumentation(&mut f, &descriptions)?;
+
+        Self::write_rule_map(&mut f, program.rule_map, program.native_rules)?;
+
+        for function in program.functions {
+            Self::write_function(&mut f, function)?;
+        }
+        Self::write_function_meta(&mut f, program.function_meta)?;
+        Self::write_main(&mut f, program.entry_point)?;
+        Ok(())
+    }
+}
+
+impl C {
+    const INCLUDES: &str = r#"
+#include <stdio.h>
+#include <stdlib.h>
+#include <stdint.h>
+"#;
+
+    fn write_includes(mut f: impl std::io::Write, options: &Options) -> Result<(), super::Error> {
+        f.write_all(C::INCLUDES.trim_start().as_bytes())?;
+
+        if options.timing {
+            writeln!(f, "#include <time.h>")?;
+            writeln!(f, "#define ZZ_TIMING")?;
+        }
+        if options.trace {
+            writeln!(f, "#define ZZ_TRACE")?;
+        }
+        if options.profile {
+            writeln!(f, "#define ZZ_PROFILE")?;
+        }
+        if let Some(path) = &options.trace_file {
+            let path = path.display().to_string();
+            writeln!(f, "#define ZZ_TRACE_FILE {}", Self::string_literal(&path))?;
+        }
+        if options.allocator == Allocator::Malloc {
+            writeln!(f, "#define ZZ_MALLOC")?;
+        }
+
+        Ok(())
+    }
+
+    const PRELUDE: &str = r#"
+size_t* (*EQ_STACK)[2] = NULL;
+size_t EQ_STACK_SIZE = 0;
+size_t EQ_STACK_CAPACITY = 0;
+
+#if defined(ZZ_TIMING) || defined(MAX_REDUCTIONS) || defined(ZZ_TRACE_FILE)
+#define ZZ_COUNT_REDUCTIONS
+size_t REDUCTIONS = 0;
+#endif
+
+#if defined(MAX_AGENTS) || defined(ZZ_PROFILE)
+#define ZZ_COUNT_AGENTS
+size_t LIVE_AGENTS = 0;
+#endif
+
+#ifdef ZZ_PROFILE
+size_t PEAK_STACK_SIZE = 0;
+size_t PEAK_AGENTS = 0;
+#endif
+
+#ifdef ZZ_TRACE_FILE
+FILE* TRACE_FILE = NULL;
+#endif
+
+// Rules are numbered for profiling and tracing, native rules after the program's
+#if defined(ZZ_PROFILE) || defined(ZZ_TRACE_FILE)
+#define ZZ_RULE_SLOTS
+#endif
+
+// Exit status when a reduction or agent limit is exceeded
+#define LIMIT_EXIT_STATUS 3
+
+// Formats of the results
+#define RESULT_TEXT 0
+#define RESULT_SEXP 1
+#define RESULT_JSON 2
+
+// Source variable of a name generated by the compiler
+#define NO_VARIABLE ((size_t) -1)
+
+typedef void (*RuleFun)(size_t* left, size_t* right);
+typedef size_t** (*NetFun)();
+
+size_t* alloc_words(size_t words);
+void free_words(size_t* block, size_t words);
+void free_node(size_t* node);
+void out_of_memory();
+size_t* new_agent(size_t agent_id);
+size_t* new_int(int64_t value);
+size_t* new_name(size_t variable);
+void grow_stack();
+void push_equation(size_t* left, size_t* right);
+void division_by_zero();
+void pop_equation(size_t** left, size_t** right);
+void print_term(FILE* f, size_t* term, size_t max_recursion);
+void print_result(FILE* f, size_t* term);
+void init_rules();
+int run();
+#ifdef ZZ_RULE_SLOTS
+void apply_rule(size_t a_left, size_t a_right, size_t* left, size_t* right);
+#endif
+#ifdef ZZ_PROFILE
+void print_profile();
+#endif
+"#;
+
+    fn write_prelude(mut f: impl std::io::Write, options: &Options) -> Result<(), super::Error> {
+        writeln!(f, "#define INITIAL_STACK_SIZE {}", options.stack_size)?;
+        if let Some(max_stack_size) = options.max_stack_size {
+            writeln!(f, "#define MAX_STACK_SIZE {}", max_stack_size)?;
+        }
+        if let Some(max_reductions) = options.max_reductions {
+            writeln!(f, "#define MAX_REDUCTIONS {}", max_reductions)?;
+        }
+        if let Some(max_agents) = options.max_agents {
+            writeln!(f, "#define MAX_AGENTS {}", max_agents)?;
+        }
+        let result_format = match options.result_format {
+            ResultFormat::Text => "RESULT_TEXT",
+            ResultFormat::Sexp => "RESULT_SEXP",
+            ResultFormat::Json => "RESULT_JSON",
+        };
+        writeln!(f, "#define RESULT_FORMAT {}", result_format)?;
+        writeln!(f, "#define MAX_DEPTH {}", options.max_depth)?;
+        f.write_all(C::PRELUDE.as_bytes())?;
+        Ok(())
+    }
+
+    fn write_global(
+        mut f: impl std::io::Write,
+        agents: Vec<AgentMeta>,
+        variables: &[String],
+        sugar: &Sugar,
+    ) -> Result<(), super::Error> {
+        let agents_count = agents.len();
+        let agents_arity = agents
+            .iter()
+            .map(|meta| meta.arity.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let agents_names = agents
+            .iter()
+            .map(|meta| format!("\"{}\"", meta.name))
+            .collect::<Vec<_>>()
+            .join(", ");
+        // 名称占两个字，交互器占元数加一个字
+        let max_words = agents
+            .iter()
+            .map(|meta| meta.arity + 1)
+            .max()
+            .unwrap_or_default()
+            .max(2);
+        // 末尾的 `NULL` 使没有变量时数组也不为空
+        let variables = variables
+            .iter()
+            .map(|name| format!("\"{}\", ", name))
+            .collect::<String>();
+        let agent_int = AgentId::INT;
+        let agent_true = AgentId::TRUE;
+        let agent_false = AgentId::FALSE;
+
+        write!(
+            f,
+            r#"
+#define AGENT_COUNT {agents_count}
+#define AGENT_INT {agent_int}
+#define AGENT_TRUE {agent_true}
+#define AGENT_FALSE {agent_false}
+#define NAME_COUNTER_START {agents_count}
+#define MAX_WORDS {max_words}
+const char* AGENTS[] = {{ {agents_names} }};
+const size_t ARITY[] = {{ {agents_arity} }};
+const char* VARIABLES[] = {{ {variables}NULL }};
+size_t NAME_COUNTER = NAME_COUNTER_START;
+"#
+        )?;
+        let sugars = [
+            ("SUGAR_CONS", sugar.cons),
+            ("SUGAR_NIL", sugar.nil),
+            ("SUGAR_SUCC", sugar.succ),
+            ("SUGAR_ZERO", sugar.zero),
+            ("SUGAR_TUPLE", sugar.tuple),
+        ];
+        for (define, id) in sugars {
+            if let Some(id) = id {
+                writeln!(f, "#define {} {}", define, id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    const RUNTIME: &str = r##"
+RuleFun RULES[AGENT_COUNT][AGENT_COUNT] = { NULL };
+
+#ifdef ZZ_RULE_SLOTS
+size_t RULE_SLOTS[AGENT_COUNT][AGENT_COUNT];
+#define APPLY_RULE(a, b, left, right) apply_rule(a, b, left, right)
+#else
+#define APPLY_RULE(a, b, left, right) RULES[a][b](left, right)
+#endif
+
+#define IS_NAME(term) ((term)[0] >= AGENT_COUNT)
+#define IS_AGENT(term) ((term)[0] < AGENT_COUNT)
+#define INT_VALUE(term) ((int64_t) (term)[1])
+
+#ifdef ZZ_MALLOC
+size_t* alloc_words(size_t words) {
+    size_t* block = malloc(sizeof(size_t) * words);
+    if (block == NULL) {
+        out_of_memory();
+    }
+    return block;
+}
+
+void free_words(size_t* block, size_t words) {
+    free(block);
+}
+#else
+// Blocks are carved out of large chunks and recycled through one free list
+// per size in words; a free block stores the next free block in its first word.
+#define CHUNK_WORDS 65536
+
+size_t* FREE_LISTS[MAX_WORDS + 1] = { NULL };
+size_t* CHUNK = NULL;
+size_t CHUNK_USED = CHUNK_WORDS;
+
+size_t* alloc_words(size_t words) {
+    size_t* block = FREE_LISTS[words];
+    if (block != NULL) {
+        FREE_LISTS[words] = (size_t*) block[0];
+        return block;
+    }
+    if (CHUNK_USED + words > CHUNK_WORDS) {
+        CHUNK = malloc(sizeof(size_t) * CHUNK_WORDS);
+        if (CHUNK == NULL) {
+            out_of_memory();
+        }
+        CHUNK_USED = 0;
+    }
+    block = CHUNK + CHUNK_USED;
+    CHUNK_USED += words;
+    return block;
+}
+
+void free_words(size_t* block, size_t words) {
+    block[0] = (size_t) FREE_LISTS[words];
+    FREE_LISTS[words] = block;
+}
+#endif
+
+// Names and indirections (the `$` agent) take two words, agents their arity plus one
+void free_node(size_t* node) {
+#ifdef ZZ_COUNT_AGENTS
+    if (!IS_NAME(node) && node[0] != 0) {
+        LIVE_AGENTS--;
+    }
+#endif
+    free_words(node, IS_NAME(node) ? 2 : ARITY[node[0]] + 1);
+}
+
+void out_of_memory() {
+    fprintf(stderr, "\x1b[31merror\x1b[0m: out of memory\n");
+    exit(1);
+}
+
+size_t* new_agent(size_t agent_id) {
+    size_t arity = ARITY[agent_id];
+    size_t* agent = alloc_words(arity + 1);
+    agent[0] = agent_id;
+#ifdef ZZ_COUNT_AGENTS
+    LIVE_AGENTS++;
+#endif
+#ifdef ZZ_PROFILE
+    if (LIVE_AGENTS > PEAK_AGENTS) {
+        PEAK_AGENTS = LIVE_AGENTS;
+    }
+#endif
+    return agent;
+}
+
+size_t* new_int(int64_t value) {
+    size_t* agent = new_agent(AGENT_INT);
+    agent[1] = (size_t) value;
+    return agent;
+}
+
+// The second word of a name is the index of its source variable name, until it is connected
+size_t* new_name(size_t variable) {
+    size_t* name = alloc_words(2);
+    name[0] = NAME_COUNTER++;
+    name[1] = variable;
+    return name;
+}
+
+void grow_stack() {
+    size_t capacity = EQ_STACK_CAPACITY ? EQ_STACK_CAPACITY * 2 : INITIAL_STACK_SIZE;
+    if (capacity == 0) {
+        capacity = 1;
+    }
+#ifdef MAX_STACK_SIZE
+    if (EQ_STACK_CAPACITY >= MAX_STACK_SIZE) {
+        fprintf(stderr, "\x1b[31merror\x1b[0m: stack overflow\n");
+        fprintf(stderr, "\x1b[33mhint\x1b[0m: try to increase the stack limit with `--max-stack-size`\n");
+        exit(1);
+    }
+    if (capacity > MAX_STACK_SIZE) {
+        capacity = MAX_STACK_SIZE;
+    }
+#endif
+    EQ_STACK = realloc(EQ_STACK, sizeof(*EQ_STACK) * capacity);
+    if (EQ_STACK == NULL) {
+        out_of_memory();
+    }
+    EQ_STACK_CAPACITY = capacity;
+}
+
+void push_equation(size_t* left, size_t* right) {
+    if (EQ_STACK_SIZE == EQ_STACK_CAPACITY) {
+        grow_stack();
+    }
+    EQ_STACK[EQ_STACK_SIZE][0] = left;
+    EQ_STACK[EQ_STACK_SIZE][1] = right;
+    EQ_STACK_SIZE++;
+#ifdef ZZ_PROFILE
+    if (EQ_STACK_SIZE > PEAK_STACK_SIZE) {
+        PEAK_STACK_SIZE = EQ_STACK_SIZE;
+    }
+#endif
+}
+
+void division_by_zero() {
+    fprintf(stderr, "\x1b[31merror\x1b[0m: division by zero\n");
+    exit(1);
+}
+
+void pop_equation(size_t** left, size_t** right) {
+    EQ_STACK_SIZE--;
+    *left = EQ_STACK[EQ_STACK_SIZE][0];
+    *right = EQ_STACK[EQ_STACK_SIZE][1];
+}
+
+void print_name(FILE* f, size_t* name) {
+    if (name[1] == NO_VARIABLE) {
+        fprintf(f, "x%zu", name[0]);
+    } else {
+        fprintf(f, "%s_%zu", VARIABLES[name[1]], name[0]);
+    }
+}
+
+void print_term(FILE* f, size_t* term, size_t max_recursion) {
+    if (term[0] == 0) {        // the `$` agent
+        print_term(f, (size_t*) term[1], max_recursion);
+        return;
+    }
+    if (IS_NAME(term)) {       // name
+        print_name(f, term);
+        return;
+    }
+    if (term[0] == AGENT_INT) { // integer
+        fprintf(f, "%lld", (long long) INT_VALUE(term));
+        return;
+    }
+
+    size_t arity = ARITY[term[0]];
+    if (arity == 0) {
+        fprintf(f, "%s", AGENTS[term[0]]);
+        return;
+    }
+
+    fprintf(f, "%s(", AGENTS[term[0]]);
+    if (max_recursion > 0) {
+        for (size_t i = 1; i <= arity; i++) {
+            print_term(f, (size_t*) term[i], max_recursion - 1);
+            if (i != arity) {
+                fprintf(f, ", ");
+            }
+        }
+    } else {
+        fprintf(f, "...");
+    }
+    fprintf(f, ")");
+}
+
+// Open-addressing map from agents to labels, for finding shared subterms and cycles in results.
+// A label is 0 for agents seen once, SHARED for agents seen more than once but not printed yet.
+#define SHARED ((size_t) -1)
+
+typedef struct {
+    size_t** nodes;
+    size_t* labels;
+    size_t capacity;
+    size_t size;
+} NodeMap;
+
+size_t* node_map_entry(NodeMap* map, size_t* node, int* found);
+
+void node_map_grow(NodeMap* map) {
+    NodeMap old = *map;
+    map->capacity = old.capacity ? old.capacity * 2 : 64;
+    map->size = 0;
+    map->nodes = calloc(map->capacity, sizeof(size_t*));
+    map->labels = malloc(map->capacity * sizeof(size_t));
+    if (map->nodes == NULL || map->labels == NULL) {
+        out_of_memory();
+    }
+    for (size_t i = 0; i < old.capacity; i++) {
+        if (old.nodes[i] != NULL) {
+            int found;
+            *node_map_entry(map, old.nodes[i], &found) = old.labels[i];
+        }
+    }
+    free(old.nodes);
+    free(old.labels);
+}
+
+size_t* node_map_entry(NodeMap* map, size_t* node, int* found) {
+    if (2 * (map->size + 1) > map->capacity) {
+        node_map_grow(map);
+    }
+    size_t i = ((size_t) node >> 3) * 11400714819323198485ull & (map->capacity - 1);
+    while (map->nodes[i] != NULL && map->nodes[i] != node) {
+        i = (i + 1) & (map->capacity - 1);
+    }
+    *found = map->nodes[i] != NULL;
+    if (!*found) {
+        map->nodes[i] = node;
+        map->labels[i] = 0;
+        map->size++;
+    }
+    return &map->labels[i];
+}
+
+size_t* resolve(size_t* term) {
+    while (term[0] == 0) {
+        term = (size_t*) term[1];
+    }
+    return term;
+}
+
+void find_shared(NodeMap* map, size_t* term, size_t max_depth) {
+    term = resolve(term);
+    if (IS_NAME(term) || term[0] == AGENT_INT || ARITY[term[0]] == 0) {
+        return;
+    }
+    int found;
+    size_t* label = node_map_entry(map, term, &found);
+    if (found) {
+        *label = SHARED;
+        return;
+    }
+    if (max_depth > 0) {
+        for (size_t i = 1; i <= ARITY[term[0]]; i++) {
+            find_shared(map, (size_t*) term[i], max_depth - 1);
+        }
+    }
+}
+
+void print_result_term(FILE* f, NodeMap* map, size_t* labels, size_t* term, size_t max_depth);
+
+#if RESULT_FORMAT == RESULT_TEXT
+int is_shared(NodeMap* map, size_t* term) {
+    int found;
+    return *node_map_entry(map, term, &found) != 0;
+}
+
+// Agents named by `sugar` directives are printed as literals; returns 0 when `term` cannot be
+int print_sugar(FILE* f, NodeMap* map, size_t* labels, size_t* term, size_t max_depth) {
+#ifdef SUGAR_NIL
+    if (term[0] == SUGAR_NIL) {
+        fprintf(f, "[]");
+        return 1;
+    }
+#endif
+#ifdef SUGAR_ZERO
+    if (term[0] == SUGAR_ZERO) {
+        fprintf(f, "0");
+        return 1;
+    }
+#endif
+#ifdef SUGAR_SUCC
+    // A number only when the successors end in zero without shared agents within the depth
+    if (term[0] == SUGAR_SUCC) {
+        size_t n = 1;
+        while (max_depth > 0) {
+            term = resolve((size_t*) term[1]);
+#ifdef SUGAR_ZERO
+            if (term[0] == SUGAR_ZERO) {
+                fprintf(f, "%zu", n);
+                return 1;
+            }
+#endif
+            if (term[0] != SUGAR_SUCC || is_shared(map, term)) {
+                break;
+            }
+            n++;
+            max_depth--;
+        }
+        return 0;
+    }
+#endif
+#ifdef SUGAR_CONS
+    // `[a, b]`, or `[a, b | t]` when the list does not end in nil
+    if (term[0] == SUGAR_CONS) {
+        fprintf(f, "[");
+        for (;;) {
+            if (max_depth == 0) {
+                fprintf(f, "...");
+                break;
+            }
+            print_result_term(f, map, labels, (size_t*) term[1], max_depth - 1);
+            size_t* tail = resolve((size_t*) term[2]);
+#ifdef SUGAR_NIL
+            if (tail[0] == SUGAR_NIL) {
+                break;
+            }
+#endif
+            if (tail[0] == SUGAR_CONS && !is_shared(map, tail)) {
+                fprintf(f, ", ");
+                term = tail;
+                max_depth--;
+                continue;
+            }
+            fprintf(f, " | ");
+            print_result_term(f, map, labels, tail, max_depth - 1);
+            break;
+        }
+        fprintf(f, "]");
+        return 1;
+    }
+#endif
+#ifdef SUGAR_TUPLE
+    if (term[0] == SUGAR_TUPLE) {
+        size_t arity = ARITY[term[0]];
+        fprintf(f, "(");
+        if (max_depth > 0) {
+            for (size_t i = 1; i <= arity; i++) {
+                if (i != 1) {
+                    fprintf(f, ", ");
+                }
+                print_result_term(f, map, labels, (size_t*) term[i], max_depth - 1);
+            }
+        } else {
+            fprintf(f, "...");
+        }
+        fprintf(f, ")");
+        return 1;
+    }
+#endif
+    return 0;
+}
+#endif
+
+// Shared agents are printed once with a label, `#1=A(...)`, and referred to as `#1#` afterwards
+void print_result_term(FILE* f, NodeMap* map, size_t* labels, size_t* term, size_t max_depth) {
+    term = resolve(term);
+    if (IS_NAME(term)) {
+#if RESULT_FORMAT == RESULT_JSON
+        fprintf(f, "{\"name\":\"");
+        print_name(f, term);
+        fprintf(f, "\"}");
+#else
+        print_name(f, term);
+#endif
+        return;
+    }
+    if (term[0] == AGENT_INT) {
+        fprintf(f, "%lld", (long long) INT_VALUE(term));
+        return;
+    }
+
+    const char* name = AGENTS[term[0]];
+    size_t arity = ARITY[term[0]];
+    size_t label = 0;
+    if (arity > 0) {
+        int found;
+        size_t* entry = node_map_entry(map, term, &found);
+        if (*entry == SHARED) {
+            label = *entry = ++*labels;
+        } else if (*entry != 0) {
+#if RESULT_FORMAT == RESULT_JSON
+            fprintf(f, "{\"ref\":%zu}", *entry);
+#else
+            fprintf(f, "#%zu#", *entry);
+#endif
+            return;
+        }
+    }
+
+#if RESULT_FORMAT == RESULT_JSON
+    fprintf(f, "{\"agent\":\"%s\"", name);
+    if (label) {
+        fprintf(f, ",\"label\":%zu", label);
+    }
+    if (arity > 0 && max_depth == 0) {
+        fprintf(f, ",\"truncated\":true}");
+        return;
+    }
+    fprintf(f, ",\"ports\":[");
+    for (size_t i = 1; i <= arity; i++) {
+        if (i != 1) {
+            fprintf(f, ",");
+        }
+        print_result_term(f, map, labels, (size_t*) term[i], max_depth - 1);
+    }
+    fprintf(f, "]}");
+#elif RESULT_FORMAT == RESULT_SEXP
+    if (label) {
+        fprintf(f, "#%zu=", label);
+    }
+    if (arity == 0) {
+        fprintf(f, "%s", name);
+        return;
+    }
+    fprintf(f, "(%s", name);
+    if (max_depth > 0) {
+        for (size_t i = 1; i <= arity; i++) {
+            fprintf(f, " ");
+            print_result_term(f, map, labels, (size_t*) term[i], max_depth - 1);
+        }
+    } else {
+        fprintf(f, " ...");
+    }
+    fprintf(f, ")");
+#else
+    if (label) {
+        fprintf(f, "#%zu=", label);
+    }
+    if (print_sugar(f, map, labels, term, max_depth)) {
+        return;
+    }
+    if (arity == 0) {
+        fprintf(f, "%s", name);
+        return;
+    }
+    fprintf(f, "%s(", name);
+    if (max_depth > 0) {
+        for (size_t i = 1; i <= arity; i++) {
+            if (i != 1) {
+                fprintf(f, ", ");
+            }
+            print_result_term(f, map, labels, (size_t*) term[i], max_depth - 1);
+        }
+    } else {
+        fprintf(f, "...");
+    }
+    fprintf(f, ")");
+#endif
+}
+
+void print_result(FILE* f, size_t* term) {
+    NodeMap map = { NULL, NULL, 0, 0 };
+    find_shared(&map, term, MAX_DEPTH);
+    size_t labels = 0;
+    print_result_term(f, &map, &labels, term, MAX_DEPTH);
+    free(map.nodes);
+    free(map.labels);
+}
+
+// Returns 0 when the stack is empty, or LIMIT_EXIT_STATUS when a limit is exceeded
+// before the next interaction, leaving the net intact for printing the partial result
+int run() {
+    size_t *left, *right;
+
+    init_rules();
+
+    while (EQ_STACK_SIZE) {
+        pop_equation(&left, &right);
+#ifdef ZZ_COUNT_REDUCTIONS
+        REDUCTIONS++;
+#endif
+
+#ifdef ZZ_TRACE
+        fprintf(stderr, "\x1b[90m");
+        print_term(stderr, left, 3);
+        fprintf(stderr, " = ");
+        print_term(stderr, right, 3);
+        fprintf(stderr, "\x1b[0m\n");
+#endif
+
+        // Indirection
+        if (left[0] == 0) {
+            push_equation((size_t*) left[1], right);
+            free_node(left);
+            continue;
+        }
+        if (right[0] == 0) {
+            push_equation(left, (size_t*) right[1]);
+            free_node(right);
+            continue;
+        }
+
+        // Interaction
+        if (IS_AGENT(left) && IS_AGENT(right)) {
+#ifdef MAX_REDUCTIONS
+            if (REDUCTIONS > MAX_REDUCTIONS) {
+                REDUCTIONS--; // the popped active pair is not reduced
+                fprintf(stderr, "\x1b[31merror\x1b[0m: reduction limit of %zu exceeded\n", (size_t) MAX_REDUCTIONS);
+                fprintf(stderr, "\x1b[33mhint\x1b[0m: try to increase the limit with `--max-reductions`\n");
+                return LIMIT_EXIT_STATUS;
+            }
+#endif
+#ifdef MAX_AGENTS
+            if (LIVE_AGENTS > MAX_AGENTS) {
+#ifdef ZZ_COUNT_REDUCTIONS
+                REDUCTIONS--;
+#endif
+                fprintf(stderr, "\x1b[31merror\x1b[0m: agent limit of %zu exceeded\n", (size_t) MAX_AGENTS);
+                fprintf(stderr, "\x1b[33mhint\x1b[0m: try to increase the limit with `--max-agents`\n");
+                return LIMIT_EXIT_STATUS;
+            }
+#endif
+            size_t a_left = left[0];
+            size_t a_right = right[0];
+
+            if (a_left <= a_right) {
+                if (RULES[a_left][a_right]) {
+                    APPLY_RULE(a_left, a_right, left, right);
+                    continue;
+                }
+            } else {
+                if (RULES[a_right][a_left]) {
+                    APPLY_RULE(a_right, a_left, right, left);
+                    continue;
+                }
+            }
+            fprintf(stderr, "\x1b[31merror\x1b[0m: no rule for ");
+            print_term(stderr, left, 3);
+            fprintf(stderr, " and ");
+            print_term(stderr, right, 3);
+            fprintf(stderr, "\n");
+            exit(1);
+        }
+
+        // Variable
+        if (IS_NAME(left)) {
+            left[0] = 0;
+            left[1] = (size_t) right;
+            continue;
+        }
+        if (IS_NAME(right)) {
+            right[0] = 0;
+            right[1] = (size_t) left;
+            continue;
+        }
+    }
+    return 0;
+}
+"##;
+
+    fn write_runtime(mut f: impl std::io::Write) -> Result<(), super::Error> {
+        f.write_all(C::RUNTIME.as_bytes())?;
+        Ok(())
+    }
+
+    fn write_rule(mut f: impl std::io::Write, rule: Rule) -> Result<(), super::Error> {
+        write!(
+            f,
+            r#"
+// {description}
+void rule_{index}(size_t* left, size_t* right) {{
+"#,
+            index = rule.index,
+            description = rule.description
+        )?;
+
+        for initailizer in rule.initializers {
+            Self::write_rule_initializer(&mut f, initailizer)?;
+        }
+        for instruction in rule.instructions {
+            Self::write_rule_instruction(&mut f, instruction)?;
+        }
+
+        writeln!(f, "}}")?;
+
+        Ok(())
+    }
+
+    fn write_rule_initializer(
+        mut f: impl std::io::Write,
+        initializer: RuleInitializer,
+    ) -> Result<(), super::Error> {
+        match initializer {
+            RuleInitializer::Name { index, variable } => {
+                let variable = Self::variable(variable);
+                writeln!(f, "    size_t* x{index} = new_name({variable});")?;
+            }
+            RuleInitializer::Agent { index, id } => {
+                writeln!(f, "    size_t* a{index} = new_agent({id});")?
+            }
+            RuleInitializer::Int { index, value } => {
+                let value = Self::int_literal(value);
+                writeln!(f, "    size_t* a{index} = new_int({value});")?
+            }
+            RuleInitializer::SlotFromLeft { index, slot } => {
+                writeln!(f, "    size_t* s{index} = (size_t*) left[{slot}];",)?
+            }
+            RuleInitializer::SlotFromRight { index, slot } => {
+                writeln!(f, "    size_t* s{index} = (size_t*) right[{slot}];",)?
+            }
+            RuleInitializer::ReuseLeft { index } => writeln!(f, "    size_t* a{index} = left;")?,
+            RuleInitializer::ReuseRight { index } => writeln!(f, "    size_t* a{index} = right;")?,
+        }
+        Ok(())
+    }
+
+    fn write_rule_instruction(
+        mut f: impl std::io::Write,
+        instruction: RuleInstruction,
+    ) -> Result<(), super::Error> {
+        match instruction {
+            RuleInstruction::SetSlot {
+                target,
+                slot,
+                value,
+            } => writeln!(f, "    {target}[{slot}] = (size_t) {value};")?,
+            RuleInstruction::PushEquation {
+                left,
+                right,
+                description,
+            } => writeln!(f, "    push_equation({left}, {right});  // {description}")?,
+            RuleInstruction::FreeLeft => writeln!(f, "    free_node(left);")?,
+            RuleInstruction::FreeRight => writeln!(f, "    free_node(right);")?,
+        }
+        Ok(())
+    }
+
+    /// 变量名称在 `VARIABLES` 中的下标
+    fn variable(variable: Option<usize>) -> String {
+        variable.map_or("NO_VARIABLE".to_string(), |index| index.to_string())
+    }
+
+    /// C 语言中的字符串字面量
+    fn string_literal(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    /// C 语言中的 `int64_t` 字面量
+    fn int_literal(value: i64) -> String {
+        if value == i64::MIN {
+            // `-9223372036854775808` 会被解析为对正数取负，而该正数超出了范围
+            format!("INT64_C({}) - 1", value + 1)
+        } else {
+            format!("INT64_C({value})")
+        }
+    }
+
+    fn write_native_rule(
+        mut f: impl std::io::Write,
+        index: usize,
+        rule: NativeRule,
+    ) -> Result<(), super::Error> {
+        write!(
+            f,
+            r#"
+// {description}
+void native_{index}(size_t* left, size_t* right) {{
+"#,
+            description = rule.description()
+        )?;
+
+        match rule {
+            NativeRule::IntDup => {
+                writeln!(f, "    size_t* copy = new_int(INT_VALUE(right));")?;
+                writeln!(f, "    push_equation(right, (size_t*) left[1]);")?;
+                writeln!(f, "    push_equation(copy, (size_t*) left[2]);")?;
+                writeln!(f, "    free_node(left);")?;
+            }
+            NativeRule::IntErase => {
+                writeln!(f, "    free_node(left);")?;
+                writeln!(f, "    free_node(right);")?;
+            }
+            NativeRule::Apply(op) => {
+                let partial = op.partial();
+                writeln!(f, "    size_t* y = (size_t*) right[1];")?;
+                writeln!(f, "    right[0] = {partial};")?;
+                writeln!(f, "    right[1] = (size_t) left;")?;
+                writeln!(f, "    push_equation(y, right);")?;
+            }
+            NativeRule::Compute(op) => {
+                writeln!(f, "    size_t* n = (size_t*) right[1];")?;
+                writeln!(f, "    size_t* out = (size_t*) right[2];")?;
+                writeln!(f, "    int64_t x = INT_VALUE(n);")?;
+                writeln!(f, "    int64_t y = INT_VALUE(left);")?;
+                if matches!(op, NativeOp::Div | NativeOp::Mod) {
+                    writeln!(f, "    if (y == 0) division_by_zero();")?;
+                }
+                // 算术运算在无符号整数上进行，以得到与补码回绕一致的结果
+                let expr = match op {
+                    NativeOp::Add => "(size_t) x + (size_t) y",
+                    NativeOp::Sub => "(size_t) x - (size_t) y",
+                    NativeOp::Mul => "(size_t) x * (size_t) y",
+                    NativeOp::Div => "y == -1 ? (size_t) 0 - (size_t) x : (size_t) (x / y)",
+                    NativeOp::Mod => "y == -1 ? 0 : (size_t) (x % y)",
+                    NativeOp::Lt => "x < y",
+                    NativeOp::Le => "x <= y",
+                    NativeOp::Gt => "x > y",
+                    NativeOp::Ge => "x >= y",
+                    NativeOp::Eq => "x == y",
+                    NativeOp::Ne => "x != y",
+                };
+                if op.is_comparison() {
+                    writeln!(
+                        f,
+                        "    size_t* result = new_agent({expr} ? AGENT_TRUE : AGENT_FALSE);"
+                    )?;
+                    writeln!(f, "    push_equation(result, out);")?;
+                    writeln!(f, "    free_node(n);")?;
+                } else {
+                    writeln!(f, "    n[1] = {expr};")?;
+                    writeln!(f, "    push_equation(n, out);")?;
+                }
+                writeln!(f, "    free_node(left);")?;
+                writeln!(f, "    free_node(right);")?;
+            }
+        }
+
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+
+    const INSTRUMENTATION: &str = r#"
+#ifdef ZZ_PROFILE
+size_t RULE_COUNTS[RULE_COUNT] = { 0 };
+#endif
+
+#ifdef ZZ_TIMING
+// Wall clock in nanoseconds
+uint64_t now_ns() {
+    struct timespec ts;
+    timespec_get(&ts, TIME_UTC);
+    return (uint64_t) ts.tv_sec * 1000000000 + (uint64_t) ts.tv_nsec;
+}
+#endif
+
+#if defined(ZZ_PROFILE) && defined(ZZ_TIMING)
+#define ZZ_TIME_RULES
+// Nanoseconds spent in each rule, measured with the wall clock
+uint64_t RULE_TIMES[RULE_COUNT] = { 0 };
+#endif
+
+#ifdef ZZ_TRACE_FILE
+// One JSON object per interaction, with the equations pushed by the rule
+void trace_rule(size_t a_left, size_t a_right, size_t slot, size_t stack_size) {
+    fprintf(TRACE_FILE, "{\"step\":%zu,\"left\":\"%s\",\"right\":\"%s\",\"rule\":%zu,\"pushed\":[",
+        REDUCTIONS, AGENTS[a_left], AGENTS[a_right], slot);
+    for (size_t i = stack_size; i < EQ_STACK_SIZE; i++) {
+        fprintf(TRACE_FILE, i == stack_size ? "[\"" : ",[\"");
+        print_term(TRACE_FILE, EQ_STACK[i][0], 3);
+        fprintf(TRACE_FILE, "\",\"");
+        print_term(TRACE_FILE, EQ_STACK[i][1], 3);
+        fprintf(TRACE_FILE, "\"]");
+    }
+    fprintf(TRACE_FILE, "]}\n");
+}
+#endif
+
+void apply_rule(size_t a_left, size_t a_right, size_t* left, size_t* right) {
+    size_t slot = RULE_SLOTS[a_left][a_right];
+#ifdef ZZ_PROFILE
+    RULE_COUNTS[slot]++;
+#endif
+#ifdef ZZ_TRACE_FILE
+    size_t stack_size = EQ_STACK_SIZE;
+#endif
+#ifdef ZZ_TIME_RULES
+    uint64_t start = now_ns();
+    RULES[a_left][a_right](left, right);
+    RULE_TIMES[slot] += now_ns() - start;
+#else
+    RULES[a_left][a_right](left, right);
+#endif
+#ifdef ZZ_TRACE_FILE
+    trace_rule(a_left, a_right, slot, stack_size);
+#endif
+}
+
+#ifdef ZZ_PROFILE
+// Most applied rules first, then in the order of the program
+int compare_rules(const void* a, const void* b) {
+    size_t x = *(const size_t*) a;
+    size_t y = *(const size_t*) b;
+    if (RULE_COUNTS[x] != RULE_COUNTS[y]) {
+        return RULE_COUNTS[x] < RULE_COUNTS[y] ? 1 : -1;
+    }
+    return x < y ? -1 : x > y;
+}
+
+void print_profile() {
+    size_t slots[RULE_COUNT];
+    for (size_t i = 0; i < RULE_COUNT; i++) {
+        slots[i] = i;
+    }
+    qsort(slots, RULE_COUNT, sizeof(size_t), compare_rules);
+
+    fprintf(stderr, "\n[Profile]\n");
+#ifdef ZZ_TIME_RULES
+    fprintf(stderr, "%10s  %10s  rule\n", "count", "time (s)");
+#else
+    fprintf(stderr, "%10s  rule\n", "count");
+#endif
+    for (size_t i = 0; i < RULE_COUNT && RULE_COUNTS[slots[i]] > 0; i++) {
+        size_t slot = slots[i];
+#ifdef ZZ_TIME_RULES
+        fprintf(stderr, "%10zu  %10.6f  %s\n", RULE_COUNTS[slot], RULE_TIMES[slot] / 1e9, RULE_DESCRIPTIONS[slot]);
+#else
+        fprintf(stderr, "%10zu  %s\n", RULE_COUNTS[slot], RULE_DESCRIPTIONS[slot]);
+#endif
+    }
+    fprintf(stderr, "[Peak stack depth: %zu, peak live agents: %zu]\n", PEAK_STACK_SIZE, PEAK_AGENTS);
+}
+#endif
+#endif
+"#;
+
+    /// 统计与跟踪规则应用的代码，规则依次编号为 `Program::rules` 与 `Program::native_rules` 中的规则
+    fn write_instrumentation(
+        mut f: impl std::io::Write,
+        descriptions: &[String],
+    ) -> Result<(), super::Error> {
+        let descriptions = descriptions
+            .iter()
+            .map(|description| format!("\"{}\"", description))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(
+            f,
+            r#"
+#ifdef ZZ_RULE_SLOTS
+#define RULE_COUNT {count}
+const char* RULE_DESCRIPTIONS[RULE_COUNT] = {{ {descriptions} }};
+"#,
+            count = descriptions.len()
+        )?;
+        f.write_all(C::INSTRUMENTATION.as_bytes())?;
+        Ok(())
+    }
+
+    fn write_rule_map(
+        mut f: impl std::io::Write,
+        rule_map: Vec<(AgentId, AgentId, usize)>,
+        native_rules: Vec<NativeRule>,
+    ) -> Result<(), super::Error> {
+        write!(
+            f,
+            r#"
+void init_rules() {{
+"#
+        )?;
+
+        for &(left, right, index) in &rule_map {
+            writeln!(f, "    RULES[{left}][{right}] = rule_{index};")?;
+        }
+        for (index, rule) in native_rules.iter().enumerate() {
+            let (left, right) = rule.agents();
+            writeln!(f, "    RULES[{left}][{right}] = native_{index};")?;
+        }
+
+        // 原生规则排在程序中的规则之后
+        let rule_count = rule_map.len();
+        writeln!(f, "#ifdef ZZ_RULE_SLOTS")?;
+        for (left, right, index) in rule_map {
+            writeln!(f, "    RULE_SLOTS[{left}][{right}] = {index};")?;
+        }
+        for (index, rule) in native_rules.into_iter().enumerate() {
+            let (left, right) = rule.agents();
+            let slot = rule_count + index;
+            writeln!(f, "    RULE_SLOTS[{left}][{right}] = {slot};")?;
+        }
+        writeln!(f, "#endif")?;
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+
+    fn write_function(mut f: impl std::io::Write, func: Function) -> Result<(), super::Error> {
+        write!(
+            f,
+            r#"
+size_t** func_{id}() {{
+"#,
+            id = func.index
+        )?;
+
+        for initializer in func.initializers {
+            Self::write_function_initializer(&mut f, initializer)?;
+        }
+        for instruction in func.instructions {
+            Self::write_function_instruction(&mut f, instruction)?;
+        }
+
+        writeln!(
+            f,
+            r#"
+    size_t** outputs = malloc(sizeof(size_t*) * {count});
+"#,
+            count = func.outputs.len()
+        )?;
+        for (i, output) in func.outputs.into_iter().enumerate() {
+            writeln!(f, r#"    outputs[{i}] = {output};"#)?;
+        }
+
+        write!(
+            f,
+            r#"
+    return outputs;
+}}
+"#,
+        )?;
+
+        Ok(())
+    }
+
+    fn write_function_initializer(
+        mut f: impl std::io::Write,
+        initializer: NetInitializer,
+    ) -> Result<(), super::Error> {
+        match initializer {
+            NetInitializer::Name { index, variable } => {
+                let variable = Self::variable(variable);
+                writeln!(f, "    size_t* x{index} = new_name({variable});")?;
+            }
+            NetInitializer::Agent { index, id } => {
+                writeln!(f, "    size_t* a{index} = new_agent({id});")?
+            }
+            NetInitializer::Int { index, value } => {
+                let value = Self::int_literal(value);
+                writeln!(f, "    size_t* a{index} = new_int({value});")?
+            }
+        }
+        Ok(())
+    }
+
+    fn write_function_instruction(
+        mut f: impl std::io::Write,
+        instruction: NetInstruction,
+    ) -> Result<(), super::Error> {
+        match instruction {
+            NetInstruction::SetSlot {
+                target,
+                slot,
+                value,
+            } => writeln!(f, "    {target}[{slot}] = (size_t) {value};")?,
+            NetInstruction::PushEquation {
+                left,
+                right,
+                description,
+            } => writeln!(f, "    push_equation({left}, {right});  // {description}")?,
+        }
+        Ok(())
+    }
+
+    fn write_function_meta(
+        mut f: impl std::io::Write,
+        function_meta: Vec<FunctionMeta>,
+    ) -> Result<(), super::Error> {
+        write!(
+            f,
+            r#"
+const NetFun NET_FUNCS[] = {{ {} }};
+const size_t OUTPUT_COUNTS[] = {{ {} }};
+"#,
+            (0..function_meta.len())
+                .map(|i| format!("func_{}", i))
+                .collect::<Vec<_>>()
+                .join(", "),
+            function_meta
+                .into_iter()
+                .map(|m| m.output_count.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )?;
+
+        Ok(())
+    }
+
+    fn write_main(mut f: impl std::io::Write, entry_point: usize) -> Result<(), super::Error> {
+        write!(
+            f,
+            r#"
+int main() {{
+#ifdef ZZ_TRACE_FILE
+    TRACE_FILE = fopen(ZZ_TRACE_FILE, "w");
+    if (TRACE_FILE == NULL) {{
+        fprintf(stderr, "\x1b[31merror\x1b[0m: cannot open trace file %s\n", ZZ_TRACE_FILE);
+        exit(1);
+    }}
+#endif
+
+    size_t** outputs = NET_FUNCS[{entry_point}]();
+
+#ifdef ZZ_TIMING
+    uint64_t start = now_ns();
+#endif
+    int status = run();
+#ifdef ZZ_TIMING
+    double time = (double) (now_ns() - start) / 1e9;
+#endif
+    // The results may share subterms or contain cycles, and the program
+    // exits right after printing them, so they are not freed.
+    for (size_t i = 0; i < OUTPUT_COUNTS[{entry_point}]; i++) {{
+        print_result(stdout, outputs[i]);
+        printf("\n");
+    }}
+    free(outputs);
+
+#ifdef ZZ_TIMING
+    double reductions_per_second = (double) REDUCTIONS / time;
+    fprintf(stderr, "\n[Reductions: %zu, Wall time: %f, R/s: %f]\n", REDUCTIONS, time, reductions_per_second);
+#endif
+#ifdef ZZ_PROFILE
+    print_profile();
+#endif
+
+    return status;
+}}
+"#
+        )?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,442 @@
+//! 输出为 Graphviz DOT 格式的图
+
+use std::collections::HashMap;
+
+use crate::{
+    backend::{
+        interpreter::{Addr, Cell, Machine},
+        Function, FunctionMeta, Local, NetInitializer, NetInstruction, Program, Rule,
+        RuleInitializer, RuleInstruction,
+    },
+    options::{Options, Snapshot},
+};
+
+/// 输出为 Graphviz DOT 格式的图
+///
+/// 默认绘制每个网络以及每条规则的左右两侧；设置了 [`Options::snapshot`] 时，
+/// 改为在解释器中规约并绘制运行时的网络。
+pub struct Dot;
+
+impl super::Target for Dot {
+    fn write(
+        mut f: impl std::io::Write,
+        program: Program,
+        options: &Options,
+    ) -> Result<(), super::Error> {
+        writeln!(f, "graph zamuza {{")?;
+        writeln!(f, "    node [fontname=\"monospace\"];")?;
+        writeln!(f, "    edge [fontname=\"monospace\"];")?;
+
+        match options.snapshot {
+            Some(snapshot) => Self::write_snapshot(&mut f, program, options, snapshot)?,
+            None => {
+                for (function, meta) in program.functions.iter().zip(&program.function_meta) {
+                    Self::write_function(&mut f, &program, function, meta)?;
+                }
+                for rule in &program.rules {
+                    Self::write_rule(&mut f, &program, rule)?;
+                }
+            }
+        }
+
+        writeln!(f, "}}")?;
+        Ok(())
+    }
+}
+
+impl Dot {
+    fn write_function(
+        f: &mut impl std::io::Write,
+        program: &Program,
+        function: &Function,
+        meta: &FunctionMeta,
+    ) -> Result<(), super::Error> {
+        let mut graph = Graph::new(format!("net{}", function.index));
+        let mut locals = HashMap::new();
+
+        for initializer in &function.initializers {
+            let (local, end) = match *initializer {
+                NetInitializer::Name { index, .. } => (Local::Name(index), graph.add_wire()),
+                NetInitializer::Agent { index, id } => {
+                    let agent = &program.agents[id.0];
+                    (
+                        Local::Agent(index),
+                        graph.add_agent(&agent.name, agent.arity),
+                    )
+                }
+                NetInitializer::Int { index, value } => {
+                    (Local::Agent(index), graph.add_agent(&value.to_string(), 0))
+                }
+            };
+            locals.insert(local, end);
+        }
+        for instruction in &function.instructions {
+            match instruction {
+                NetInstruction::SetSlot {
+                    target,
+                    slot,
+                    value,
+                } => graph.connect(locals[target].aux(*slot), locals[value]),
+                NetInstruction::PushEquation { left, right, .. } => {
+                    graph.connect(locals[left], locals[right])
+                }
+            }
+        }
+        for (i, output) in function.outputs.iter().enumerate() {
+            let free = graph.add_free(&i.to_string());
+            graph.connect(free, locals[output]);
+        }
+
+        graph.write(f, &meta.name, 1)
+    }
+
+    fn write_rule(
+        f: &mut impl std::io::Write,
+        program: &Program,
+        rule: &Rule,
+    ) -> Result<(), super::Error> {
+        let (left_id, right_id, _) = program.rule_map[rule.index];
+        let left = &program.agents[left_id.0];
+        let right = &program.agents[right_id.0];
+
+        // 两侧的自由端口以所属交互器及端口编号命名
+        let (left_label, right_label) = if left.name == right.name {
+            (
+                format!("{}(left)", left.name),
+                format!("{}(right)", right.name),
+            )
+        } else {
+            (left.name.clone(), right.name.clone())
+        };
+        let free_label = |initializer: &RuleInitializer| match *initializer {
+            RuleInitializer::SlotFromLeft { slot, .. } => format!("{}.{}", left_label, slot),
+            RuleInitializer::SlotFromRight { slot, .. } => format!("{}.{}", right_label, slot),
+            _ => unreachable!(),
+        };
+
+        writeln!(f, "    subgraph cluster_rule{} {{", rule.index)?;
+        writeln!(f, "        label=\"{}\";", escape(&rule.description))?;
+
+        // 左侧：活跃对
+        let mut lhs = Graph::new(format!("rule{}_lhs", rule.index));
+        let a_left = lhs.add_agent(&left.name, left.arity);
+        let a_right = lhs.add_agent(&right.name, right.arity);
+        lhs.connect(a_left, a_right);
+        for initializer in &rule.initializers {
+            let port = match *initializer {
+                RuleInitializer::SlotFromLeft { slot, .. } => a_left.aux(slot),
+                RuleInitializer::SlotFromRight { slot, .. } => a_right.aux(slot),
+                _ => continue,
+            };
+            let free = lhs.add_free(&free_label(initializer));
+            lhs.connect(port, free);
+        }
+        lhs.write(f, "lhs", 2)?;
+
+        // 右侧：规则体
+        let mut rhs = Graph::new(format!("rule{}_rhs", rule.index));
+        let mut locals = HashMap::new();
+        for initializer in &rule.initializers {
+            let (local, end) = match *initializer {
+                RuleInitializer::Name { index, .. } => (Local::Name(index), rhs.add_wire()),
+                RuleInitializer::Agent { index, id } => {
+                    let agent = &program.agents[id.0];
+                    (Local::Agent(index), rhs.add_agent(&agent.name, agent.arity))
+                }
+                RuleInitializer::Int { index, value } => {
+                    (Local::Agent(index), rhs.add_agent(&value.to_string(), 0))
+                }
+                RuleInitializer::SlotFromLeft { index, .. }
+                | RuleInitializer::SlotFromRight { index, .. } => {
+                    (Local::Slot(index), rhs.add_free(&free_label(initializer)))
+                }
+                RuleInitializer::ReuseLeft { index } => {
+                    (Local::Agent(index), rhs.add_agent(&left.name, left.arity))
+                }
+                RuleInitializer::ReuseRight { index } => {
+                    (Local::Agent(index), rhs.add_agent(&right.name, right.arity))
+                }
+            };
+            locals.insert(local, end);
+        }
+        for instruction in &rule.instructions {
+            match instruction {
+                RuleInstruction::SetSlot {
+                    target,
+                    slot,
+                    value,
+                } => rhs.connect(locals[target].aux(*slot), locals[value]),
+                RuleInstruction::PushEquation { left, right, .. } => {
+                    rhs.connect(locals[left], locals[right])
+                }
+                RuleInstruction::FreeLeft | RuleInstruction::FreeRight => {}
+            }
+        }
+        rhs.write(f, "rhs", 2)?;
+
+        writeln!(f, "    }}")?;
+        Ok(())
+    }
+
+    fn write_snapshot(
+        f: &mut impl std::io::Write,
+        program: Program,
+        options: &Options,
+        snapshot: Snapshot,
+    ) -> Result<(), super::Error> {
+        let mut machine = Machine::with_stack_limit(program, options.max_stack_size)?;
+        machine.set_limits(options.max_reductions, options.max_agents);
+
+        // 出错时绘制出错时的网络，便于查看卡住的活跃对
+        let mut error = None;
+        while match snapshot {
+            Snapshot::End => true,
+            Snapshot::After(count) => machine.reductions() < count,
+        } {
+            match machine.reduce() {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+
+        let mut graph = Graph::new("snapshot".to_string());
+        let mut ends = HashMap::new();
+        let mut pending = vec![];
+        for (i, &output) in machine.outputs().iter().enumerate() {
+            let free = graph.add_free(&format!("out{}", i));
+            pending.push((free, output));
+        }
+        for &(left, right) in machine.stack() {
+            let left = snapshot_end(&machine, &mut graph, &mut ends, &mut pending, left);
+            let right = snapshot_end(&machine, &mut graph, &mut ends, &mut pending, right);
+            graph.connect(left, right);
+        }
+        while let Some((port, addr)) = pending.pop() {
+            let end = snapshot_end(&machine, &mut graph, &mut ends, &mut pending, addr);
+            graph.connect(port, end);
+        }
+
+        let mut label = format!("after {} reductions", machine.reductions());
+        if let Some(e) = error {
+            label.push_str(&format!(", stopped: {}", e));
+        }
+        graph.write(f, &label, 1)
+    }
+}
+
+/// 运行时的项在图中对应的端点，交互器的辅助端口加入 `pending` 等待连接
+fn snapshot_end(
+    machine: &Machine,
+    graph: &mut Graph,
+    ends: &mut HashMap<Addr, End>,
+    pending: &mut Vec<(End, Addr)>,
+    mut addr: Addr,
+) -> End {
+    while let Some(Cell::Link(target)) = machine.cell(addr) {
+        addr = *target;
+    }
+    if let Some(&end) = ends.get(&addr) {
+        return end;
+    }
+    let end = match machine.cell(addr) {
+        Some(Cell::Agent { id, ports }) => {
+            let agent = &machine.program().agents[id.0];
+            let end = graph.add_agent(&agent.name, ports.len());
+            for (i, &port) in ports.iter().enumerate() {
+                pending.push((end.aux(i + 1), port));
+            }
+            end
+        }
+        Some(Cell::Int(value)) => graph.add_agent(&value.to_string(), 0),
+        Some(Cell::Name { .. }) => graph.add_wire(),
+        Some(Cell::Link(_)) | Some(Cell::Free) | None => graph.add_free("<freed>"),
+    };
+    ends.insert(addr, end);
+    end
+}
+
+/// 图中的端点
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum End {
+    /// 交互器的主端口
+    Principal(usize),
+    /// 交互器的辅助端口，从 1 开始编号
+    Aux(usize, usize),
+    /// 自由端口
+    Free(usize),
+    /// 变量，最终会被消去
+    Wire(usize),
+}
+
+impl End {
+    fn aux(self, slot: usize) -> End {
+        match self {
+            End::Principal(agent) => End::Aux(agent, slot),
+            end => unreachable!("{:?} has no auxiliary ports", end),
+        }
+    }
+}
+
+/// 一张网络图
+struct Graph {
+    prefix: String,
+    agents: Vec<(String, usize)>,
+    free: Vec<String>,
+    wires: usize,
+    edges: Vec<(End, End)>,
+}
+
+impl Graph {
+    fn new(prefix: String) -> Self {
+        Self {
+            prefix,
+            agents: vec![],
+            free: vec![],
+            wires: 0,
+            edges: vec![],
+        }
+    }
+
+    fn add_agent(&mut self, name: &str, arity: usize) -> End {
+        self.agents.push((name.to_string(), arity));
+        End::Principal(self.agents.len() - 1)
+    }
+
+    fn add_free(&mut self, label: &str) -> End {
+        self.free.push(label.to_string());
+        End::Free(self.free.len() - 1)
+    }
+
+    fn add_wire(&mut self) -> End {
+        self.wires += 1;
+        End::Wire(self.wires - 1)
+    }
+
+    fn connect(&mut self, a: End, b: End) {
+        self.edges.push((a, b));
+    }
+
+    /// 消去变量：恰好连接两个端点的变量替换为一条边，首尾相连的变量成环后丢弃
+    fn resolve(&mut self) {
+        for wire in (0..self.wires).map(End::Wire) {
+            let incident = self
+                .edges
+                .iter()
+                .enumerate()
+                .filter(|(_, &(a, b))| a == wire || b == wire)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            match incident[..] {
+                [i] if self.edges[i] == (wire, wire) => {
+                    self.edges.remove(i);
+                }
+                [i, j] => {
+                    let other = |(a, b): (End, End)| if a == wire { b } else { a };
+                    let (a, b) = (other(self.edges[i]), other(self.edges[j]));
+                    self.edges.remove(j);
+                    self.edges.remove(i);
+                    self.edges.push((a, b));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn node(&self, end: End) -> String {
+        match end {
+            End::Principal(agent) => format!("{}_a{}:p", self.prefix, agent),
+            End::Aux(agent, slot) => format!("{}_a{}:p{}", self.prefix, agent, slot),
+            End::Free(free) => format!("{}_f{}", self.prefix, free),
+            End::Wire(wire) => format!("{}_w{}", self.prefix, wire),
+        }
+    }
+
+    /// 以 `depth` 层缩进写入子图
+    fn write(
+        mut self,
+        f: &mut impl std::io::Write,
+        label: &str,
+        depth: usize,
+    ) -> Result<(), super::Error> {
+        self.resolve();
+        let indent = "    ".repeat(depth);
+
+        writeln!(f, "{}subgraph cluster_{} {{", indent, self.prefix)?;
+        writeln!(f, "{}    label=\"{}\";", indent, escape(label))?;
+        for (i, (name, arity)) in self.agents.iter().enumerate() {
+            let ports = (1..=*arity)
+                .map(|slot| format!("<p{}> {}", slot, slot))
+                .collect::<Vec<_>>()
+                .join("|");
+            let ports = if ports.is_empty() {
+                String::new()
+            } else {
+                format!("|{{{}}}", ports)
+            };
+            writeln!(
+                f,
+                "{}    {}_a{} [shape=record, label=\"{{<p> •|{}{}}}\"];",
+                indent,
+                self.prefix,
+                i,
+                escape_record(name),
+                ports
+            )?;
+        }
+        for (i, label) in self.free.iter().enumerate() {
+            writeln!(
+                f,
+                "{}    {}_f{} [shape=plaintext, label=\"{}\"];",
+                indent,
+                self.prefix,
+                i,
+                escape(label)
+            )?;
+        }
+        for wire in 0..self.wires {
+            if self
+                .edges
+                .iter()
+                .any(|&(a, b)| a == End::Wire(wire) || b == End::Wire(wire))
+            {
+                writeln!(f, "{}    {}_w{} [shape=point];", indent, self.prefix, wire)?;
+            }
+        }
+        for &(a, b) in &self.edges {
+            let style = match (a, b) {
+                // 活跃对
+                (End::Principal(_), End::Principal(_)) => " [color=red, penwidth=2]",
+                _ => "",
+            };
+            writeln!(
+                f,
+                "{}    {} -- {}{};",
+                indent,
+                self.node(a),
+                self.node(b),
+                style
+            )?;
+        }
+        writeln!(f, "{}}}", indent)?;
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_record(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        if matches!(c, '{' | '}' | '<' | '>' | '|' | '"' | '\\' | ' ') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
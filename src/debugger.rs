@@ -0,0 +1,468 @@
+//! 交互式调试器。
+//!
+//! 在解释器中逐步执行程序，支持在交互器对或规则上设置断点、查看方程栈以及打印任意子项。
+
+use std::io::{BufRead, Write};
+
+use anyhow::Result;
+
+use crate::backend::interpreter::{ActivePair, Addr, Cell, Machine};
+
+const HELP: &str = "\
+commands:
+  step [n]              reduce the next n active pairs (alias: s)
+  continue              reduce until a breakpoint is hit (alias: c)
+  where                 show the next active pair (alias: w)
+  break <A> <B>         stop before agents A and B interact (alias: b)
+  break rule <text>     stop before a rule whose description contains text
+  breakpoints           list breakpoints
+  delete <n>            delete breakpoint n (alias: d)
+  stack [n]             show the top n equations, 10 by default
+  print <path> [depth]  print a term, e.g. `12`, `12.0.1` or `out0.1` (alias: p)
+  outputs               print the outputs of Main
+  rules                 list all rules
+  help                  show this message (alias: h)
+  quit                  exit the debugger (alias: q)
+an empty line repeats the last command";
+
+/// 断点
+enum Breakpoint {
+    /// 两个交互器相互作用之前
+    Pair(String, String),
+    /// 描述中包含指定文本的规则被使用之前
+    Rule(String),
+}
+
+impl std::fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Breakpoint::Pair(a, b) => write!(f, "{} >< {}", a, b),
+            Breakpoint::Rule(text) => write!(f, "rule containing `{}`", text),
+        }
+    }
+}
+
+/// 程序的状态
+#[derive(PartialEq, Eq)]
+enum State {
+    Running,
+    Finished,
+    Failed,
+}
+
+/// 调试器
+pub struct Debugger<R, W> {
+    machine: Machine,
+    breakpoints: Vec<Breakpoint>,
+    /// 是否停在了断点处，此时继续运行不会再次命中当前的活跃对
+    at_breakpoint: bool,
+    state: State,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// 创建调试器，从 `input` 读取命令并向 `output` 输出
+    pub fn new(machine: Machine, input: R, output: W) -> Self {
+        Self {
+            machine,
+            breakpoints: vec![],
+            at_breakpoint: false,
+            state: State::Running,
+            input,
+            output,
+        }
+    }
+
+    /// 读取并执行命令，直到输入结束或退出
+    pub fn run(mut self) -> Result<()> {
+        writeln!(self.output, "type `help` for a list of commands")?;
+        self.show_next()?;
+
+        let mut last = String::new();
+        loop {
+            write!(self.output, "(zz) ")?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                break;
+            }
+            let line = match line.trim() {
+                "" => last.clone(),
+                line => line.to_string(),
+            };
+            last = line.clone();
+
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.as_slice() {
+                [] => {}
+                ["q" | "quit"] => break,
+                ["h" | "help"] => writeln!(self.output, "{}", HELP)?,
+                ["s" | "step"] => self.step(1)?,
+                ["s" | "step", n] => match n.parse() {
+                    Ok(n) => self.step(n)?,
+                    Err(_) => writeln!(self.output, "invalid count `{}`", n)?,
+                },
+                ["c" | "continue"] => self.resume()?,
+                ["w" | "where"] => self.show_next()?,
+                ["b" | "break", "rule", text @ ..] if !text.is_empty() => {
+                    self.add_breakpoint(Breakpoint::Rule(text.join(" ")))?
+                }
+                ["b" | "break", a, b] => {
+                    self.add_breakpoint(Breakpoint::Pair(a.to_string(), b.to_string()))?
+                }
+                ["breakpoints"] => self.list_breakpoints()?,
+                ["d" | "delete", n] => self.delete_breakpoint(n)?,
+                ["stack"] => self.show_stack(10)?,
+                ["stack", n] => match n.parse() {
+                    Ok(n) => self.show_stack(n)?,
+                    Err(_) => writeln!(self.output, "invalid count `{}`", n)?,
+                },
+                ["p" | "print", path] => self.print(path, 10)?,
+                ["p" | "print", path, depth] => match depth.parse() {
+                    Ok(depth) => self.print(path, depth)?,
+                    Err(_) => writeln!(self.output, "invalid depth `{}`", depth)?,
+                },
+                ["outputs"] => self.show_outputs()?,
+                ["rules"] => self.list_rules()?,
+                _ => writeln!(
+                    self.output,
+                    "unknown command `{}`, type `help` for a list of commands",
+                    line
+                )?,
+            }
+        }
+        Ok(())
+    }
+
+    fn is_running(&mut self) -> Result<bool> {
+        match self.state {
+            State::Running => Ok(true),
+            State::Finished => {
+                writeln!(self.output, "the program has finished")?;
+                Ok(false)
+            }
+            State::Failed => {
+                writeln!(self.output, "the program has stopped with an error")?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// 规约一个活跃对，程序结束或出错时返回 `false`
+    fn reduce(&mut self) -> Result<bool> {
+        self.at_breakpoint = false;
+        match self.machine.reduce() {
+            Ok(Some(_)) => {
+                if self.machine.next_active_pair().is_none() {
+                    self.state = State::Finished;
+                    writeln!(
+                        self.output,
+                        "finished after {} reductions",
+                        self.machine.reductions()
+                    )?;
+                    self.show_outputs()?;
+                    return Ok(false);
+                }
+                Ok(true)
+            }
+            Ok(None) => {
+                self.state = State::Finished;
+                Ok(false)
+            }
+            Err(e) => {
+                self.state = State::Failed;
+                writeln!(self.output, "error: {}", e)?;
+                Ok(false)
+            }
+        }
+    }
+
+    fn step(&mut self, count: usize) -> Result<()> {
+        if !self.is_running()? {
+            return Ok(());
+        }
+        for _ in 0..count {
+            if !self.reduce()? {
+                return Ok(());
+            }
+        }
+        self.show_next()
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        if !self.is_running()? {
+            return Ok(());
+        }
+        // 在即将规约的活跃对上检查断点，只跳过刚刚停下的那一个
+        while let Some(pair) = self.machine.next_active_pair() {
+            if !self.at_breakpoint {
+                if let Some(index) = self.hit(&pair) {
+                    self.at_breakpoint = true;
+                    writeln!(
+                        self.output,
+                        "breakpoint {}: {}",
+                        index + 1,
+                        self.breakpoints[index]
+                    )?;
+                    return self.show_next();
+                }
+            }
+            if !self.reduce()? {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+
+    /// 命中的断点
+    fn hit(&self, pair: &ActivePair) -> Option<usize> {
+        let left = self.machine.agent_name(pair.left).unwrap_or_default();
+        let right = self.machine.agent_name(pair.right).unwrap_or_default();
+        self.breakpoints
+            .iter()
+            .position(|breakpoint| match breakpoint {
+                Breakpoint::Pair(a, b) => {
+                    (a.as_str(), b.as_str()) == (left, right)
+                        || (a.as_str(), b.as_str()) == (right, left)
+                }
+                Breakpoint::Rule(text) => pair.rule.is_some_and(|rule| {
+                    self.machine.rule_description(rule).contains(text.as_str())
+                }),
+            })
+    }
+
+    fn show_next(&mut self) -> Result<()> {
+        if !self.is_running()? {
+            return Ok(());
+        }
+        let Some(pair) = self.machine.next_active_pair() else {
+            self.state = State::Finished;
+            return self.is_running().map(|_| ());
+        };
+        writeln!(
+            self.output,
+            "next: {} >< {}",
+            self.term(pair.left, 3),
+            self.term(pair.right, 3)
+        )?;
+        match pair.rule {
+            Some(rule) => writeln!(self.output, "rule: {}", self.machine.rule_description(rule))?,
+            None => writeln!(self.output, "rule: (none)")?,
+        }
+        writeln!(self.output, "reductions: {}", self.machine.reductions())?;
+        Ok(())
+    }
+
+    fn term(&self, addr: Addr, depth: usize) -> String {
+        format!("&{} {}", addr.0, self.machine.display(addr, depth))
+    }
+
+    fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> Result<()> {
+        self.breakpoints.push(breakpoint);
+        writeln!(
+            self.output,
+            "breakpoint {}: {}",
+            self.breakpoints.len(),
+            self.breakpoints.last().unwrap()
+        )?;
+        Ok(())
+    }
+
+    fn list_breakpoints(&mut self) -> Result<()> {
+        if self.breakpoints.is_empty() {
+            writeln!(self.output, "no breakpoints")?;
+        }
+        for (index, breakpoint) in self.breakpoints.iter().enumerate() {
+            writeln!(self.output, "{}: {}", index + 1, breakpoint)?;
+        }
+        Ok(())
+    }
+
+    fn delete_breakpoint(&mut self, n: &str) -> Result<()> {
+        match n.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                let breakpoint = self.breakpoints.remove(n - 1);
+                writeln!(self.output, "deleted breakpoint {}: {}", n, breakpoint)?;
+            }
+            _ => writeln!(self.output, "no breakpoint `{}`", n)?,
+        }
+        Ok(())
+    }
+
+    fn show_stack(&mut self, count: usize) -> Result<()> {
+        let stack = self.machine.stack();
+        if stack.is_empty() {
+            writeln!(self.output, "the equation stack is empty")?;
+            return Ok(());
+        }
+        let lines = stack
+            .iter()
+            .rev()
+            .take(count)
+            .enumerate()
+            .map(|(i, &(left, right))| {
+                format!("{:>4}: {} = {}", i, self.term(left, 3), self.term(right, 3))
+            })
+            .collect::<Vec<_>>();
+        for line in lines {
+            writeln!(self.output, "{}", line)?;
+        }
+        if stack.len() > count {
+            writeln!(self.output, "      ... {} more", stack.len() - count)?;
+        }
+        Ok(())
+    }
+
+    fn show_outputs(&mut self) -> Result<()> {
+        let outputs = self
+            .machine
+            .outputs()
+            .iter()
+            .enumerate()
+            .map(|(i, &output)| format!("out{}: {}", i, self.machine.display(output, 1000)))
+            .collect::<Vec<_>>();
+        for output in outputs {
+            writeln!(self.output, "{}", output)?;
+        }
+        Ok(())
+    }
+
+    fn list_rules(&mut self) -> Result<()> {
+        let rules = self
+            .machine
+            .program()
+            .rules
+            .iter()
+            .map(|rule| format!("{:>4}: {}", rule.index, rule.description))
+            .collect::<Vec<_>>();
+        for rule in rules {
+            writeln!(self.output, "{}", rule)?;
+        }
+        Ok(())
+    }
+
+    fn print(&mut self, path: &str, depth: usize) -> Result<()> {
+        match self.resolve(path) {
+            Ok(addr) => {
+                let term = self.term(addr, depth);
+                writeln!(self.output, "{}", term)?
+            }
+            Err(e) => writeln!(self.output, "{}", e)?,
+        }
+        Ok(())
+    }
+
+    /// 跳过已连接的变量
+    fn deref(&self, mut addr: Addr) -> Addr {
+        while let Some(Cell::Link(target)) = self.machine.cell(addr) {
+            addr = *target;
+        }
+        addr
+    }
+
+    /// 解析形如 `12.0.1` 或 `out0.1` 的路径：堆地址或输出，后接端口下标
+    fn resolve(&self, path: &str) -> std::result::Result<Addr, String> {
+        let mut parts = path.split('.');
+        let root = parts.next().unwrap_or_default();
+        let mut addr = if let Some(index) = root.strip_prefix("out") {
+            index
+                .parse::<usize>()
+                .ok()
+                .and_then(|index| self.machine.outputs().get(index).copied())
+                .ok_or_else(|| format!("no output `{}`", root))?
+        } else {
+            root.parse::<usize>()
+                .ok()
+                .map(Addr)
+                .filter(|&addr| self.machine.cell(addr).is_some())
+                .ok_or_else(|| format!("no term at `{}`", root))?
+        };
+
+        for part in parts {
+            addr = self.deref(addr);
+            let ports = match self.machine.cell(addr) {
+                Some(Cell::Agent { ports, .. }) => ports,
+                _ => return Err(format!("&{} has no ports", addr.0)),
+            };
+            addr = part
+                .parse::<usize>()
+                .ok()
+                .and_then(|port| ports.get(port).copied())
+                .ok_or_else(|| format!("&{} has no port `{}`", addr.0, part))?;
+        }
+        Ok(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::options::Options;
+    use crate::testing::backends;
+    use crate::Context;
+
+    const ADD: &str = "S(#x) >> Add(#y, @w) => #x -> Add(#y, @z), @w <- S(#z)
+Zero  >> Add(#x, @y) => #x -> @y
+
+Main <| #result |> S(S(Zero)) -> Add(S(Zero), @result)";
+
+    /// 依次执行 `commands` 中的命令，返回每条命令的输出，第一项是启动时的输出
+    fn debug(source: &str, commands: &str) -> Vec<String> {
+        let mut output = vec![];
+        Context::new()
+            .add_file("test.zz", source)
+            .unwrap()
+            .debug(commands.as_bytes(), &mut output)
+            .unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .split("(zz) ")
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn breakpoint_on_first_pair() {
+        let output = debug(ADD, "b S Add\nc\n");
+        assert!(output[0].contains("next: &4 Add(S(Zero), result_30) >< &1 S(S(Zero))"));
+        assert!(output[0].contains("reductions: 0"));
+        assert_eq!(output[1], "breakpoint 1: S >< Add\n");
+        assert!(output[2].starts_with("breakpoint 1: S >< Add\nnext: &4 Add(S(Zero), result_30)"));
+        assert!(output[2].contains("reductions: 0"));
+    }
+
+    #[test]
+    fn continue_skips_only_the_current_pair() {
+        let output = debug(ADD, "b S Add\nc\nc\nc\n");
+        assert!(output[2].contains("reductions: 0"));
+        assert!(output[3].starts_with("breakpoint 1: S >< Add\n"));
+        assert!(output[3].contains("reductions: 2"));
+        assert!(output[4].starts_with("finished after"));
+    }
+
+    #[test]
+    fn step_clears_the_breakpoint() {
+        let output = debug(ADD, "b rule Zero\nc\nstep\nc\n");
+        assert!(output[2].starts_with("breakpoint 1: rule containing `Zero`\n"));
+        assert!(output[2].contains("rule: Zero >> Add(#x, @y) => #x -> @y"));
+        assert!(output[3].starts_with("finished after"));
+        assert_eq!(output[4], "the program has finished\n");
+    }
+
+    #[test]
+    fn outputs_agree_with_c() {
+        let source = ADD.replace(
+            "Main <| #result |> S(S(Zero)) -> Add(S(Zero), @result)",
+            "Main <| #a, #b |> S(Zero) -> Add(S(Zero), @a), 6 -> Mul(7, @b)",
+        );
+        let output = debug(&source, "c\n");
+        let expected = backends::run(&source, Options::default());
+        let outputs = output[1]
+            .lines()
+            .skip(1)
+            .map(|line| line.split_once(": ").unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(outputs, expected.stdout.lines().collect::<Vec<_>>());
+    }
+}
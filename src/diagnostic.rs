@@ -0,0 +1,170 @@
+//! 结构化的诊断信息。
+//!
+//! 编译错误与警告除了渲染为带颜色的文本，还可以转换为 [`Diagnostic`]，
+//! 序列化为 JSON 供编辑器与持续集成等工具使用。
+
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::utils::Span;
+
+/// 严重程度
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// 错误，编译无法继续
+    Error,
+    /// 警告，不影响编译
+    Warning,
+}
+
+/// 源代码中的位置
+///
+/// `start` 与 `end` 是字节偏移；行号与列号从 1 开始，列号按字符计数。
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Location {
+    /// 文件名
+    pub file: String,
+    /// 起始字节偏移
+    pub start: usize,
+    /// 结束字节偏移（不含）
+    pub end: usize,
+    /// 起始行号
+    pub line: usize,
+    /// 起始列号
+    pub column: usize,
+    /// 结束行号
+    pub end_line: usize,
+    /// 结束列号
+    pub end_column: usize,
+}
+
+impl Location {
+    pub(crate) fn new(span: &Span<()>) -> Self {
+        let (line, column) = line_column(span.source, span.start);
+        let (end_line, end_column) = line_column(span.source, span.end);
+        Self {
+            file: span.filename.to_string(),
+            start: span.start,
+            end: span.end,
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+/// 去掉终端颜色等 ANSI 转义序列
+fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' && chars.clone().next() == Some('[') {
+            // 跳过参数，直到结尾的字母
+            chars.by_ref().skip(1).find(|c| ('@'..='~').contains(c));
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+fn serialize_plain<S: serde::Serializer>(text: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&strip_ansi(text))
+}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line = before.matches('\n').count() + 1;
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+/// 带说明的位置
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Label {
+    /// 位置
+    #[serde(flatten)]
+    pub location: Location,
+    /// 说明
+    pub message: String,
+}
+
+/// 诊断信息
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    /// 诊断代码，例如 `overlapping-rules`
+    pub code: &'static str,
+    /// 严重程度
+    pub severity: Severity,
+    /// 信息
+    pub message: String,
+    /// 主要位置，与程序整体相关的诊断没有位置
+    #[serde(flatten)]
+    pub location: Option<Location>,
+    /// 主要位置的说明
+    pub label: Option<String>,
+    /// 其他相关位置，例如与之重叠的另一条规则
+    pub related: Vec<Label>,
+    /// 渲染后的文本，与终端中显示的一致；序列化时去掉颜色
+    #[serde(serialize_with = "serialize_plain")]
+    pub rendered: String,
+}
+
+impl Diagnostic {
+    /// 由若干带说明的位置创建诊断信息，第一个位置是主要位置
+    pub(crate) fn new(
+        code: &'static str,
+        severity: Severity,
+        message: String,
+        spans: Vec<(Span<()>, String)>,
+        rendered: String,
+    ) -> Self {
+        let mut spans = spans.into_iter().map(|(span, message)| Label {
+            location: Location::new(&span),
+            message,
+        });
+        let primary = spans.next();
+        Self {
+            code,
+            severity,
+            message,
+            label: primary.as_ref().map(|label| label.message.clone()),
+            location: primary.map(|label| label.location),
+            related: spans.collect(),
+            rendered,
+        }
+    }
+
+    /// 序列化为单行 JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("diagnostics are always serializable")
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.rendered.fmt(f)
+    }
+}
+
+/// 编译失败时的全部错误
+#[derive(Clone, Debug)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, diagnostic) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            diagnostic.fmt(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
@@ -3,16 +3,45 @@
 //! 语法树包括以下结构体：
 //! - Name：名称，用于表示变量名、交互器名等
 //! - Agent：交互器，由名称和交互器体组成
-//! - Term：项，包括名称和交互器
+//! - Term：项，包括名称、整数、交互器和字面量
+//! - Literal：列表与元组字面量，检查之前按语法糖指令展开为交互器
 //! - Equation：方程，由左右两个项组成
-//! - RuleTerm：规则中的项，由交互器名称和交互器体组成
+//! - RuleTerm：规则中的项，由交互器名称和模式组成，模式是变量或者嵌套的规则项
 //! - Rule：规则，由两个规则项和若干方程组成
-//! - Program：整个程序，由规则、方程和接口组成
+//! - Declaration：交互器声明，由交互器名称和端口组成
+//! - Import：导入其他源文件
+//! - 模块声明：`module list`，文件中的交互器属于模块 `list`
+//! - Sugar：语法糖指令，指定字面量与输出结果使用的交互器
+//! - Net：网络，由名称、接口和若干方程组成
+//! - Module：整个程序，由导入、语法糖指令、声明、规则、网络以及顶级语句和输出组成
 
 use std::fmt::Display;
 
+use typed_arena::Arena;
+
 use crate::utils::Span;
 
+/// 不在源代码中的名称，例如改写后的完整名称以及展开嵌套模式时生成的名称
+///
+/// 语法树借用这里保存的字符串，因此它需要比语法树活得更久。
+#[derive(Default)]
+pub struct GeneratedNames(Arena<String>);
+
+impl GeneratedNames {
+    /// 保存一个名称
+    pub fn alloc(&self, name: String) -> &str {
+        self.0.alloc(name)
+    }
+}
+
+impl std::fmt::Debug for GeneratedNames {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("GeneratedNames")
+            .field(&self.0.len())
+            .finish()
+    }
+}
+
 /// 变量名称
 #[derive(Debug, Clone, PartialEq)]
 pub enum Name<'a> {
@@ -74,15 +103,95 @@ impl<'a> Display for Agent<'a> {
 pub enum Term<'a> {
     /// 名称
     Name(Span<'a, Name<'a>>),
+    /// 整数
+    Int(Span<'a, i64>),
     /// 交互器
     Agent(Span<'a, Agent<'a>>),
+    /// 列表或元组字面量
+    Literal(Span<'a, Literal<'a>>),
+}
+
+impl<'a> Term<'a> {
+    /// 项的位置
+    pub fn span(&self) -> Span<'a, ()> {
+        match self {
+            Term::Name(name) => name.as_unit(),
+            Term::Int(value) => value.as_unit(),
+            Term::Agent(agent) => agent.as_unit(),
+            Term::Literal(literal) => literal.as_unit(),
+        }
+    }
 }
 
 impl<'a> Display for Term<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Term::Name(name) => write!(f, "{}", name),
+            Term::Int(value) => write!(f, "{}", value),
             Term::Agent(agent) => write!(f, "{}", agent),
+            Term::Literal(literal) => write!(f, "{}", literal),
+        }
+    }
+}
+
+/// 列表与元组字面量
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal<'a> {
+    /// 列表 `[a, b | t]`，没有 `| t` 时以空表结尾
+    List {
+        /// 元素
+        elements: Vec<Term<'a>>,
+        /// `|` 之后的剩余部分
+        tail: Option<Box<Term<'a>>>,
+    },
+    /// 元组 `(a, b)`，至少有两个元素
+    Tuple(Vec<Term<'a>>),
+}
+
+impl<'a> Literal<'a> {
+    /// 展开时使用的语法糖
+    pub fn kind(&self) -> SugarKind {
+        match self {
+            Literal::List { .. } => SugarKind::List,
+            Literal::Tuple(_) => SugarKind::Tuple,
+        }
+    }
+
+    /// 字面量中直接出现的项
+    pub fn terms(&self) -> impl Iterator<Item = &Term<'a>> {
+        let (elements, tail) = match self {
+            Literal::List { elements, tail } => (elements, tail.as_deref()),
+            Literal::Tuple(elements) => (elements, None),
+        };
+        elements.iter().chain(tail)
+    }
+
+    /// 字面量中直接出现的项，可以修改
+    pub fn terms_mut(&mut self) -> impl Iterator<Item = &mut Term<'a>> {
+        let (elements, tail) = match self {
+            Literal::List { elements, tail } => (elements, tail.as_deref_mut()),
+            Literal::Tuple(elements) => (elements, None),
+        };
+        elements.iter_mut().chain(tail)
+    }
+}
+
+impl<'a> Display for Literal<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |terms: &[Term]| {
+            terms
+                .iter()
+                .map(|t| t.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Literal::List {
+                elements,
+                tail: Some(tail),
+            } => write!(f, "[{} | {}]", join(elements), tail),
+            Literal::List { elements, .. } => write!(f, "[{}]", join(elements)),
+            Literal::Tuple(elements) => write!(f, "({})", join(elements)),
         }
     }
 }
@@ -102,13 +211,62 @@ impl<'a> Display for Equation<'a> {
     }
 }
 
+/// 规则项中的模式
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern<'a> {
+    /// 变量
+    Name(Span<'a, Name<'a>>),
+    /// 通配符 `_`，端口上的值直接交给 `Erase`
+    Wildcard(Span<'a, ()>),
+    /// 嵌套的交互器，只能出现在构造函数一侧
+    Agent(Span<'a, RuleTerm<'a>>),
+}
+
+impl<'a> Pattern<'a> {
+    /// 模式的位置
+    pub fn span(&self) -> Span<'a, ()> {
+        match self {
+            Pattern::Name(name) => name.as_unit(),
+            Pattern::Wildcard(span) => span.clone(),
+            Pattern::Agent(term) => term.as_unit(),
+        }
+    }
+}
+
+impl<'a> Display for Pattern<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Name(name) => write!(f, "{}", name),
+            Pattern::Wildcard(_) => write!(f, "_"),
+            Pattern::Agent(term) => write!(f, "{}", term),
+        }
+    }
+}
+
 /// 规则中的项
 #[derive(Debug, Clone, PartialEq)]
 pub struct RuleTerm<'a> {
     /// 交互器名称
     pub agent: Span<'a, &'a str>,
     /// 交互器体
-    pub body: Vec<Span<'a, Name<'a>>>,
+    pub body: Vec<Pattern<'a>>,
+}
+
+impl<'a> RuleTerm<'a> {
+    /// 交互器体中直接出现的变量，不含通配符与嵌套模式中的变量
+    pub fn names(&self) -> impl Iterator<Item = &Span<'a, Name<'a>>> {
+        self.body.iter().filter_map(|pattern| match pattern {
+            Pattern::Name(name) => Some(name),
+            Pattern::Wildcard(_) | Pattern::Agent(_) => None,
+        })
+    }
+
+    /// 交互器体中是否没有嵌套模式
+    pub fn is_flat(&self) -> bool {
+        self.body
+            .iter()
+            .all(|pattern| !matches!(pattern, Pattern::Agent(_)))
+    }
 }
 
 impl<'a> Display for RuleTerm<'a> {
@@ -173,6 +331,99 @@ impl<'a> Display for Rule<'a> {
     }
 }
 
+/// 交互器声明
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration<'a> {
+    /// 是否为私有交互器，私有交互器只能在声明它的文件中使用
+    pub private: bool,
+    /// 交互器名称
+    pub agent: Span<'a, &'a str>,
+    /// 交互器端口
+    pub ports: Vec<Span<'a, Name<'a>>>,
+}
+
+impl<'a> Display for Declaration<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.private {
+            write!(f, "private ")?;
+        }
+        if self.ports.is_empty() {
+            write!(f, "agent {}", self.agent)
+        } else {
+            write!(
+                f,
+                "agent {}({})",
+                self.agent,
+                self.ports
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        }
+    }
+}
+
+/// 语法糖的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SugarKind {
+    /// 列表，指定非空表与空表，例如 `sugar list(Cons, Nil)`
+    List,
+    /// 自然数，指定后继与零，例如 `sugar number(S, Zero)`
+    Number,
+    /// 元组，例如 `sugar tuple(Tuple)`
+    Tuple,
+}
+
+impl Display for SugarKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SugarKind::List => write!(f, "list"),
+            SugarKind::Number => write!(f, "number"),
+            SugarKind::Tuple => write!(f, "tuple"),
+        }
+    }
+}
+
+/// 语法糖指令
+///
+/// 指令对整个程序有效：字面量按指令展开为交互器，输出结果时这些交互器以字面量的形式显示。
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sugar<'a> {
+    /// 语法糖的种类
+    pub kind: SugarKind,
+    /// 使用的交互器，顺序与种类的说明一致
+    pub agents: Vec<Span<'a, &'a str>>,
+}
+
+impl<'a> Display for Sugar<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "sugar {}({})",
+            self.kind,
+            self.agents
+                .iter()
+                .map(|agent| agent.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )
+    }
+}
+
+/// 导入其他源文件
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import<'a> {
+    /// 被导入文件的路径，不含引号
+    pub path: Span<'a, &'a str>,
+}
+
+impl<'a> Display for Import<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "import \"{}\"", self.path)
+    }
+}
+
 /// 程序中的网络
 #[derive(Debug, Clone, PartialEq)]
 pub struct Net<'a> {
@@ -209,22 +460,79 @@ impl<'a> Display for Net<'a> {
 }
 
 /// 整个程序
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Module<'a> {
+    /// 程序导入的其他源文件
+    pub imports: Vec<Span<'a, Import<'a>>>,
+    /// 模块声明 `module list` 中的模块名，每个文件至多一个
+    pub modules: Vec<Span<'a, &'a str>>,
+    /// 语法糖指令
+    pub sugars: Vec<Span<'a, Sugar<'a>>>,
+    /// 程序中的交互器声明
+    pub declarations: Vec<Span<'a, Declaration<'a>>>,
     /// 程序中的规则
     pub rules: Vec<Span<'a, Rule<'a>>>,
     /// 程序中的网络
     pub nets: Vec<Span<'a, Net<'a>>>,
+    /// 顶级语句 `:: ...`，与输出一起构成入口网络
+    pub statements: Vec<Span<'a, Equation<'a>>>,
+    /// 程序的输出 `$ = ...`
+    pub outputs: Vec<Term<'a>>,
+}
+
+impl<'a> Module<'a> {
+    /// 是否使用顶级语句或输出定义入口网络
+    pub fn has_top_level(&self) -> bool {
+        !self.statements.is_empty() || !self.outputs.is_empty()
+    }
+
+    /// 作为被导入的库使用，去掉入口网络 `Main` 以及顶级语句与输出
+    pub fn into_library(mut self) -> Self {
+        self.nets.retain(|net| *net.name.as_ref() != "Main");
+        self.statements.clear();
+        self.outputs.clear();
+        self
+    }
+
+    /// 将另一个模块的内容追加到这个模块中
+    pub fn extend(&mut self, other: Module<'a>) {
+        self.imports.extend(other.imports);
+        self.modules.extend(other.modules);
+        self.sugars.extend(other.sugars);
+        self.declarations.extend(other.declarations);
+        self.rules.extend(other.rules);
+        self.nets.extend(other.nets);
+        self.statements.extend(other.statements);
+        self.outputs.extend(other.outputs);
+    }
 }
 
 impl<'a> Display for Module<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for module in &self.modules {
+            writeln!(f, "module {}", module)?;
+        }
+        for import in &self.imports {
+            writeln!(f, "{}", import)?;
+        }
+        for sugar in &self.sugars {
+            writeln!(f, "{}", sugar)?;
+        }
+        for declaration in &self.declarations {
+            writeln!(f, "{}", declaration)?;
+        }
         for rule in &self.rules {
             writeln!(f, "{}", rule)?;
         }
         for net in &self.nets {
             writeln!(f, "{}", net)?;
         }
+        for statement in &self.statements {
+            writeln!(f, ":: {}", statement)?;
+        }
+        for output in &self.outputs {
+            writeln!(f, "$ = {}", output)?;
+        }
         Ok(())
     }
 }
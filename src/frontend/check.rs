@@ -4,7 +4,11 @@ use std::collections::{HashMap, HashSet};
 
 use thiserror::Error;
 
-use crate::{frontend::ast, utils::Span};
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    frontend::{ast, flow, namespace, pattern, semantic},
+    utils::Span,
+};
 
 /// 类型检查错误。
 #[derive(Error, Debug)]
@@ -25,6 +29,18 @@ pub enum TypeError<'a> {
         &'a Span<'a, ast::RuleTermPair<'a>>,
     ),
 
+    #[error("rules overlap")]
+    OverlappingPatterns {
+        rule: &'a Span<'a, ast::RuleTermPair<'a>>,
+        other: &'a Span<'a, ast::RuleTermPair<'a>>,
+        value: String,
+    },
+
+    #[error("patterns can only be nested on the constructor side")]
+    ReducerPattern {
+        pattern: &'a Span<'a, ast::RuleTerm<'a>>,
+    },
+
     #[error("input-output balance error")]
     MultipleTimesAsInput { name: &'a Span<'a, ast::Name<'a>> },
 
@@ -39,135 +55,463 @@ pub enum TypeError<'a> {
 
     #[error("no main function")]
     NoMainFunction,
+
+    #[error("`Main` is defined both as a net and by top-level statements")]
+    MainDefinedTwice {
+        net: &'a Span<'a, &'a str>,
+        top_level: Span<'a, ()>,
+    },
+
+    #[error("agent `{}` has arity {}, but {} is given", .name, .expected, .found)]
+    ArityMismatch {
+        name: &'a Span<'a, &'a str>,
+        expected: usize,
+        found: usize,
+        previous: Option<&'a Span<'a, &'a str>>,
+    },
+
+    #[error("inconsistent port polarity")]
+    PolarityConflict {
+        span: Span<'a, ()>,
+        found: semantic::Polarity,
+        previous: Option<Span<'a, ()>>,
+    },
+
+    #[error("equation connects two inputs")]
+    ConnectsTwoInputs {
+        equation: &'a Span<'a, ast::Equation<'a>>,
+    },
+
+    #[error("equation connects two outputs")]
+    ConnectsTwoOutputs {
+        equation: &'a Span<'a, ast::Equation<'a>>,
+    },
+
+    #[error("agent `{}` is declared more than once", .0.agent)]
+    DuplicateDeclaration(
+        &'a Span<'a, ast::Declaration<'a>>,
+        &'a Span<'a, ast::Declaration<'a>>,
+    ),
+
+    #[error("port name appears more than once in a declaration")]
+    DuplicatePort { name: &'a Span<'a, ast::Name<'a>> },
+
+    #[error("module `{}` is declared more than once", .0)]
+    DuplicateModule(&'a Span<'a, &'a str>, &'a Span<'a, &'a str>),
+
+    #[error("a file can declare only one module")]
+    MultipleModules(&'a Span<'a, &'a str>, &'a Span<'a, &'a str>),
+
+    #[error("unknown module `{}`", module_of(.name))]
+    UnknownModule { name: &'a Span<'a, &'a str> },
+
+    #[error("module `{}` has no agent `{}`", module_of(.name), agent_of(.name))]
+    UnknownAgent { name: &'a Span<'a, &'a str> },
+
+    #[error("agent `{}` is private", .name)]
+    PrivateAgent {
+        name: &'a Span<'a, &'a str>,
+        declaration: &'a Span<'a, ast::Declaration<'a>>,
+    },
+}
+
+fn module_of(name: &str) -> &str {
+    name.split_once("::").map_or("", |(module, _)| module)
+}
+
+fn agent_of(name: &str) -> &str {
+    name.split_once("::").map_or(name, |(_, agent)| agent)
 }
 
 impl<'a> TypeError<'a> {
-    fn slices(&self) -> Vec<annotate_snippets::snippet::Slice> {
-        use annotate_snippets::snippet::AnnotationType;
+    /// 诊断代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            TypeError::NonLinearRule { .. } => "non-linear-rule",
+            TypeError::VariableCountError { .. } => "variable-count",
+            TypeError::OverlappingRules(..) => "overlapping-rules",
+            TypeError::OverlappingPatterns { .. } => "overlapping-patterns",
+            TypeError::ReducerPattern { .. } => "reducer-pattern",
+            TypeError::MultipleTimesAsInput { .. } => "multiple-times-as-input",
+            TypeError::MultipleTimesAsOutput { .. } => "multiple-times-as-output",
+            TypeError::MisdirectedInput { .. } => "misdirected-input",
+            TypeError::MisdirectedOutput { .. } => "misdirected-output",
+            TypeError::NoMainFunction => "no-main-function",
+            TypeError::MainDefinedTwice { .. } => "main-defined-twice",
+            TypeError::ArityMismatch { .. } => "arity-mismatch",
+            TypeError::PolarityConflict { .. } => "polarity-conflict",
+            TypeError::ConnectsTwoInputs { .. } => "connects-two-inputs",
+            TypeError::ConnectsTwoOutputs { .. } => "connects-two-outputs",
+            TypeError::DuplicateDeclaration(..) => "duplicate-declaration",
+            TypeError::DuplicatePort { .. } => "duplicate-port",
+            TypeError::DuplicateModule(..) => "duplicate-module",
+            TypeError::MultipleModules(..) => "multiple-modules",
+            TypeError::UnknownModule { .. } => "unknown-module",
+            TypeError::UnknownAgent { .. } => "unknown-agent",
+            TypeError::PrivateAgent { .. } => "private-agent",
+        }
+    }
+
+    /// 相关的位置及其说明，第一个是主要位置
+    fn spans(&self) -> Vec<(Span<'a, ()>, String)> {
+        let label = |span: Span<'a, ()>, label: &str| (span, label.to_string());
         match self {
             TypeError::NonLinearRule { name } => {
-                vec![name
-                    .lines()
-                    .unwrap_or_default()
-                    .as_annotation("appears more than once", AnnotationType::Error)]
+                vec![label(name.as_unit(), "appears more than once")]
             }
             TypeError::VariableCountError { name, .. } => {
-                vec![name
-                    .lines()
-                    .unwrap_or_default()
-                    .as_annotation("appears more than once", AnnotationType::Error)]
+                vec![label(name.as_unit(), "appears more than once")]
             }
+            TypeError::OverlappingRules(r1, r2) if r1.filename != r2.filename => vec![
+                (
+                    r1.as_unit(),
+                    format!("overlaps with a rule in {} ...", r2.filename),
+                ),
+                label(r2.as_unit(), "... this rule"),
+            ],
             TypeError::OverlappingRules(r1, r2) => vec![
-                r1.lines()
-                    .unwrap_or_default()
-                    .as_annotation("overlaps with ...", AnnotationType::Error),
-                r2.lines()
-                    .unwrap_or_default()
-                    .as_annotation("... this rule", AnnotationType::Info),
+                label(r1.as_unit(), "overlaps with ..."),
+                label(r2.as_unit(), "... this rule"),
             ],
-            TypeError::MultipleTimesAsInput { name } => vec![name
-                .lines()
-                .unwrap_or_default()
-                .as_annotation("appears more than once as input", AnnotationType::Error)],
-            TypeError::MultipleTimesAsOutput { name } => vec![name
-                .lines()
-                .unwrap_or_default()
-                .as_annotation("appears more than once as output", AnnotationType::Error)],
-            TypeError::MisdirectedInput { name } => {
-                vec![name.lines().unwrap_or_default().as_annotation(
-                    "appears as input, where it should be output",
-                    AnnotationType::Error,
-                )]
+            TypeError::OverlappingPatterns { rule, other, value } => vec![
+                (
+                    rule.as_unit(),
+                    format!("`{}` is matched by this rule ...", value),
+                ),
+                label(other.as_unit(), "... and also by this rule"),
+            ],
+            TypeError::ReducerPattern { pattern } => vec![label(
+                pattern.as_unit(),
+                "the reducer side can only list variables",
+            )],
+            TypeError::MultipleTimesAsInput { name } => {
+                vec![label(name.as_unit(), "appears more than once as input")]
             }
-            TypeError::MisdirectedOutput { name } => {
-                vec![name.lines().unwrap_or_default().as_annotation(
-                    "appears as output, where it should be input",
-                    AnnotationType::Error,
-                )]
+            TypeError::MultipleTimesAsOutput { name } => {
+                vec![label(name.as_unit(), "appears more than once as output")]
             }
-            TypeError::NoMainFunction => vec![annotate_snippets::snippet::Slice {
-                source: "",
-                line_start: 0,
-                origin: None,
-                annotations: vec![annotate_snippets::snippet::SourceAnnotation {
-                    range: (0, 0),
-                    label: "no main function",
-                    annotation_type: AnnotationType::Error,
-                }],
-                fold: false,
-            }],
+            TypeError::MisdirectedInput { name } => vec![label(
+                name.as_unit(),
+                "appears as input, where it should be output",
+            )],
+            TypeError::MisdirectedOutput { name } => vec![label(
+                name.as_unit(),
+                "appears as output, where it should be input",
+            )],
+            TypeError::NoMainFunction => vec![],
+            TypeError::MainDefinedTwice { net, top_level } => vec![
+                label(net.as_unit(), "`Main` net defined here"),
+                label(top_level.clone(), "top-level statement here"),
+            ],
+            TypeError::ArityMismatch {
+                name,
+                expected,
+                previous,
+                ..
+            } => {
+                let expected = format!("expected arity {}", expected);
+                match previous {
+                    Some(previous) => vec![
+                        label(name.as_unit(), "wrong number of ports"),
+                        (previous.as_unit(), expected),
+                    ],
+                    None => vec![(name.as_unit(), expected)],
+                }
+            }
+            TypeError::PolarityConflict {
+                span,
+                found,
+                previous,
+            } => {
+                let expected = match found {
+                    semantic::Polarity::In => semantic::Polarity::Out,
+                    semantic::Polarity::Out => semantic::Polarity::In,
+                };
+                match previous {
+                    Some(previous) => vec![
+                        (span.clone(), format!("used as {}", found)),
+                        (previous.clone(), format!("used as {} here", expected)),
+                    ],
+                    None => vec![(span.clone(), format!("built-in agent expects {}", expected))],
+                }
+            }
+            TypeError::ConnectsTwoInputs { equation } => {
+                vec![label(equation.as_unit(), "both sides are inputs")]
+            }
+            TypeError::ConnectsTwoOutputs { equation } => {
+                vec![label(equation.as_unit(), "both sides are outputs")]
+            }
+            TypeError::DuplicateDeclaration(d1, d2) => vec![
+                label(d1.agent.as_unit(), "declared again here"),
+                label(d2.agent.as_unit(), "first declared here"),
+            ],
+            TypeError::DuplicatePort { name } => {
+                vec![label(name.as_unit(), "appears more than once")]
+            }
+            TypeError::DuplicateModule(m1, m2) => vec![
+                label(m1.as_unit(), "declared again here"),
+                label(m2.as_unit(), "first declared here"),
+            ],
+            TypeError::MultipleModules(m1, m2) => vec![
+                label(m1.as_unit(), "second module declaration"),
+                label(m2.as_unit(), "first module declaration"),
+            ],
+            TypeError::UnknownModule { name } => vec![(
+                name.as_unit(),
+                format!("no file declares `module {}`", module_of(name)),
+            )],
+            TypeError::UnknownAgent { name } => vec![(
+                name.as_unit(),
+                format!(
+                    "not declared or matched by a rule in module `{}`",
+                    module_of(name)
+                ),
+            )],
+            TypeError::PrivateAgent { name, declaration } => vec![
+                label(name.as_unit(), "used outside the file that declares it"),
+                label(declaration.agent.as_unit(), "declared private here"),
+            ],
         }
     }
 
     /// 将错误转换为可供显示的字符串。
     pub fn to_snippet(&self) -> String {
-        use annotate_snippets::display_list::{DisplayList, FormatOptions};
-        use annotate_snippets::snippet::{Annotation, AnnotationType, Snippet};
-
-        let label = self.to_string();
-
-        let snippet = Snippet {
-            title: Some(Annotation {
-                id: None,
-                label: Some(label.as_str()),
-                annotation_type: AnnotationType::Error,
-            }),
-            footer: vec![],
-            slices: self.slices(),
-            opt: FormatOptions {
-                color: true,
-                ..Default::default()
-            },
-        };
+        use annotate_snippets::snippet::AnnotationType;
+
+        render_snippet(&self.to_string(), AnnotationType::Error, &self.spans())
+    }
+
+    /// 将错误转换为结构化的诊断信息。
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(
+            self.code(),
+            Severity::Error,
+            self.to_string(),
+            self.spans(),
+            self.to_snippet(),
+        )
+    }
+}
+
+/// 检查警告，不影响编译。
+#[derive(Error, Debug)]
+#[allow(missing_docs)]
+pub enum Warning<'a> {
+    #[error("no rule for {} and {}", agent_label(.left), agent_label(.right))]
+    MissingRule {
+        left: &'a str,
+        left_span: Span<'a, ()>,
+        right: &'a str,
+        right_span: Span<'a, ()>,
+    },
+
+    #[error("non-exhaustive patterns: `{}` not covered", .value)]
+    NonExhaustivePatterns { span: Span<'a, ()>, value: String },
+}
+
+fn agent_label(agent: &str) -> String {
+    if agent == crate::backend::native::INT {
+        "integer".to_string()
+    } else {
+        format!("`{}`", agent)
+    }
+}
+
+impl<'a> Warning<'a> {
+    /// 诊断代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::MissingRule { .. } => "missing-rule",
+            Warning::NonExhaustivePatterns { .. } => "non-exhaustive-patterns",
+        }
+    }
+
+    /// 相关的位置及其说明，第一个是主要位置
+    fn spans(&self) -> Vec<(Span<'a, ()>, String)> {
+        match self {
+            Warning::MissingRule {
+                left,
+                left_span,
+                right,
+                right_span,
+            } => vec![
+                (
+                    left_span.clone(),
+                    format!("{} is created here ...", agent_label(left)),
+                ),
+                (
+                    right_span.clone(),
+                    format!(
+                        "... and may interact with {} created here",
+                        agent_label(right)
+                    ),
+                ),
+            ],
+            Warning::NonExhaustivePatterns { span, value } => {
+                vec![(span.clone(), format!("no rule matches `{}`", value))]
+            }
+        }
+    }
+
+    /// 将警告转换为可供显示的字符串。
+    pub fn to_snippet(&self) -> String {
+        use annotate_snippets::snippet::AnnotationType;
 
-        DisplayList::from(snippet).to_string()
+        render_snippet(&self.to_string(), AnnotationType::Warning, &self.spans())
+    }
+
+    /// 将警告转换为结构化的诊断信息。
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(
+            self.code(),
+            Severity::Warning,
+            self.to_string(),
+            self.spans(),
+            self.to_snippet(),
+        )
+    }
+}
+
+/// 渲染诊断信息，第一个位置使用 `annotation_type` 标注，其余位置作为补充说明
+pub(crate) fn render_snippet(
+    label: &str,
+    annotation_type: annotate_snippets::snippet::AnnotationType,
+    spans: &[(Span<()>, String)],
+) -> String {
+    use annotate_snippets::display_list::{DisplayList, FormatOptions};
+    use annotate_snippets::snippet::{
+        Annotation, AnnotationType, Slice, Snippet, SourceAnnotation,
+    };
+
+    let mut slices = spans
+        .iter()
+        .enumerate()
+        .map(|(i, (span, message))| {
+            let annotation_type = if i == 0 {
+                annotation_type
+            } else {
+                AnnotationType::Info
+            };
+            span.lines()
+                .unwrap_or_default()
+                .as_annotation(message, annotation_type)
+        })
+        .collect::<Vec<_>>();
+
+    // 与程序整体相关的诊断没有位置
+    if slices.is_empty() {
+        slices.push(Slice {
+            source: "",
+            line_start: 0,
+            origin: None,
+            annotations: vec![SourceAnnotation {
+                range: (0, 0),
+                label,
+                annotation_type,
+            }],
+            fold: false,
+        });
+    }
+
+    let snippet = Snippet {
+        title: Some(Annotation {
+            id: None,
+            label: Some(label),
+            annotation_type,
+        }),
+        footer: vec![],
+        slices,
+        opt: FormatOptions {
+            color: true,
+            ..Default::default()
+        },
+    };
+
+    DisplayList::from(snippet).to_string()
+}
+
+/// 交互器至多声明一次，端口名称不能重复
+pub fn check_declarations<'a>(program: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
+    let mut declarations: HashMap<_, &Span<ast::Declaration>> = HashMap::new();
+    for declaration in &program.declarations {
+        let agent = *declaration.agent.as_ref();
+        match declarations.get(agent) {
+            Some(other) => errors.push(TypeError::DuplicateDeclaration(declaration, other)),
+            None => {
+                declarations.insert(agent, declaration);
+            }
+        }
+
+        let mut names = HashSet::new();
+        for name in &declaration.ports {
+            if !names.insert(name.as_name()) {
+                errors.push(TypeError::DuplicatePort { name });
+            }
+        }
     }
 }
 
 /// 规则项中，每个变量只能出现一次
-pub fn check_rule_terms<'a>(rule: &'a ast::Rule) -> Result<(), TypeError<'a>> {
+pub fn check_rule_terms<'a>(rule: &'a ast::Rule, errors: &mut Vec<TypeError<'a>>) {
     let mut names = HashSet::new();
     for name in rule
         .term_pair
         .left
-        .body
-        .iter()
-        .chain(rule.term_pair.right.body.iter())
+        .names()
+        .chain(rule.term_pair.right.names())
     {
         if !names.insert(name.as_name()) {
-            return Err(TypeError::NonLinearRule { name });
+            errors.push(TypeError::NonLinearRule { name });
         }
     }
-    Ok(())
 }
 
-fn count_names<'a>(
-    term: &'a ast::Term,
-    names: &mut HashMap<&'a str, (i32, Option<&'a Span<'a, ast::Name<'a>>>)>,
-) {
+type NameCounts<'a> = HashMap<&'a str, (i32, Option<&'a Span<'a, ast::Name<'a>>>)>;
+
+fn count_names<'a>(term: &'a ast::Term, names: &mut NameCounts<'a>) {
     match term {
         ast::Term::Name(name) => {
             let entry = names.entry(name.as_name()).or_insert((0, None));
             entry.0 += 1;
             entry.1 = Some(name);
         }
+        ast::Term::Int(_) => {}
         ast::Term::Agent(agent) => {
             for term in &agent.body {
                 count_names(term, names);
             }
         }
+        ast::Term::Literal(literal) => {
+            for term in literal.terms() {
+                count_names(term, names);
+            }
+        }
     }
 }
 
+/// 按位置顺序报告出现次数不为二的变量
+fn check_name_counts<'a>(names: NameCounts<'a>, errors: &mut Vec<TypeError<'a>>) {
+    let mut wrong = names
+        .into_values()
+        .filter(|(count, _)| *count != 2)
+        .map(|(count, name)| (name.unwrap(), count))
+        .collect::<Vec<_>>();
+    wrong.sort_by_key(|(name, _)| name.start);
+    errors.extend(
+        wrong
+            .into_iter()
+            .map(|(name, count)| TypeError::VariableCountError { name, count }),
+    );
+}
+
 /// 规则中，所有变量必须恰好出现两次
-pub fn check_rule_variables<'a>(rule: &'a ast::Rule) -> Result<(), TypeError<'a>> {
+pub fn check_rule_variables<'a>(rule: &'a ast::Rule, errors: &mut Vec<TypeError<'a>>) {
     let mut names = HashMap::new();
     for name in rule
         .term_pair
         .left
-        .body
-        .iter()
-        .chain(rule.term_pair.right.body.iter())
+        .names()
+        .chain(rule.term_pair.right.names())
     {
         let entry = names.entry(name.as_name()).or_insert((0, None));
         entry.0 += 1;
@@ -177,68 +521,69 @@ pub fn check_rule_variables<'a>(rule: &'a ast::Rule) -> Result<(), TypeError<'a>
         count_names(&equation.left, &mut names);
         count_names(&equation.right, &mut names);
     }
-
-    for (_, (count, name)) in names {
-        if count != 2 {
-            let name = name.unwrap();
-            return Err(TypeError::VariableCountError { name, count });
-        }
-    }
-    Ok(())
+    check_name_counts(names, errors);
 }
 
 /// 网络中，所有变量必须恰好出现两次
-pub fn check_net_variables<'a>(net: &'a ast::Net) -> Result<(), TypeError<'a>> {
+pub fn check_net_variables<'a>(net: &'a ast::Net, errors: &mut Vec<TypeError<'a>>) {
+    check_interface_variables(&net.equations, &net.interfaces, errors)
+}
+
+/// 全部顶级语句与输出中，所有变量必须恰好出现两次
+pub fn check_top_level_variables<'a>(module: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
+    check_interface_variables(&module.statements, &module.outputs, errors)
+}
+
+fn check_interface_variables<'a>(
+    equations: &'a [Span<ast::Equation>],
+    interfaces: &'a [ast::Term],
+    errors: &mut Vec<TypeError<'a>>,
+) {
     let mut names = HashMap::new();
-    for equation in &net.equations {
+    for equation in equations {
         count_names(&equation.left, &mut names);
         count_names(&equation.right, &mut names);
     }
-    for interface in &net.interfaces {
+    for interface in interfaces {
         count_names(interface, &mut names);
     }
-
-    for (_, (count, name)) in names {
-        if count != 2 {
-            let name = name.unwrap();
-            return Err(TypeError::VariableCountError { name, count });
-        }
-    }
-    Ok(())
+    check_name_counts(names, errors);
 }
 
 /// 不允许冲突的规则
-pub fn check_overlapping<'a>(program: &'a ast::Module) -> Result<(), TypeError<'a>> {
+pub fn check_overlapping<'a>(program: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
     let mut rule_sets: HashMap<_, &Span<ast::Rule>> = HashMap::new();
     for rule in &program.rules {
         let agents = (
             *rule.term_pair.left.agent.as_ref(),
             *rule.term_pair.right.agent.as_ref(),
         );
-        if let Some(other) = rule_sets.get(&agents) {
-            return Err(TypeError::OverlappingRules(
+        match rule_sets.get(&agents) {
+            Some(other) => errors.push(TypeError::OverlappingRules(
                 &rule.term_pair,
                 &other.term_pair,
-            ));
+            )),
+            None => {
+                rule_sets.insert(agents, rule);
+            }
         }
-        rule_sets.insert(agents, rule);
     }
-    Ok(())
 }
 
 fn check_term_io_balance<'a>(
     term: &'a ast::Term,
     input_map: &mut HashMap<&'a str, bool>,
-) -> Result<(), TypeError<'a>> {
+    errors: &mut Vec<TypeError<'a>>,
+) {
     match term {
         ast::Term::Name(name) => {
             if let Some(&input) = input_map.get(name.as_name()) {
                 match name.as_ref() {
                     ast::Name::In(_) if input => {
-                        return Err(TypeError::MultipleTimesAsInput { name })
+                        errors.push(TypeError::MultipleTimesAsInput { name })
                     }
                     ast::Name::Out(_) if !input => {
-                        return Err(TypeError::MultipleTimesAsOutput { name })
+                        errors.push(TypeError::MultipleTimesAsOutput { name })
                     }
                     _ => {}
                 };
@@ -249,44 +594,61 @@ fn check_term_io_balance<'a>(
                 };
             }
         }
+        ast::Term::Int(_) => {}
         ast::Term::Agent(agent) => {
             for term in &agent.body {
-                check_term_io_balance(term, input_map)?;
+                check_term_io_balance(term, input_map, errors);
+            }
+        }
+        ast::Term::Literal(literal) => {
+            for term in literal.terms() {
+                check_term_io_balance(term, input_map, errors);
             }
         }
     }
-    Ok(())
 }
 
 fn check_equations_io_balance<'a>(
     net: &'a [Span<ast::Equation>],
     mut input_map: HashMap<&'a str, bool>,
-) -> Result<(), TypeError<'a>> {
+    errors: &mut Vec<TypeError<'a>>,
+) {
     for equation in net {
-        check_term_io_balance(&equation.left, &mut input_map)?;
-        check_term_io_balance(&equation.right, &mut input_map)?;
+        check_term_io_balance(&equation.left, &mut input_map, errors);
+        check_term_io_balance(&equation.right, &mut input_map, errors);
     }
-    Ok(())
 }
 
 /// 网络输入-输出平衡
-pub fn check_net_io_balance<'a>(net: &'a ast::Net) -> Result<(), TypeError<'a>> {
+pub fn check_net_io_balance<'a>(net: &'a ast::Net, errors: &mut Vec<TypeError<'a>>) {
+    check_interface_io_balance(&net.equations, &net.interfaces, errors)
+}
+
+/// 全部顶级语句与输出的输入-输出平衡
+pub fn check_top_level_io_balance<'a>(module: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
+    check_interface_io_balance(&module.statements, &module.outputs, errors)
+}
+
+fn check_interface_io_balance<'a>(
+    equations: &'a [Span<ast::Equation>],
+    interfaces: &'a [ast::Term],
+    errors: &mut Vec<TypeError<'a>>,
+) {
     let mut input_map = HashMap::new();
-    for interface in &net.interfaces {
-        check_term_io_balance(interface, &mut input_map)?;
+    for interface in interfaces {
+        check_term_io_balance(interface, &mut input_map, errors);
     }
-    check_equations_io_balance(&net.equations, input_map)
+    check_equations_io_balance(equations, input_map, errors)
 }
 
 /// 规则输入-输出平衡
-pub fn check_rule_io_balance<'a>(rule: &'a ast::Rule) -> Result<(), TypeError<'a>> {
+pub fn check_rule_io_balance<'a>(rule: &'a ast::Rule, errors: &mut Vec<TypeError<'a>>) {
     let mut input_map = HashMap::new();
     for name in rule
         .term_pair
         .left
-        .body
-        .iter()
-        .chain(rule.term_pair.right.body.iter())
+        .names()
+        .chain(rule.term_pair.right.names())
     {
         match name.as_ref() {
             // Write the opposite input flag
@@ -294,52 +656,165 @@ pub fn check_rule_io_balance<'a>(rule: &'a ast::Rule) -> Result<(), TypeError<'a
             ast::Name::Out(_) => input_map.insert(name.as_name(), true),
         };
     }
-    check_equations_io_balance(&rule.equations, input_map)
+    check_equations_io_balance(&rule.equations, input_map, errors)
 }
 
 /// 变量：左输入右输出
-pub fn check_equation_var_io_dir<'a>(equation: &'a ast::Equation) -> Result<(), TypeError<'a>> {
+pub fn check_equation_var_io_dir<'a>(equation: &'a ast::Equation, errors: &mut Vec<TypeError<'a>>) {
     if let ast::Term::Name(name) = &equation.left {
         if let ast::Name::Out(_) = name.as_ref() {
-            return Err(TypeError::MisdirectedOutput { name });
+            errors.push(TypeError::MisdirectedOutput { name });
         }
     }
     if let ast::Term::Name(name) = &equation.right {
         if let ast::Name::In(_) = name.as_ref() {
-            return Err(TypeError::MisdirectedInput { name });
+            errors.push(TypeError::MisdirectedInput { name });
         }
     }
-    Ok(())
 }
 
-/// Main 函数存在
-pub fn check_main<'a>(program: &'a ast::Module) -> Result<(), TypeError<'a>> {
-    if !program.nets.iter().any(|net| *net.name.as_ref() == "Main") {
-        return Err(TypeError::NoMainFunction);
+/// Main 函数存在，由 `Main` 网络或顶级语句之一定义
+pub fn check_main<'a>(program: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
+    let main = program.nets.iter().find(|net| *net.name.as_ref() == "Main");
+    let top_level = program
+        .statements
+        .first()
+        .map(|statement| statement.as_unit())
+        .or_else(|| program.outputs.first().map(|output| output.span()));
+
+    match (main, top_level) {
+        (None, None) => errors.push(TypeError::NoMainFunction),
+        (Some(main), Some(top_level)) => errors.push(TypeError::MainDefinedTwice {
+            net: &main.name,
+            top_level,
+        }),
+        _ => {}
+    }
+}
+
+/// 检查模块声明：每个文件至多声明一个模块，模块名不能重复
+pub fn check_modules<'a>(program: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
+    let mut files: HashMap<&str, &Span<&str>> = HashMap::new();
+    let mut names: HashMap<&str, &Span<&str>> = HashMap::new();
+    for module in &program.modules {
+        if let Some(first) = files.get(module.filename) {
+            errors.push(TypeError::MultipleModules(module, first));
+            continue;
+        }
+        files.insert(module.filename, module);
+        match names.get(module.as_ref()) {
+            Some(first) => errors.push(TypeError::DuplicateModule(module, first)),
+            None => {
+                names.insert(module, module);
+            }
+        }
     }
-    Ok(())
 }
 
-/// 检查整个程序
-pub fn check_module<'a>(module: &'a ast::Module) -> Result<(), TypeError<'a>> {
+/// 检查交互器的引用：限定名称中的模块必须存在并且用到了这个交互器，私有交互器只能在声明它的文件中使用
+pub fn check_agent_references<'a>(program: &'a ast::Module, errors: &mut Vec<TypeError<'a>>) {
+    let modules: HashSet<&str> = program.modules.iter().map(|m| *m.as_ref()).collect();
+    let agents = namespace::agent_names(program);
+    let defined: HashSet<&str> = agents
+        .iter()
+        .filter(|agent| !namespace::is_qualified(agent))
+        .map(|agent| *agent.as_ref())
+        .collect();
+    let private: HashMap<&str, &Span<ast::Declaration>> = program
+        .declarations
+        .iter()
+        .filter(|declaration| declaration.private)
+        .map(|declaration| (*declaration.agent.as_ref(), declaration))
+        .collect();
+
+    for agent in agents {
+        if namespace::is_qualified(agent) {
+            if !modules.contains(module_of(agent)) {
+                errors.push(TypeError::UnknownModule { name: agent });
+                continue;
+            }
+            if !defined.contains(agent.as_ref()) {
+                errors.push(TypeError::UnknownAgent { name: agent });
+                continue;
+            }
+        }
+        if let Some(declaration) = private.get(agent.as_ref()) {
+            if declaration.filename != agent.filename {
+                errors.push(TypeError::PrivateAgent {
+                    name: agent,
+                    declaration,
+                });
+            }
+        }
+    }
+}
+
+/// 检查整个程序，返回不影响编译的警告，或者全部错误
+pub fn check_module<'a>(module: &'a ast::Module) -> Result<Vec<Warning<'a>>, Vec<TypeError<'a>>> {
+    let mut errors = vec![];
+    check_modules(module, &mut errors);
+    check_declarations(module, &mut errors);
+    check_agent_references(module, &mut errors);
+
     for rule in &module.rules {
-        check_rule_terms(rule)?;
-        check_rule_variables(rule)?;
-        check_rule_io_balance(rule)?;
+        check_rule_terms(rule, &mut errors);
+        check_rule_variables(rule, &mut errors);
+        check_rule_io_balance(rule, &mut errors);
         for equation in &rule.equations {
-            check_equation_var_io_dir(equation)?;
+            check_equation_var_io_dir(equation, &mut errors);
         }
     }
 
     for net in &module.nets {
-        check_net_variables(net)?;
-        check_net_io_balance(net)?;
+        check_net_variables(net, &mut errors);
+        check_net_io_balance(net, &mut errors);
         for equation in &net.equations {
-            check_equation_var_io_dir(equation)?;
+            check_equation_var_io_dir(equation, &mut errors);
         }
     }
 
-    check_overlapping(module)?;
-    check_main(module)?;
-    Ok(())
+    check_top_level_variables(module, &mut errors);
+    check_top_level_io_balance(module, &mut errors);
+    for statement in &module.statements {
+        check_equation_var_io_dir(statement, &mut errors);
+    }
+
+    check_overlapping(module, &mut errors);
+    let signatures = semantic::infer_signatures(module, &mut errors);
+    check_main(module, &mut errors);
+
+    // 存在错误时，规则完整性的分析没有意义
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(flow::check_rule_completeness(module, &signatures))
+}
+
+/// 检查全部文件：展开嵌套模式，再合并为一个程序检查
+///
+/// 返回每个文件展开后的模块以及全部警告，或者全部错误。
+pub fn check_files<'a>(
+    modules: &'a [ast::Module<'a>],
+    names: &'a ast::GeneratedNames,
+) -> Result<(Vec<ast::Module<'a>>, Vec<Diagnostic>), Vec<Diagnostic>> {
+    let mut errors = vec![];
+    let mut warnings = vec![];
+    let modules = pattern::desugar(modules, names, &mut errors, &mut warnings);
+    if !errors.is_empty() {
+        return Err(errors.iter().map(|error| error.to_diagnostic()).collect());
+    }
+    let mut warnings = warnings
+        .iter()
+        .map(|warning| warning.to_diagnostic())
+        .collect::<Vec<_>>();
+
+    let mut program = ast::Module::default();
+    for module in &modules {
+        program.extend(module.clone());
+    }
+    match check_module(&program) {
+        Ok(more) => warnings.extend(more.iter().map(|warning| warning.to_diagnostic())),
+        Err(errors) => return Err(errors.iter().map(|error| error.to_diagnostic()).collect()),
+    }
+    Ok((modules, warnings))
 }
@@ -0,0 +1,411 @@
+//! 规则完备性分析。
+//!
+//! 从 Main 网络（或顶级语句）出发，抽象地模拟规约过程：交互器按其在源代码中创建的位置区分，
+//! 每个位置上交互器的每个辅助端口记录可能与之相连的端点。当两个交互器的主端口可能相连，
+//! 而它们之间没有规则时，运行时必然会因为 “no rule” 而退出，这里提前给出警告。
+//!
+//! 分析是保守的：同一位置创建的所有实例共享辅助端口的信息，因此可能给出多余的警告，
+//! 但不会遗漏任何可能相遇的交互器对。
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use crate::{
+    backend::{native, NativeOp},
+    frontend::{ast, check::Warning, pattern, semantic::Signatures},
+    utils::Span,
+};
+
+/// 由内置规则产生的交互器的来源
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Origin<'a> {
+    /// 原生规则由 `site` 处的交互器产生的 `agent`
+    Native(&'a str, usize),
+    /// 源代码中 `root` 处的 `Dup` 为复制 `site` 处交互器的 `port` 号端口而产生的 `Dup`
+    Copy {
+        root: usize,
+        site: usize,
+        port: usize,
+    },
+}
+
+/// 规则左侧的两个交互器
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Side {
+    Left,
+    Right,
+}
+
+/// 连接的端点
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Node {
+    /// 某处创建的交互器的主端口
+    Site(usize),
+    /// 某处创建的交互器的辅助端口
+    Port(usize, usize),
+    /// 规则左侧交互器的辅助端口上原本连接的端点
+    Var(Side, usize),
+    /// 网络的接口
+    Root,
+    /// 变量，连接恰好两个端点
+    Wire(usize),
+}
+
+/// 规则右侧或网络中的全部连接
+#[derive(Default)]
+struct Wiring<'a> {
+    links: Vec<(Node, Node)>,
+    wires: HashMap<&'a str, usize>,
+}
+
+impl<'a> Wiring<'a> {
+    fn wire(&mut self, name: &'a str) -> Node {
+        let next = self.wires.len();
+        Node::Wire(*self.wires.entry(name).or_insert(next))
+    }
+
+    /// 消去变量，得到直接相连的端点对
+    fn resolve(self) -> Vec<(Node, Node)> {
+        let mut incident = vec![vec![]; self.wires.len()];
+        for (index, (x, y)) in self.links.iter().enumerate() {
+            for node in [x, y] {
+                if let Node::Wire(wire) = node {
+                    incident[*wire].push(index);
+                }
+            }
+        }
+
+        let mut pairs = vec![];
+        for (index, &(x, y)) in self.links.iter().enumerate() {
+            let ends = match (x, y) {
+                (Node::Wire(_), Node::Wire(_)) => continue,
+                (Node::Wire(wire), node) | (node, Node::Wire(wire)) => {
+                    (node, self.follow(&incident, wire, index))
+                }
+                (x, y) => (x, Some(y)),
+            };
+            if let (x, Some(y)) = ends {
+                pairs.push((x, y));
+            }
+        }
+        pairs
+    }
+
+    /// 从 `from` 号连接进入变量 `wire`，沿变量走到另一端的端点
+    fn follow(&self, incident: &[Vec<usize>], mut wire: usize, mut from: usize) -> Option<Node> {
+        // 变量首尾相连时不存在端点，步数不会超过变量的个数
+        for _ in 0..=incident.len() {
+            let &next = incident[wire].iter().find(|&&link| link != from)?;
+            let (x, y) = self.links[next];
+            let other = if x == Node::Wire(wire) { y } else { x };
+            match other {
+                Node::Wire(other) => (wire, from) = (other, next),
+                node => return Some(node),
+            }
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Analysis<'a> {
+    /// 交互器的创建位置
+    sites: Vec<(&'a str, Span<'a, ()>)>,
+    /// 由内置规则创建的交互器
+    derived: HashMap<Origin<'a>, usize>,
+    /// 由 `Dup` 规则创建的 `Dup`，对应源代码中最初的 `Dup`
+    roots: HashMap<usize, usize>,
+    arities: HashMap<&'a str, usize>,
+    rules: HashMap<(&'a str, &'a str), usize>,
+    wirings: Vec<Vec<(Node, Node)>>,
+    partners: HashMap<(usize, usize), HashSet<Node>>,
+    meets: BTreeSet<(usize, usize)>,
+    missing: BTreeMap<(&'a str, &'a str), (usize, usize)>,
+    changed: bool,
+}
+
+impl<'a> Analysis<'a> {
+    fn site(&mut self, agent: &'a str, span: Span<'a, ()>) -> usize {
+        self.sites.push((agent, span));
+        self.sites.len() - 1
+    }
+
+    /// 由内置规则产生的交互器，位置与 `span_of` 处的交互器相同
+    fn derived(&mut self, agent: &'a str, origin: Origin<'a>, span_of: usize) -> usize {
+        if let Some(&site) = self.derived.get(&origin) {
+            return site;
+        }
+        let site = self.site(agent, self.sites[span_of].1.clone());
+        self.derived.insert(origin, site);
+        site
+    }
+
+    fn term(&mut self, wiring: &mut Wiring<'a>, term: &'a ast::Term<'a>) -> Node {
+        match term {
+            ast::Term::Name(name) => wiring.wire(name.as_name()),
+            ast::Term::Int(value) => Node::Site(self.site(native::INT, value.as_unit())),
+            ast::Term::Agent(agent) => {
+                let name = *agent.name.as_ref();
+                self.arities.insert(name, agent.body.len());
+                let site = self.site(name, agent.name.as_unit());
+                for (port, term) in agent.body.iter().enumerate() {
+                    let node = self.term(wiring, term);
+                    wiring.links.push((node, Node::Port(site, port)));
+                }
+                Node::Site(site)
+            }
+            // 字面量在检查之前已经展开
+            ast::Term::Literal(_) => unreachable!(),
+        }
+    }
+
+    fn equations(&mut self, wiring: &mut Wiring<'a>, equations: &'a [Span<'a, ast::Equation<'a>>]) {
+        for equation in equations {
+            let left = self.term(wiring, &equation.left);
+            let right = self.term(wiring, &equation.right);
+            wiring.links.push((left, right));
+        }
+    }
+
+    fn rule(&mut self, rule: &'a ast::Rule<'a>) {
+        let mut wiring = Wiring::default();
+        for (side, term) in [
+            (Side::Left, &rule.term_pair.left),
+            (Side::Right, &rule.term_pair.right),
+        ] {
+            self.arities.insert(term.agent.as_ref(), term.body.len());
+            for (port, pattern) in term.body.iter().enumerate() {
+                let node = match pattern {
+                    ast::Pattern::Name(name) => wiring.wire(name.as_name()),
+                    ast::Pattern::Wildcard(span) => Node::Site(self.site("Erase", span.clone())),
+                    // 嵌套模式在检查之前已经展开
+                    ast::Pattern::Agent(_) => unreachable!(),
+                };
+                wiring.links.push((node, Node::Var(side, port)));
+            }
+        }
+        self.equations(&mut wiring, &rule.equations);
+
+        let (left, right) = (
+            *rule.term_pair.left.agent.as_ref(),
+            *rule.term_pair.right.agent.as_ref(),
+        );
+        self.rules.insert((left, right), self.wirings.len());
+        self.wirings.push(wiring.resolve());
+    }
+
+    fn net(
+        &mut self,
+        equations: &'a [Span<'a, ast::Equation<'a>>],
+        interfaces: &'a [ast::Term<'a>],
+    ) -> Vec<(Node, Node)> {
+        let mut wiring = Wiring::default();
+        self.equations(&mut wiring, equations);
+        for interface in interfaces {
+            let node = self.term(&mut wiring, interface);
+            wiring.links.push((node, Node::Root));
+        }
+        wiring.resolve()
+    }
+
+    fn partners(&self, site: usize, port: usize) -> Vec<Node> {
+        self.partners
+            .get(&(site, port))
+            .map(|nodes| nodes.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    fn add_partner(&mut self, site: usize, port: usize, node: Node) {
+        self.changed |= self.partners.entry((site, port)).or_default().insert(node);
+    }
+
+    /// 连接两个端点
+    fn connect(&mut self, x: Node, y: Node) {
+        match (x, y) {
+            (Node::Site(a), Node::Site(b)) => {
+                self.changed |= self.meets.insert((a.min(b), a.max(b)));
+            }
+            (Node::Site(a), Node::Port(b, port)) | (Node::Port(b, port), Node::Site(a)) => {
+                self.add_partner(b, port, Node::Site(a))
+            }
+            (Node::Port(a, i), Node::Port(b, j)) => {
+                self.add_partner(a, i, Node::Port(b, j));
+                self.add_partner(b, j, Node::Port(a, i));
+            }
+            _ => {}
+        }
+    }
+
+    /// 将端点 `x` 与 `site` 处交互器的 `port` 号辅助端口上原本连接的端点相连
+    fn connect_partners(&mut self, x: Node, site: usize, port: usize) {
+        for y in self.partners(site, port) {
+            self.connect(x, y);
+        }
+    }
+
+    /// `left` 与 `right` 处的交互器按第 `index` 条规则相互作用
+    fn apply_rule(&mut self, index: usize, left: usize, right: usize) {
+        let expand = |this: &Self, node| match node {
+            Node::Var(Side::Left, port) => this.partners(left, port),
+            Node::Var(Side::Right, port) => this.partners(right, port),
+            node => vec![node],
+        };
+        for k in 0..self.wirings[index].len() {
+            let (x, y) = self.wirings[index][k];
+            for x in expand(self, x) {
+                for y in expand(self, y) {
+                    self.connect(x, y);
+                }
+            }
+        }
+    }
+
+    /// 内置交互器 `a` 与交互器 `b` 之间的规则，不存在时返回 `false`
+    fn apply_builtin(&mut self, a: usize, b: usize) -> bool {
+        let (ka, kb) = (self.sites[a].0, self.sites[b].0);
+        match (ka, kb) {
+            ("Dup", "Dup") => {
+                for port in 0..2 {
+                    for x in self.partners(a, port) {
+                        self.connect_partners(x, b, port);
+                    }
+                }
+            }
+            ("Erase", "Erase") | ("Erase", native::INT) => {}
+            ("Dup", native::INT) => {
+                self.connect_partners(Node::Site(b), a, 0);
+                self.connect_partners(Node::Site(b), a, 1);
+            }
+            (native::INT, op) if NativeOp::from_name(op).is_some() => {
+                let op = NativeOp::from_name(op).unwrap();
+                let origin = Origin::Native(op.partial_name(), b);
+                let partial = self.derived(op.partial_name(), origin, b);
+                self.connect_partners(Node::Site(partial), b, 0);
+                for x in self.partners(b, 1) {
+                    self.connect(Node::Port(partial, 1), x);
+                }
+            }
+            (native::INT, partial) if NativeOp::from_partial_name(partial).is_some() => {
+                let op = NativeOp::from_partial_name(partial).unwrap();
+                let results: &[&str] = if op.is_comparison() {
+                    &["True", "False"]
+                } else {
+                    &[native::INT]
+                };
+                for result in results {
+                    let site = self.derived(result, Origin::Native(result, b), b);
+                    self.connect_partners(Node::Site(site), b, 1);
+                }
+            }
+            // 复制得到的交互器与原来的交互器视为在同一位置创建
+            ("Dup", agent) if !is_native(agent) => {
+                self.connect_partners(Node::Site(b), a, 0);
+                self.connect_partners(Node::Site(b), a, 1);
+                let root = self.roots.get(&a).copied().unwrap_or(a);
+                for port in 0..self.arities.get(agent).copied().unwrap_or_default() {
+                    let origin = Origin::Copy {
+                        root,
+                        site: b,
+                        port,
+                    };
+                    let dup = self.derived("Dup", origin, root);
+                    self.roots.insert(dup, root);
+                    self.connect_partners(Node::Site(dup), b, port);
+                    self.connect(Node::Port(dup, 0), Node::Port(b, port));
+                    self.connect(Node::Port(dup, 1), Node::Port(b, port));
+                }
+            }
+            ("Erase", agent) if !is_native(agent) => {
+                for port in 0..self.arities.get(agent).copied().unwrap_or_default() {
+                    self.connect_partners(Node::Site(a), b, port);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn interact(&mut self, a: usize, b: usize) {
+        let (ka, kb) = (self.sites[a].0, self.sites[b].0);
+        if let Some(&index) = self.rules.get(&(ka, kb)) {
+            return self.apply_rule(index, a, b);
+        }
+        if let Some(&index) = self.rules.get(&(kb, ka)) {
+            return self.apply_rule(index, b, a);
+        }
+        if self.apply_builtin(a, b) || self.apply_builtin(b, a) {
+            return;
+        }
+        let key = if ka <= kb { (ka, kb) } else { (kb, ka) };
+        self.missing.entry(key).or_insert((a, b));
+    }
+
+    fn run(&mut self, main: &[(Node, Node)]) {
+        loop {
+            self.changed = false;
+            for &(x, y) in main {
+                self.connect(x, y);
+            }
+            for (a, b) in self.meets.clone() {
+                self.interact(a, b);
+            }
+            if !self.changed {
+                break;
+            }
+        }
+    }
+}
+
+/// 行为由编译目标实现的内置交互器，没有自动生成的 `Dup` 与 `Erase` 规则
+fn is_native(agent: &str) -> bool {
+    agent == "Dup" || agent == "Erase" || agent == native::INT
+}
+
+/// 找出 Main 网络规约过程中可能相遇、但没有规则的交互器对
+pub fn check_rule_completeness<'a>(
+    module: &'a ast::Module<'a>,
+    signatures: &Signatures<'a>,
+) -> Vec<Warning<'a>> {
+    let (equations, interfaces) = match module.nets.iter().find(|net| *net.name.as_ref() == "Main")
+    {
+        Some(main) => (&main.equations[..], &main.interfaces[..]),
+        None => (&module.statements[..], &module.outputs[..]),
+    };
+
+    let mut analysis = Analysis::default();
+    for builtin in native::builtin_agents() {
+        analysis.arities.insert(builtin.name, builtin.ports.len());
+    }
+    for rule in &module.rules {
+        analysis.rule(rule);
+    }
+    let main = analysis.net(equations, interfaces);
+    analysis.run(&main);
+
+    // 辅助交互器缺少的规则由嵌套模式的完备性检查报告
+    let mut missing = analysis
+        .missing
+        .into_values()
+        .filter(|&(a, b)| {
+            !pattern::is_auxiliary(analysis.sites[a].0)
+                && !pattern::is_auxiliary(analysis.sites[b].0)
+        })
+        .collect::<Vec<_>>();
+    missing.sort();
+    missing
+        .into_iter()
+        .map(|(a, b)| {
+            let (mut a, mut b) = (&analysis.sites[a], &analysis.sites[b]);
+            // 构造函数在前，与规则的写法一致
+            let is_reducer = |agent| signatures.get(agent).is_some_and(|s| s.is_reducer());
+            if is_reducer(a.0) {
+                (a, b) = (b, a);
+            }
+            Warning::MissingRule {
+                left: a.0,
+                left_span: a.1.clone(),
+                right: b.0,
+                right_span: b.1.clone(),
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,524 @@
+//! 源代码格式化。
+//!
+//! 语法树中没有注释，也不记录 `<<` 与 `<-` 的写法，因此格式化器在语法树之外
+//! 还参考源代码：项之间与方程之间的注释原样保留，方向由两侧在源代码中的先后判断。
+//!
+//! 格式化的规则：
+//! - 相邻且之间没有空行与注释的规则构成一组，组内的 `>>` 与 `=>` 对齐；
+//! - 规则与网络的方程原本写在多行，或者一行超过 [`MAX_WIDTH`] 个字符时，
+//!   每个方程单独一行，缩进四个空格并以 `;` 结尾；否则写在同一行，以 `, ` 分隔；
+//! - 连续的多个空行合并为一个；
+//! - 注释出现在无法安排的位置（例如项的内部）时，整个声明、规则或网络保持原样。
+//!
+//! 格式化的结果再次格式化时保持不变。
+
+use crate::{
+    frontend::{ast, parser},
+    utils::Span,
+};
+
+/// 单行的最大宽度
+pub const MAX_WIDTH: usize = 100;
+
+/// 格式化源代码
+pub fn format<'a>(
+    source: &'a str,
+    filename: &'a str,
+) -> Result<String, Vec<parser::SyntaxError<'a>>> {
+    let module = parser::parse(source, filename)?;
+    Ok(Formatter::new(source).module(&module))
+}
+
+/// 顶级项
+enum Item<'a> {
+    Import(&'a Span<'a, ast::Import<'a>>),
+    Module(&'a Span<'a, &'a str>),
+    Sugar(&'a Span<'a, ast::Sugar<'a>>),
+    Declaration(&'a Span<'a, ast::Declaration<'a>>),
+    Rule(&'a Span<'a, ast::Rule<'a>>),
+    Net(&'a Span<'a, ast::Net<'a>>),
+    Statement(&'a Span<'a, ast::Equation<'a>>),
+    Output(&'a ast::Term<'a>),
+}
+
+/// 项之间的注释
+struct Comment<'s> {
+    text: &'s str,
+    /// 与前一项在同一行
+    trailing: bool,
+    /// 与前一项之间有空行
+    blank_before: bool,
+}
+
+/// 两项之间的空白与注释
+struct Gap<'s> {
+    comments: Vec<Comment<'s>>,
+    /// 与后一项之间有空行
+    blank_after: bool,
+}
+
+/// 格式化后的一项，`lines` 中的每个元素是一行或一个多行注释
+struct Lines {
+    lines: Vec<String>,
+}
+
+impl Lines {
+    fn new() -> Self {
+        Self { lines: vec![] }
+    }
+
+    fn push(&mut self, line: impl Into<String>) {
+        self.lines.push(line.into())
+    }
+
+    /// 插入项之间的注释，`indent` 为独占一行的注释的缩进
+    fn gap(&mut self, gap: &Gap, indent: &str) {
+        for comment in &gap.comments {
+            match self.lines.last_mut() {
+                Some(last) if comment.trailing => {
+                    last.push(' ');
+                    last.push_str(comment.text);
+                }
+                _ => {
+                    if comment.blank_before && !self.lines.is_empty() {
+                        self.push("");
+                    }
+                    self.push(format!("{}{}", indent, comment.text));
+                }
+            }
+        }
+        if gap.blank_after && !self.lines.is_empty() {
+            self.push("");
+        }
+    }
+}
+
+struct Formatter<'s> {
+    source: &'s str,
+    /// 源代码中全部注释的范围
+    comments: Vec<(usize, usize)>,
+}
+
+impl<'s> Formatter<'s> {
+    fn new(source: &'s str) -> Self {
+        let mut comments = vec![];
+        let mut offset = 0;
+        while let Some(start) = source[offset..].find("/*") {
+            let start = offset + start;
+            let end = source[start + 2..]
+                .find("*/")
+                .map(|i| start + 2 + i + 2)
+                .unwrap_or(source.len());
+            comments.push((start, end));
+            offset = end;
+        }
+        Self { source, comments }
+    }
+
+    fn has_comment(&self, start: usize, end: usize) -> bool {
+        self.comments.iter().any(|&(s, e)| start < e && s < end)
+    }
+
+    /// 去掉末尾的空白、注释与分隔符后的结束位置
+    ///
+    /// 语法中可选部分之前的空白与注释会被计入片段，这里将其去掉。
+    fn content_end(&self, start: usize, end: usize) -> usize {
+        let mut end = end;
+        loop {
+            let text = self.source[start..end]
+                .trim_end()
+                .trim_end_matches([',', ';'])
+                .trim_end();
+            let trimmed = start + text.len();
+            match self.comments.iter().find(|&&(_, e)| e == trimmed) {
+                Some(&(s, _)) if s >= start => end = s,
+                _ if trimmed != end => end = trimmed,
+                _ => return end,
+            }
+        }
+    }
+
+    fn gap(&self, start: usize, end: usize, has_previous: bool) -> Gap<'s> {
+        let mut comments = vec![];
+        let mut cursor = start;
+        for &(s, e) in &self.comments {
+            if s < start || e > end {
+                continue;
+            }
+            let between = &self.source[cursor..s];
+            comments.push(Comment {
+                text: &self.source[s..e],
+                trailing: (has_previous || !comments.is_empty()) && !between.contains('\n'),
+                blank_before: between.matches('\n').count() >= 2,
+            });
+            cursor = e;
+        }
+        Gap {
+            comments,
+            blank_after: self.source[cursor..end].matches('\n').count() >= 2,
+        }
+    }
+
+    fn item_span(&self, item: &Item) -> (usize, usize) {
+        let (start, end) = match item {
+            Item::Import(import) => (import.start, import.end),
+            // 模块声明的片段只有模块名
+            Item::Module(name) => {
+                let start = self.source[..name.start].rfind("module").unwrap_or(0);
+                (start, name.end)
+            }
+            Item::Sugar(sugar) => (sugar.start, sugar.end),
+            Item::Declaration(declaration) => (declaration.start, declaration.end),
+            Item::Rule(rule) => (rule.start, rule.end),
+            Item::Net(net) => (net.start, net.end),
+            // 语句与输出的片段不含开头的 `::` 与 `$ =`
+            Item::Statement(statement) => {
+                let start = self.source[..statement.start].rfind("::").unwrap_or(0);
+                (start, statement.end)
+            }
+            Item::Output(output) => {
+                let span = output.span();
+                let start = self.source[..span.start].rfind('$').unwrap_or(0);
+                (start, span.end)
+            }
+        };
+        (start, self.content_end(start, end))
+    }
+
+    fn module(&self, module: &ast::Module) -> String {
+        let mut items = module
+            .imports
+            .iter()
+            .map(Item::Import)
+            .chain(module.modules.iter().map(Item::Module))
+            .chain(module.sugars.iter().map(Item::Sugar))
+            .chain(module.declarations.iter().map(Item::Declaration))
+            .chain(module.rules.iter().map(Item::Rule))
+            .chain(module.nets.iter().map(Item::Net))
+            .chain(module.statements.iter().map(Item::Statement))
+            .chain(module.outputs.iter().map(Item::Output))
+            .map(|item| (self.item_span(&item), item))
+            .collect::<Vec<_>>();
+        items.sort_by_key(|((start, _), _)| *start);
+
+        // 相邻的规则之间没有空行与注释时构成一组，组内对齐
+        let gaps = items
+            .iter()
+            .enumerate()
+            .map(|(i, ((start, _), _))| {
+                let previous = i.checked_sub(1).map(|i| items[i].0 .1);
+                self.gap(previous.unwrap_or(0), *start, previous.is_some())
+            })
+            .collect::<Vec<_>>();
+        let mut widths = vec![(0, 0); items.len()];
+        let mut group_start = 0;
+        for i in 0..=items.len() {
+            let continues = i < items.len()
+                && i > 0
+                && self.alignable(&items[i].1)
+                && self.alignable(&items[i - 1].1)
+                && gaps[i].comments.is_empty()
+                && !gaps[i].blank_after;
+            if continues {
+                continue;
+            }
+            let heads = items[group_start..i]
+                .iter()
+                .filter_map(|(_, item)| match item {
+                    Item::Rule(rule) if self.alignable(item) => Some(self.rule_head(rule)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            let group_widths = heads.iter().fold((0, 0), |(w0, w1), (left, _, right)| {
+                (w0.max(left.chars().count()), w1.max(right.chars().count()))
+            });
+            widths[group_start..i].fill(group_widths);
+            group_start = i;
+        }
+
+        let mut lines = Lines::new();
+        for (i, (((start, end), item), gap)) in items.iter().zip(&gaps).enumerate() {
+            lines.gap(gap, "");
+            let verbatim = || vec![self.source[*start..*end].to_string()];
+            let formatted = match item {
+                Item::Import(import) => {
+                    if self.has_comment(*start, *end) {
+                        verbatim()
+                    } else {
+                        vec![import.to_string()]
+                    }
+                }
+                Item::Module(name) => {
+                    if self.has_comment(*start, *end) {
+                        verbatim()
+                    } else {
+                        vec![format!("module {}", name)]
+                    }
+                }
+                Item::Sugar(sugar) => {
+                    if self.has_comment(*start, *end) {
+                        verbatim()
+                    } else {
+                        vec![sugar.to_string()]
+                    }
+                }
+                Item::Declaration(declaration) => {
+                    if self.has_comment(*start, *end) {
+                        verbatim()
+                    } else {
+                        vec![declaration.to_string()]
+                    }
+                }
+                Item::Rule(rule) => self.rule(rule, *end, widths[i]).unwrap_or_else(verbatim),
+                Item::Net(net) => self.net(net, *end).unwrap_or_else(verbatim),
+                Item::Statement(statement) => {
+                    if self.has_comment(*start, *end) {
+                        verbatim()
+                    } else {
+                        vec![format!(":: {}", self.equation(statement))]
+                    }
+                }
+                Item::Output(output) => {
+                    if self.has_comment(*start, *end) {
+                        verbatim()
+                    } else {
+                        vec![format!("$ = {}", output)]
+                    }
+                }
+            };
+            for line in formatted {
+                lines.push(line);
+            }
+        }
+        let end = items.last().map(|((_, end), _)| *end).unwrap_or(0);
+        let mut trailing = self.gap(end, self.source.len(), !items.is_empty());
+        trailing.blank_after = false;
+        lines.gap(&trailing, "");
+
+        let mut output = lines.lines.join("\n");
+        output.push('\n');
+        output
+    }
+
+    /// 可以与相邻的规则对齐，即两个项中没有注释的规则
+    fn alignable(&self, item: &Item) -> bool {
+        match item {
+            Item::Rule(rule) => {
+                let pair = &rule.term_pair;
+                !self.has_comment(rule.start, self.content_end(pair.start, pair.end))
+            }
+            _ => false,
+        }
+    }
+
+    /// 方程，保留原来的方向
+    fn equation(&self, equation: &ast::Equation) -> String {
+        if equation.left.span().start > equation.right.span().start {
+            format!("{} <- {}", equation.right, equation.left)
+        } else {
+            format!("{} -> {}", equation.left, equation.right)
+        }
+    }
+
+    /// 规则的两个项与其间的符号，按源代码中的顺序
+    fn rule_head(&self, rule: &ast::Rule) -> (String, &'static str, String) {
+        let pair = &rule.term_pair;
+        if pair.left.start > pair.right.start {
+            (pair.right.to_string(), "<<", pair.left.to_string())
+        } else {
+            (pair.left.to_string(), ">>", pair.right.to_string())
+        }
+    }
+
+    fn rule(
+        &self,
+        rule: &Span<ast::Rule>,
+        end: usize,
+        widths: (usize, usize),
+    ) -> Option<Vec<String>> {
+        let pair = &rule.term_pair;
+        let head_end = self.content_end(pair.start, pair.end);
+        if self.has_comment(rule.start, head_end) {
+            return None;
+        }
+        let (left, op, right) = self.rule_head(rule);
+        let head = format!(
+            "{:w0$} {} {:w1$} =>",
+            left,
+            op,
+            right,
+            w0 = widths.0,
+            w1 = widths.1
+        );
+        self.equations(head, &rule.equations, head_end, end)
+    }
+
+    fn net(&self, net: &Span<ast::Net>, end: usize) -> Option<Vec<String>> {
+        let interfaces_end = net
+            .interfaces
+            .last()
+            .map(|interface| self.content_end(interface.span().start, interface.span().end))
+            .unwrap_or(net.name.end);
+        let head_end = interfaces_end + self.source[interfaces_end..].find("|>")? + 2;
+        if self.has_comment(net.start, head_end) {
+            return None;
+        }
+        let interfaces = net
+            .interfaces
+            .iter()
+            .map(|interface| interface.to_string())
+            .collect::<Vec<_>>();
+        let head = format!("{} <| {} |>", net.name, interfaces.join(", "));
+        self.equations(head, &net.equations, head_end, end)
+    }
+
+    /// 规则或网络的方程部分，`head_end` 之后是 `=>` 或 `|>` 之后的方程
+    fn equations(
+        &self,
+        head: String,
+        equations: &[Span<ast::Equation>],
+        head_end: usize,
+        end: usize,
+    ) -> Option<Vec<String>> {
+        if equations.is_empty() {
+            return Some(vec![format!("{} _", head)]);
+        }
+
+        let mut formatted = vec![];
+        let mut gaps = vec![];
+        let mut previous = head_end;
+        for equation in equations {
+            let equation_end = self.content_end(equation.start, equation.end);
+            if self.has_comment(equation.start, equation_end) {
+                return None;
+            }
+            gaps.push(self.gap(previous, equation.start, true));
+            formatted.push((equation, self.equation(equation)));
+            previous = equation_end;
+        }
+        // 最后一个方程之后的注释属于项之间的空隙，这里只需去掉开头的空行
+        gaps[0].blank_after = false;
+
+        let has_comments = gaps.iter().any(|gap| !gap.comments.is_empty());
+        let single = format!(
+            "{} {}",
+            head,
+            formatted
+                .iter()
+                .map(|(_, equation)| equation.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let multiline = has_comments
+            || self.source[head_end..end].contains('\n')
+            || single.chars().count() > MAX_WIDTH;
+        if !multiline {
+            return Some(vec![single]);
+        }
+
+        let mut lines = Lines::new();
+        lines.push(head);
+        for (gap, (equation, line)) in gaps.iter().zip(formatted) {
+            lines.gap(gap, "    ");
+            let line = format!("    {};", line);
+            if line.chars().count() > MAX_WIDTH {
+                if let Some(broken) = self.broken_equation(equation) {
+                    lines.push(broken);
+                    continue;
+                }
+            }
+            lines.push(line);
+        }
+        Some(lines.lines)
+    }
+
+    /// 过长的方程：将一侧交互器的参数分别写在单独的行上
+    fn broken_equation(&self, equation: &ast::Equation) -> Option<String> {
+        let (first, op, second) = if equation.left.span().start > equation.right.span().start {
+            (&equation.right, "<-", &equation.left)
+        } else {
+            (&equation.left, "->", &equation.right)
+        };
+        let arguments = |term: &ast::Term| match term {
+            ast::Term::Agent(agent) if !agent.body.is_empty() => Some(format!(
+                "{}(\n{}\n    )",
+                agent.name,
+                agent
+                    .body
+                    .iter()
+                    .map(|term| format!("        {}", term))
+                    .collect::<Vec<_>>()
+                    .join(",\n")
+            )),
+            _ => None,
+        };
+        match arguments(second) {
+            Some(second) => Some(format!("    {} {} {};", first, op, second)),
+            None => Some(format!("    {} {} {};", arguments(first)?, op, second)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 格式化 `source`，并检查再次格式化时保持不变
+    fn formatted(source: &str) -> String {
+        let once = format(source, "test.zz").unwrap();
+        let twice = format(&once, "test.zz").unwrap();
+        assert_eq!(once, twice, "formatting is not idempotent");
+        once
+    }
+
+    #[test]
+    fn idempotent() {
+        let source = "S(#x)>>Add(#y,@w)=>#x->Add(#y,@z),@w<-S(#z)
+Zero >> Add(#x, @y) => #x -> @y
+
+
+
+/* Main */
+Main <| #r |> S(Zero) -> Add(S(Zero), @r)
+";
+        assert_eq!(
+            formatted(source),
+            "S(#x) >> Add(#y, @w) => #x -> Add(#y, @z), @w <- S(#z)
+Zero  >> Add(#x, @y) => #x -> @y
+
+/* Main */
+Main <| #r |> S(Zero) -> Add(S(Zero), @r)
+"
+        );
+        for example in [
+            include_str!("../../examples/simple.zz"),
+            include_str!("../../examples/unary.zz"),
+            include_str!("../../examples/qsort.zz"),
+        ] {
+            formatted(example);
+        }
+    }
+
+    #[test]
+    fn comment_after_arrow() {
+        let source = "Zero >> Add(#x, @y) => /* inner */ #x -> @y\n";
+        assert_eq!(
+            formatted(source),
+            "Zero >> Add(#x, @y) => /* inner */\n    #x -> @y;\n"
+        );
+    }
+
+    #[test]
+    fn long_right_hand_side() {
+        let source = "Main <| #r |> S(S(S(S(S(S(S(S(S(S(Zero)))))))))) -> Add(S(S(S(S(S(S(S(S(Zero)))))))), @a), #a -> Add(S(Zero), @r)\n";
+        let result = formatted(source);
+        assert_eq!(
+            result,
+            "Main <| #r |>
+    S(S(S(S(S(S(S(S(S(S(Zero)))))))))) -> Add(S(S(S(S(S(S(S(S(Zero)))))))), @a);
+    #a -> Add(S(Zero), @r);
+"
+        );
+        assert!(result.lines().all(|line| line.chars().count() <= MAX_WIDTH));
+    }
+}
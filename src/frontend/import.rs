@@ -0,0 +1,305 @@
+//! 导入其他源文件。
+//!
+//! `import "lists.zz"` 先相对于导入它的文件所在的目录查找，再依次在导入路径中查找。
+//! 每个文件只加载一次，循环导入是错误。
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use annotate_snippets::snippet::AnnotationType;
+use thiserror::Error;
+use typed_arena::Arena;
+
+use crate::diagnostic::{Diagnostic, Diagnostics, Severity};
+use crate::frontend::{ast, check::render_snippet, parser};
+use crate::utils::Span;
+
+/// 导入错误。
+#[derive(Error, Debug)]
+#[allow(missing_docs)]
+pub enum ImportError<'a> {
+    #[error("cannot find imported file")]
+    NotFound { path: &'a Span<'a, &'a str> },
+
+    #[error("cannot read imported file")]
+    Unreadable {
+        path: &'a Span<'a, &'a str>,
+        error: std::io::Error,
+    },
+
+    #[error("import cycle")]
+    Cycle {
+        path: &'a Span<'a, &'a str>,
+        cycle: Vec<String>,
+    },
+}
+
+impl<'a> ImportError<'a> {
+    /// 诊断代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImportError::NotFound { .. } => "import-not-found",
+            ImportError::Unreadable { .. } => "import-unreadable",
+            ImportError::Cycle { .. } => "import-cycle",
+        }
+    }
+
+    fn spans(&self) -> Vec<(Span<'a, ()>, String)> {
+        match self {
+            ImportError::NotFound { path } => vec![(
+                path.as_unit(),
+                "not found next to this file or in the import paths".to_string(),
+            )],
+            ImportError::Unreadable { path, error } => vec![(path.as_unit(), error.to_string())],
+            ImportError::Cycle { path, cycle } => vec![(
+                path.as_unit(),
+                format!("imports itself through {}", cycle.join(" -> ")),
+            )],
+        }
+    }
+
+    /// 将错误转换为可供显示的字符串。
+    pub fn to_snippet(&self) -> String {
+        render_snippet(&self.to_string(), AnnotationType::Error, &self.spans())
+    }
+
+    /// 将错误转换为结构化的诊断信息。
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(
+            self.code(),
+            Severity::Error,
+            self.to_string(),
+            self.spans(),
+            self.to_snippet(),
+        )
+    }
+}
+
+/// 加载的源文件
+#[derive(Clone, Debug)]
+pub struct SourceFile {
+    /// 显示的文件名，被导入的文件为导入路径与所在目录拼接的结果
+    pub filename: String,
+    /// 源代码
+    pub source: String,
+    /// 规范化的路径，不在磁盘上的文件（例如标准输入）没有路径
+    pub path: Option<PathBuf>,
+    /// 是否是被导入的文件，被导入文件的入口网络会被忽略
+    pub imported: bool,
+}
+
+/// 加载的全部源文件
+///
+/// 语法树借用这里保存的源代码，因此它需要比语法树活得更久。
+#[derive(Default)]
+pub struct Sources(Arena<SourceFile>);
+
+impl std::fmt::Debug for Sources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("Sources").field(&self.0.len()).finish()
+    }
+}
+
+/// 加载并解析源文件及其递归导入的全部文件，被导入的文件排在导入它的文件之前
+///
+/// 每个文件只解析一次，被导入的文件去掉入口网络。
+pub fn load<'s>(
+    filename: &str,
+    source: &str,
+    import_paths: &[PathBuf],
+    sources: &'s Sources,
+) -> Result<Vec<(&'s SourceFile, ast::Module<'s>)>, Diagnostics> {
+    let path = std::fs::canonicalize(filename).ok();
+    let mut loader = Loader {
+        import_paths,
+        sources,
+        files: vec![],
+        indices: path.iter().map(|path| (path.clone(), 0)).collect(),
+        stack: vec![],
+        order: vec![],
+        errors: vec![],
+    };
+    loader.add(SourceFile {
+        filename: filename.to_string(),
+        source: source.to_string(),
+        path,
+        imported: false,
+    });
+
+    if !loader.errors.is_empty() {
+        return Err(Diagnostics(loader.errors));
+    }
+    let mut files = loader.files;
+    Ok(loader
+        .order
+        .iter()
+        .map(|&index| {
+            let (file, module) = &mut files[index];
+            (*file, module.take().unwrap())
+        })
+        .collect())
+}
+
+struct Loader<'s, 'p> {
+    import_paths: &'p [PathBuf],
+    sources: &'s Sources,
+    /// 加载的文件及其语法树，有语法错误的文件没有语法树
+    files: Vec<(&'s SourceFile, Option<ast::Module<'s>>)>,
+    /// 规范化的路径对应的文件
+    indices: HashMap<PathBuf, usize>,
+    /// 正在加载的文件，用于发现循环导入
+    stack: Vec<usize>,
+    /// 加载完成的顺序
+    order: Vec<usize>,
+    errors: Vec<Diagnostic>,
+}
+
+impl<'s, 'p> Loader<'s, 'p> {
+    /// 解析文件并加载它导入的文件
+    fn add(&mut self, file: SourceFile) {
+        let file: &'s SourceFile = self.sources.0.alloc(file);
+        let module = match parser::parse(&file.source, &file.filename) {
+            Ok(module) if file.imported => module.into_inner().into_library(),
+            Ok(module) => module.into_inner(),
+            Err(errors) => {
+                self.errors
+                    .extend(errors.iter().map(|error| error.to_diagnostic()));
+                self.files.push((file, None));
+                return;
+            }
+        };
+        let imports = module
+            .imports
+            .iter()
+            .map(|import| import.path.clone())
+            .collect::<Vec<_>>();
+        let index = self.files.len();
+        self.files.push((file, Some(module)));
+
+        self.stack.push(index);
+        for import in imports {
+            let Some((filename, path)) = self.resolve(&file.filename, &import) else {
+                self.error(ImportError::NotFound { path: &import });
+                continue;
+            };
+
+            if let Some(&imported) = self.indices.get(&path) {
+                if let Some(position) = self.stack.iter().position(|&i| i == imported) {
+                    let cycle = self.stack[position..]
+                        .iter()
+                        .chain([&imported])
+                        .map(|&i| self.files[i].0.filename.clone())
+                        .collect::<Vec<_>>();
+                    self.error(ImportError::Cycle {
+                        path: &import,
+                        cycle,
+                    });
+                }
+                continue;
+            }
+
+            let source = match std::fs::read_to_string(&path) {
+                Ok(source) => source,
+                Err(error) => {
+                    self.error(ImportError::Unreadable {
+                        path: &import,
+                        error,
+                    });
+                    continue;
+                }
+            };
+            self.indices.insert(path.clone(), self.files.len());
+            self.add(SourceFile {
+                filename,
+                source,
+                path: Some(path),
+                imported: true,
+            });
+        }
+        self.stack.pop();
+        self.order.push(index);
+    }
+
+    /// 查找被导入的文件，返回显示的文件名与规范化的路径
+    fn resolve(&self, importer: &str, import: &str) -> Option<(String, PathBuf)> {
+        let dir = Path::new(importer).parent().unwrap_or(Path::new(""));
+        std::iter::once(dir)
+            .chain(self.import_paths.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(import))
+            .find(|candidate| candidate.is_file())
+            .and_then(|candidate| {
+                let path = std::fs::canonicalize(&candidate).ok()?;
+                Some((candidate.display().to_string(), path))
+            })
+    }
+
+    fn error(&mut self, error: ImportError) {
+        self.errors.push(error.to_diagnostic());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在临时目录中写入文件，返回目录
+    fn files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("zamuza-import-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (filename, source) in files {
+            std::fs::write(dir.join(filename), source).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn imported_files_come_first() {
+        let dir = files(
+            "order",
+            &[
+                (
+                    "a.zz",
+                    "import \"b.zz\"\nS(#x) >> Inc(@y) => @y <- S(S(#x))\n",
+                ),
+                (
+                    "b.zz",
+                    "Zero >> Inc(@y) => @y <- S(Zero)\nMain <| #r |> Zero -> Inc(@r)\n",
+                ),
+            ],
+        );
+        let main = dir.join("main.zz");
+        let source = "import \"a.zz\"\nimport \"b.zz\"\nMain <| #r |> S(Zero) -> Inc(@r)\n";
+        let sources = Sources::default();
+        let loaded = load(&main.display().to_string(), source, &[], &sources).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names = loaded
+            .iter()
+            .map(|(file, _)| Path::new(&file.filename).file_name().unwrap().to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["b.zz", "a.zz", "main.zz"]);
+        // 被导入的文件去掉了入口网络
+        assert!(loaded[0].1.nets.is_empty());
+        assert_eq!(loaded[1].1.rules.len(), 1);
+        assert_eq!(loaded[2].1.nets.len(), 1);
+    }
+
+    #[test]
+    fn cycle() {
+        let dir = files(
+            "cycle",
+            &[("a.zz", "import \"b.zz\"\n"), ("b.zz", "import \"a.zz\"\n")],
+        );
+        let a = dir.join("a.zz");
+        let sources = Sources::default();
+        let result = load(&a.display().to_string(), "import \"b.zz\"\n", &[], &sources);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let Err(Diagnostics(errors)) = result else {
+            panic!("the cycle is not reported");
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, "import-cycle");
+    }
+}
@@ -2,8 +2,14 @@
 
 pub mod ast;
 pub mod check;
+pub mod flow;
+pub mod format;
+pub mod import;
+pub mod namespace;
 pub mod parser;
+pub mod pattern;
 pub mod semantic;
+pub mod sugar;
 
 /// 变量名称
 #[derive(Debug, Clone, PartialEq)]
@@ -0,0 +1,239 @@
+//! 模块与命名空间。
+//!
+//! 带有模块声明 `module list` 的文件中，文件声明的交互器以及文件中规则左部的两个交互器属于模块 `list`，
+//! 在其他文件中以 `list::Cons` 的形式引用。文件中的其他交互器，例如只在规则右部或网络中出现的交互器，
+//! 仍然是全局的名称；没有模块声明的文件中的交互器都是全局的。
+//! 检查与构建之前，先把交互器名称统一改写为完整的名称。
+
+use std::collections::HashSet;
+
+use crate::{
+    frontend::{ast, semantic},
+    utils::Span,
+};
+
+/// 将每个模块中属于该模块的交互器改写为 `模块::名称`，改写后的名称保存在 `names` 中
+pub fn resolve<'a>(
+    mut modules: Vec<ast::Module<'a>>,
+    names: &'a ast::GeneratedNames,
+) -> Vec<ast::Module<'a>> {
+    for module in &mut modules {
+        let Some(namespace) = namespace(module) else {
+            continue;
+        };
+        let owned = owned(module);
+        for_each_agent_mut(module, &mut |agent| {
+            if owned.contains(**agent) {
+                **agent = names.alloc(format!("{}::{}", namespace, agent));
+            }
+        });
+    }
+    modules
+}
+
+fn namespace<'a>(module: &ast::Module<'a>) -> Option<&'a str> {
+    module.modules.first().map(|name| *name.as_ref())
+}
+
+/// 属于模块的交互器：声明的交互器与规则左部的两个交互器，不含内置交互器与限定名称
+fn owned<'a>(module: &ast::Module<'a>) -> HashSet<&'a str> {
+    let declared = module
+        .declarations
+        .iter()
+        .map(|declaration| *declaration.agent.as_ref());
+    let matched = module.rules.iter().flat_map(|rule| {
+        [
+            *rule.term_pair.left.agent.as_ref(),
+            *rule.term_pair.right.agent.as_ref(),
+        ]
+    });
+    declared
+        .chain(matched)
+        .filter(|agent| !agent.contains("::") && !semantic::is_builtin(agent))
+        .collect()
+}
+
+/// 源代码中是否写的是带模块名的限定名称
+pub(crate) fn is_qualified(agent: &Span<&str>) -> bool {
+    agent.source[agent.start..agent.end].contains("::")
+}
+
+/// 程序中交互器名称的全部出现，包括语法糖指令中的交互器，不含网络名
+pub(crate) fn agent_names<'a>(module: &'a ast::Module) -> Vec<&'a Span<'a, &'a str>> {
+    fn term<'a>(t: &'a ast::Term, names: &mut Vec<&'a Span<'a, &'a str>>) {
+        match t {
+            ast::Term::Agent(agent) => {
+                names.push(&agent.name);
+                for t in &agent.body {
+                    term(t, names);
+                }
+            }
+            ast::Term::Literal(literal) => {
+                for t in literal.terms() {
+                    term(t, names);
+                }
+            }
+            ast::Term::Name(_) | ast::Term::Int(_) => {}
+        }
+    }
+    fn rule_term<'a>(t: &'a ast::RuleTerm, names: &mut Vec<&'a Span<'a, &'a str>>) {
+        names.push(&t.agent);
+        for pattern in &t.body {
+            if let ast::Pattern::Agent(t) = pattern {
+                rule_term(t, names);
+            }
+        }
+    }
+    fn equation<'a>(equation: &'a ast::Equation, names: &mut Vec<&'a Span<'a, &'a str>>) {
+        term(&equation.left, names);
+        term(&equation.right, names);
+    }
+
+    let mut names = vec![];
+    for sugar in &module.sugars {
+        names.extend(&sugar.agents);
+    }
+    for declaration in &module.declarations {
+        names.push(&declaration.agent);
+    }
+    for rule in &module.rules {
+        rule_term(&rule.term_pair.left, &mut names);
+        rule_term(&rule.term_pair.right, &mut names);
+        for e in &rule.equations {
+            equation(e, &mut names);
+        }
+    }
+    for net in &module.nets {
+        for t in &net.interfaces {
+            term(t, &mut names);
+        }
+        for e in &net.equations {
+            equation(e, &mut names);
+        }
+    }
+    for e in &module.statements {
+        equation(e, &mut names);
+    }
+    for t in &module.outputs {
+        term(t, &mut names);
+    }
+    names
+}
+
+fn for_each_agent_mut<'a>(
+    module: &mut ast::Module<'a>,
+    f: &mut impl FnMut(&mut Span<'a, &'a str>),
+) {
+    fn term<'a>(t: &mut ast::Term<'a>, f: &mut impl FnMut(&mut Span<'a, &'a str>)) {
+        match t {
+            ast::Term::Agent(agent) => {
+                f(&mut agent.name);
+                for t in &mut agent.body {
+                    term(t, f);
+                }
+            }
+            ast::Term::Literal(literal) => {
+                for t in literal.terms_mut() {
+                    term(t, f);
+                }
+            }
+            ast::Term::Name(_) | ast::Term::Int(_) => {}
+        }
+    }
+    fn rule_term<'a>(t: &mut ast::RuleTerm<'a>, f: &mut impl FnMut(&mut Span<'a, &'a str>)) {
+        f(&mut t.agent);
+        for pattern in &mut t.body {
+            if let ast::Pattern::Agent(t) = pattern {
+                rule_term(t, f);
+            }
+        }
+    }
+    fn equation<'a>(equation: &mut ast::Equation<'a>, f: &mut impl FnMut(&mut Span<'a, &'a str>)) {
+        term(&mut equation.left, f);
+        term(&mut equation.right, f);
+    }
+
+    for sugar in &mut module.sugars {
+        for agent in &mut sugar.agents {
+            f(agent);
+        }
+    }
+    for declaration in &mut module.declarations {
+        f(&mut declaration.agent);
+    }
+    for rule in &mut module.rules {
+        rule_term(&mut rule.term_pair.left, f);
+        rule_term(&mut rule.term_pair.right, f);
+        for e in &mut rule.equations {
+            equation(e, f);
+        }
+    }
+    for net in &mut module.nets {
+        for t in &mut net.interfaces {
+            term(t, f);
+        }
+        for e in &mut net.equations {
+            equation(e, f);
+        }
+    }
+    for e in &mut module.statements {
+        equation(e, f);
+    }
+    for t in &mut module.outputs {
+        term(t, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::parser;
+
+    /// 解析并改写 `source`，返回改写后全部交互器名称的出现
+    fn resolved(source: &str) -> Vec<String> {
+        let names = ast::GeneratedNames::default();
+        let module = parser::parse(source, "test.zz").unwrap().into_inner();
+        let modules = resolve(vec![module], &names);
+        agent_names(&modules[0])
+            .iter()
+            .map(|agent| agent.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn declared_and_matched_agents_are_qualified() {
+        let source = "module list
+agent Nil
+Append(#ys, @r) << Nil => @r <- #ys
+Append(#ys, @r) << Cons(#x, #xs) => @r <- Pair(#x, #t), #ys -> Dup(@a, @b), Append(#a, @t) <- #xs, #b -> Erase
+";
+        assert_eq!(
+            resolved(source),
+            [
+                "list::Nil",
+                "list::Nil",
+                "list::Append",
+                "list::Cons",
+                "list::Append",
+                "Pair",
+                "Dup",
+                "list::Append",
+                "Erase",
+            ]
+        );
+    }
+
+    #[test]
+    fn qualified_names_are_kept() {
+        let source = "module main
+Go(@r) >> other::Run => @r <- other::Done
+";
+        assert_eq!(resolved(source), ["main::Go", "other::Run", "other::Done"]);
+    }
+
+    #[test]
+    fn files_without_module_are_global() {
+        let source = "agent Nil\nS(#x) >> Inc(@y) => @y <- S(S(#x))\n";
+        assert_eq!(resolved(source), ["Nil", "S", "Inc", "S", "S"]);
+    }
+}
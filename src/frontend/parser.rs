@@ -1,10 +1,11 @@
 //! 语法解析器。
 
-use crate::{frontend::ast, utils::Span};
-use annotate_snippets::{
-    display_list::{DisplayList, FormatOptions},
-    snippet::{Annotation, AnnotationType, Snippet},
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    frontend::{ast, check::render_snippet},
+    utils::Span,
 };
+use annotate_snippets::snippet::AnnotationType;
 use anyhow::Result;
 use pest::{iterators::Pair, Parser};
 
@@ -19,35 +20,174 @@ mod grammar {
 use grammar::{Rule, ZamuzaParser};
 
 /// 从文本生成抽象语法树
-pub fn parse<'a>(source: &'a str, filename: &'a str) -> Result<Span<'a, ast::Module<'a>>, String> {
-    let mut parsed = ZamuzaParser::parse(Rule::Program, source).map_err(|err| {
-        let (start, end) = match err.location {
-            pest::error::InputLocation::Pos(pos) => (pos, pos),
-            pest::error::InputLocation::Span(span) => span,
-        };
-        let lines = Span::new((), filename, source, start, end)
-            .lines()
-            .unwrap_or_default();
-        let message = err.variant.message();
-
-        let snippet = Snippet {
-            title: Some(Annotation {
-                id: None,
-                label: Some("syntax error"),
-                annotation_type: AnnotationType::Error,
-            }),
-            footer: vec![],
-            slices: vec![lines.as_annotation(&message, AnnotationType::Error)],
-            opt: FormatOptions {
-                color: true,
-                ..Default::default()
-            },
+///
+/// 出现语法错误时，在规则与网络的边界处恢复，尽可能多地报告错误。
+pub fn parse<'a>(
+    source: &'a str,
+    filename: &'a str,
+) -> Result<Span<'a, ast::Module<'a>>, Vec<SyntaxError<'a>>> {
+    let mut parsed = match ZamuzaParser::parse(Rule::Program, source) {
+        Ok(parsed) => parsed,
+        Err(err) => return Err(recover(source, filename, err)),
+    };
+
+    let errors = check_integers(source, filename, parsed.clone(), 0);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(ModuleParser { filename, source }.parse_module(parsed.next().unwrap()))
+}
+
+/// 将源代码按顶级项切分后逐段解析，收集每一段中的错误
+fn recover<'a>(
+    source: &'a str,
+    filename: &'a str,
+    err: pest::error::Error<Rule>,
+) -> Vec<SyntaxError<'a>> {
+    let mut errors = vec![];
+    let starts = item_starts(source);
+    let ends = starts.iter().skip(1).copied().chain([source.len()]);
+    for (start, end) in starts.iter().copied().zip(ends) {
+        match ZamuzaParser::parse(Rule::Items, &source[start..end]) {
+            Ok(parsed) => errors.extend(check_integers(source, filename, parsed, start)),
+            Err(err) => errors.push(pest_error(source, filename, err, start, end)),
+        }
+    }
+
+    // 切分不一定准确，至少报告整体解析的错误
+    if errors.is_empty() {
+        errors.push(pest_error(source, filename, err, 0, source.len()));
+    }
+    errors
+}
+
+/// 可能是顶级项开头的位置：不在注释中、没有缩进，并且看起来像是一条导入、模块声明、语法糖指令、交互器声明、规则、网络或顶级语句的行首
+fn item_starts(source: &str) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut in_comment = false;
+    let mut offset = 0;
+    for line in source.split_inclusive('\n') {
+        let at_item = !in_comment && {
+            let content = line.trim_end();
+            let head = content
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next();
+            content.starts_with("::")
+                || content.starts_with('$')
+                || head == Some("agent")
+                || head == Some("import")
+                || head == Some("module")
+                || head == Some("private")
+                || head == Some("sugar")
+                || content.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                    && [">>", "<<", "<|"].iter().any(|op| content.contains(op))
         };
+        if at_item && offset > 0 {
+            starts.push(offset);
+        }
+
+        let mut rest = line;
+        loop {
+            let token = if in_comment { "*/" } else { "/*" };
+            match rest.find(token) {
+                Some(i) => {
+                    in_comment = !in_comment;
+                    rest = &rest[i + 2..];
+                }
+                None => break,
+            }
+        }
+        offset += line.len();
+    }
+    starts
+}
 
-        DisplayList::from(snippet).to_string()
-    })?;
+/// 整数字面量的范围无法在语法中表达，在此单独检查
+fn check_integers<'a>(
+    source: &'a str,
+    filename: &'a str,
+    parsed: pest::iterators::Pairs<Rule>,
+    offset: usize,
+) -> Vec<SyntaxError<'a>> {
+    parsed
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::Integer && pair.as_str().parse::<i64>().is_err())
+        .map(|pair| {
+            let span = pair.as_span();
+            let message = "integer literal out of range".to_string();
+            SyntaxError::new(
+                filename,
+                source,
+                offset + span.start(),
+                offset + span.end(),
+                message,
+            )
+        })
+        .collect()
+}
 
-    Ok(ModuleParser { filename, source }.parse_module(parsed.next().unwrap()))
+/// `offset` 与 `end` 是被解析的片段在源代码中的范围
+fn pest_error<'a>(
+    source: &'a str,
+    filename: &'a str,
+    err: pest::error::Error<Rule>,
+    offset: usize,
+    end: usize,
+) -> SyntaxError<'a> {
+    let (start, stop) = match err.location {
+        pest::error::InputLocation::Pos(pos) => (pos, pos),
+        pest::error::InputLocation::Span(span) => span,
+    };
+    // 片段末尾的错误指向最后一个词之后，而不是下一个顶级项的开头
+    let last = offset + source[offset..end].trim_end().len();
+    let start = (offset + start).min(last);
+    let stop = (offset + stop).min(last).max(start);
+    SyntaxError::new(
+        filename,
+        source,
+        start,
+        stop,
+        err.variant.message().into_owned(),
+    )
+}
+
+/// 语法错误
+#[derive(Debug, Clone)]
+pub struct SyntaxError<'a> {
+    /// 位置
+    pub span: Span<'a, ()>,
+    /// 信息
+    pub message: String,
+}
+
+impl<'a> SyntaxError<'a> {
+    fn new(filename: &'a str, source: &'a str, start: usize, end: usize, message: String) -> Self {
+        Self {
+            span: Span::new((), filename, source, start, end),
+            message,
+        }
+    }
+
+    /// 将错误转换为可供显示的字符串。
+    pub fn to_snippet(&self) -> String {
+        render_snippet(
+            "syntax error",
+            AnnotationType::Error,
+            &[(self.span.clone(), self.message.clone())],
+        )
+    }
+
+    /// 将错误转换为结构化的诊断信息。
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(
+            "syntax-error",
+            Severity::Error,
+            "syntax error".to_string(),
+            vec![(self.span.clone(), self.message.clone())],
+            self.to_snippet(),
+        )
+    }
 }
 
 #[derive(Copy, Clone)] // 让 self 是 Copy 的，下面少引入一个生命周期（实在是被生命周期搞烦了）
@@ -61,22 +201,99 @@ impl<'a> ModuleParser<'a> {
         let span = module.as_span();
         let pairs = module.into_inner();
 
+        let mut imports = vec![];
+        let mut modules = vec![];
+        let mut sugars = vec![];
+        let mut declarations = vec![];
         let mut rules = vec![];
         let mut nets = vec![];
+        let mut statements = vec![];
+        let mut outputs = vec![];
 
         for pair in pairs {
             match pair.as_rule() {
+                Rule::Import => imports.push(self.parse_import(pair)),
+                Rule::ModuleName => {
+                    modules.push(self.parse_ident(pair.into_inner().nth(1).unwrap()))
+                }
+                Rule::Sugar => sugars.push(self.parse_sugar(pair)),
+                Rule::Declaration => declarations.push(self.parse_declaration(pair)),
                 Rule::Rule => rules.push(self.parse_rule(pair)),
                 Rule::Net => nets.push(self.parse_net(pair)),
+                Rule::Statement => {
+                    statements.push(self.parse_equation(pair.into_inner().next().unwrap()))
+                }
+                Rule::Output => outputs.push(self.parse_term(pair.into_inner().next().unwrap())),
                 Rule::EOI => {}
                 _ => unreachable!(),
             }
         }
 
-        let module = ast::Module { rules, nets };
+        let module = ast::Module {
+            imports,
+            modules,
+            sugars,
+            declarations,
+            rules,
+            nets,
+            statements,
+            outputs,
+        };
         Span::from_pest(module, self.filename, self.source, span)
     }
 
+    fn parse_import(self, import: Pair<'a, Rule>) -> Span<'a, ast::Import<'a>> {
+        let span = import.as_span();
+        let string = import.into_inner().nth(1).unwrap(); // 跳过关键字
+        let quoted = string.as_span();
+        let path = Span::new(
+            &string.as_str()[1..string.as_str().len() - 1],
+            self.filename,
+            self.source,
+            quoted.start() + 1,
+            quoted.end() - 1,
+        );
+
+        Span::from_pest(ast::Import { path }, self.filename, self.source, span)
+    }
+
+    fn parse_sugar(self, sugar: Pair<'a, Rule>) -> Span<'a, ast::Sugar<'a>> {
+        let span = sugar.as_span();
+        let directive = sugar.into_inner().nth(1).unwrap(); // 跳过关键字
+        let kind = match directive.as_rule() {
+            Rule::SugarList => ast::SugarKind::List,
+            Rule::SugarNumber => ast::SugarKind::Number,
+            Rule::SugarTuple => ast::SugarKind::Tuple,
+            _ => unreachable!(),
+        };
+        let agents = directive
+            .into_inner()
+            .map(|p| self.parse_ident(p))
+            .collect::<Vec<_>>();
+
+        let sugar = ast::Sugar { kind, agents };
+        Span::from_pest(sugar, self.filename, self.source, span)
+    }
+
+    fn parse_declaration(self, declaration: Pair<'a, Rule>) -> Span<'a, ast::Declaration<'a>> {
+        let span = declaration.as_span();
+        let mut declaration = declaration.into_inner().peekable();
+
+        let private = declaration
+            .next_if(|p| p.as_rule() == Rule::Private)
+            .is_some();
+        declaration.next(); // 跳过关键字
+        let agent = self.parse_ident(declaration.next().unwrap());
+        let ports = declaration.map(|p| self.parse_name(p)).collect::<Vec<_>>();
+
+        let declaration = ast::Declaration {
+            private,
+            agent,
+            ports,
+        };
+        Span::from_pest(declaration, self.filename, self.source, span)
+    }
+
     fn parse_rule(self, rule: Pair<'a, Rule>) -> Span<'a, ast::Rule<'a>> {
         let span = rule.as_span();
         let mut rule = rule.into_inner();
@@ -126,7 +343,19 @@ impl<'a> ModuleParser<'a> {
         let mut terms = term.into_inner();
         let head = terms.next().unwrap();
         let agent = self.parse_ident(head);
-        let body = terms.map(|p| self.parse_name(p)).collect::<Vec<_>>();
+        let body = terms
+            .map(|p| match p.as_rule() {
+                Rule::Name => ast::Pattern::Name(self.parse_name(p)),
+                Rule::Wildcard => ast::Pattern::Wildcard(Span::from_pest(
+                    (),
+                    self.filename,
+                    self.source,
+                    p.as_span(),
+                )),
+                Rule::RuleTerm => ast::Pattern::Agent(self.parse_rule_term(p)),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
         let term = ast::RuleTerm { agent, body };
         Span::from_pest(term, self.filename, self.source, span)
     }
@@ -203,12 +432,37 @@ impl<'a> ModuleParser<'a> {
         let head = terms.next().unwrap();
         let term = match head.as_rule() {
             Rule::Name => ast::Term::Name(self.parse_name(head)),
+            Rule::Integer => {
+                let value = head.as_str().parse().unwrap(); // 已在 `parse` 中检查
+                ast::Term::Int(Span::from_pest(value, self.filename, self.source, span))
+            }
             Rule::Agent => {
                 let name = self.parse_ident(head);
                 let body = terms.map(|x| self.parse_term(x)).collect::<Vec<_>>();
                 let agent = ast::Agent { name, body };
                 ast::Term::Agent(Span::from_pest(agent, self.filename, self.source, span))
             }
+            Rule::List => {
+                let mut elements = vec![];
+                let mut tail = None;
+                for p in head.into_inner() {
+                    match p.as_rule() {
+                        Rule::Term => elements.push(self.parse_term(p)),
+                        Rule::ListTail => {
+                            let term = self.parse_term(p.into_inner().next().unwrap());
+                            tail = Some(Box::new(term));
+                        }
+                        _ => unreachable!(),
+                    }
+                }
+                let list = ast::Literal::List { elements, tail };
+                ast::Term::Literal(Span::from_pest(list, self.filename, self.source, span))
+            }
+            Rule::Tuple => {
+                let elements = head.into_inner().map(|x| self.parse_term(x)).collect();
+                let tuple = ast::Literal::Tuple(elements);
+                ast::Term::Literal(Span::from_pest(tuple, self.filename, self.source, span))
+            }
             _ => unreachable!(),
         };
         term
@@ -229,7 +483,7 @@ impl<'a> ModuleParser<'a> {
         Span::from_pest(name, self.filename, self.source, span)
     }
 
-    fn parse_ident(self, agent: Pair<'a, Rule>) -> Span<'a, &str> {
+    fn parse_ident(self, agent: Pair<'a, Rule>) -> Span<'a, &'a str> {
         Span::from_pest(agent.as_str(), self.filename, self.source, agent.as_span())
     }
 }
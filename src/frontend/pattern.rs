@@ -0,0 +1,711 @@
+//! 嵌套模式。
+//!
+//! 规则左侧构造函数一侧的交互器体中可以嵌套模式，例如 `Cons(#x, Nil) >> Last(@r)`。
+//! 检查与构建之前，同一文件中同一对交互器的全部规则按模式矩阵编译为只含变量的规则：
+//! 选择一个有嵌套模式的端口，生成的规则把这个端口连接到辅助交互器 `Last/Cons.1` 的主端口，
+//! 其余端口交给辅助交互器，辅助交互器再与这个端口上的各个构造函数分别建立规则。
+//! 这个端口上是变量的规则对每个构造函数各展开一次，并用方程 `@x <- Nil` 重建原来的变量。
+//!
+//! 同一个值被两条规则匹配时报告规则重叠。端口上可能出现的构造函数由程序中
+//! 同一个交互器的同一个端口上出现过的构造函数估计，没有规则匹配其中的构造函数时给出警告。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::ops::Range;
+
+use crate::{
+    frontend::{
+        ast,
+        check::{TypeError, Warning},
+        semantic::Polarity,
+    },
+    utils::Span,
+};
+
+/// 展开所有文件中的嵌套模式，返回每个文件展开后的模块
+///
+/// 可能出现的构造函数根据全部文件估计；生成的名称保存在 `names` 中。
+pub fn desugar<'a>(
+    modules: &'a [ast::Module<'a>],
+    names: &'a ast::GeneratedNames,
+    errors: &mut Vec<TypeError<'a>>,
+    warnings: &mut Vec<Warning<'a>>,
+) -> Vec<ast::Module<'a>> {
+    let mut desugar = Desugar {
+        names,
+        families: HashMap::new(),
+        polarities: HashMap::new(),
+        errors,
+        warnings,
+        rules: vec![],
+        fresh: 0,
+        overlaps: HashSet::new(),
+    };
+    for module in modules {
+        desugar.collect(module);
+    }
+    modules
+        .iter()
+        .map(|module| desugar.module(module))
+        .collect()
+}
+
+/// 是否为展开嵌套模式时生成的辅助交互器，源代码中的名称不会含有 `/`
+pub fn is_auxiliary(agent: &str) -> bool {
+    agent.contains('/')
+}
+
+/// 规则左侧一个端口上尚未匹配的模式
+#[derive(Clone, Debug)]
+enum Cell<'a> {
+    /// 源代码中的模式
+    Source(&'a ast::Pattern<'a>),
+    /// 展开变量或通配符时生成的变量或通配符
+    Fresh(ast::Pattern<'a>),
+}
+
+impl<'a> Cell<'a> {
+    fn pattern(&self) -> &ast::Pattern<'a> {
+        match self {
+            Cell::Source(pattern) => pattern,
+            Cell::Fresh(pattern) => pattern,
+        }
+    }
+
+    fn agent(&self) -> Option<&'a Span<'a, ast::RuleTerm<'a>>> {
+        match self {
+            Cell::Source(ast::Pattern::Agent(term)) => Some(term),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> Option<&Span<'a, ast::Name<'a>>> {
+        match self.pattern() {
+            ast::Pattern::Name(name) => Some(name),
+            ast::Pattern::Wildcard(_) | ast::Pattern::Agent(_) => None,
+        }
+    }
+}
+
+/// 模式矩阵中的一行，来自一条规则
+#[derive(Clone, Debug)]
+struct Row<'a> {
+    /// 规则在分组中的序号
+    index: usize,
+    rule: &'a Span<'a, ast::Rule<'a>>,
+    /// 当前规则左侧各个端口上的模式
+    cells: Vec<Cell<'a>>,
+    /// 展开变量时重建变量的方程
+    equations: Vec<Span<'a, ast::Equation<'a>>>,
+}
+
+/// 已经匹配的部分，用于在诊断信息中展示被匹配的值
+#[derive(Clone, Debug)]
+enum Shape<'a> {
+    /// 当前规则左侧的端口
+    Slot(usize),
+    /// 已经匹配的交互器
+    Agent(&'a str, Vec<Shape<'a>>),
+}
+
+impl<'a> Shape<'a> {
+    fn agent(name: &'a str, slots: Range<usize>) -> Self {
+        Shape::Agent(name, slots.map(Shape::Slot).collect())
+    }
+
+    /// 第 `slot` 个端口匹配到有 `arity` 个端口的 `agent`，它的端口排在其余端口之前
+    fn expand(&self, slot: usize, agent: &'a str, arity: usize) -> Self {
+        match *self {
+            Shape::Slot(i) if i == slot => Shape::agent(agent, 0..arity),
+            Shape::Slot(i) if i < slot => Shape::Slot(i + arity),
+            Shape::Slot(i) => Shape::Slot(i + arity - 1),
+            Shape::Agent(name, ref body) => Shape::Agent(
+                name,
+                body.iter()
+                    .map(|shape| shape.expand(slot, agent, arity))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<'a> Display for Shape<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Shape::Slot(_) => write!(f, "_"),
+            Shape::Agent(name, body) if body.is_empty() => write!(f, "{}", name),
+            Shape::Agent(name, body) => write!(
+                f,
+                "{}({})",
+                name,
+                body.iter()
+                    .map(|shape| shape.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// 编译过程中的一个状态：规则左侧的两个交互器以及可能匹配的规则
+struct State<'a> {
+    constructor: Span<'a, &'a str>,
+    reducer: Span<'a, &'a str>,
+    /// 构造函数的端口数，之后的端口属于规约函数
+    arity: usize,
+    /// 各个端口所属的构造函数及端口序号，规约函数的端口没有
+    origins: Vec<Option<(&'a str, usize)>>,
+    shape: (Shape<'a>, Shape<'a>),
+    rows: Vec<Row<'a>>,
+}
+
+impl<'a> State<'a> {
+    /// 被匹配的值，可以把第 `slot` 个端口替换为交互器
+    fn value(&self, expand: Option<(usize, &'a str, usize)>) -> String {
+        match expand {
+            Some((slot, agent, arity)) => format!(
+                "{} >> {}",
+                self.shape.0.expand(slot, agent, arity),
+                self.shape.1.expand(slot, agent, arity)
+            ),
+            None => format!("{} >> {}", self.shape.0, self.shape.1),
+        }
+    }
+}
+
+/// 可能匹配的构造函数
+struct Constructor<'a> {
+    agent: Span<'a, &'a str>,
+    arity: usize,
+    /// 第一个匹配它的模式，由变量匹配的构造函数没有
+    pattern: Option<&'a Span<'a, ast::RuleTerm<'a>>>,
+}
+
+struct Desugar<'a, 'e> {
+    names: &'a ast::GeneratedNames,
+    /// 程序中每个交互器的每个端口上出现过的构造函数及其端口数
+    families: HashMap<(&'a str, usize), Vec<(&'a str, usize)>>,
+    /// 构造函数各个端口的极性
+    polarities: HashMap<&'a str, Vec<Polarity>>,
+    errors: &'e mut Vec<TypeError<'a>>,
+    warnings: &'e mut Vec<Warning<'a>>,
+    /// 当前分组生成的规则
+    rules: Vec<Span<'a, ast::Rule<'a>>>,
+    /// 当前分组中已经生成的变量数
+    fresh: usize,
+    /// 当前分组中已经报告的重叠规则
+    overlaps: HashSet<(usize, usize)>,
+}
+
+impl<'a, 'e> Desugar<'a, 'e> {
+    fn collect(&mut self, module: &'a ast::Module<'a>) {
+        for declaration in &module.declarations {
+            self.polarities
+                .entry(*declaration.agent)
+                .or_insert_with(|| {
+                    declaration
+                        .ports
+                        .iter()
+                        .map(|p| Polarity::of_name(p))
+                        .collect()
+                });
+        }
+        for rule in &module.rules {
+            self.collect_pattern(&rule.term_pair.left, true);
+            self.collect_pattern(&rule.term_pair.right, false);
+            for equation in &rule.equations {
+                self.collect_term(&equation.left);
+                self.collect_term(&equation.right);
+            }
+        }
+        for net in &module.nets {
+            for term in &net.interfaces {
+                self.collect_term(term);
+            }
+            for equation in &net.equations {
+                self.collect_term(&equation.left);
+                self.collect_term(&equation.right);
+            }
+        }
+        for statement in &module.statements {
+            self.collect_term(&statement.left);
+            self.collect_term(&statement.right);
+        }
+        for output in &module.outputs {
+            self.collect_term(output);
+        }
+    }
+
+    fn collect_pattern(&mut self, term: &'a ast::RuleTerm<'a>, constructor: bool) {
+        if constructor {
+            self.polarities.entry(*term.agent).or_insert_with(|| {
+                term.body
+                    .iter()
+                    .map(|pattern| match pattern {
+                        ast::Pattern::Name(name) => Polarity::of_name(name),
+                        ast::Pattern::Wildcard(_) | ast::Pattern::Agent(_) => Polarity::In,
+                    })
+                    .collect()
+            });
+        }
+        for (port, pattern) in term.body.iter().enumerate() {
+            if let ast::Pattern::Agent(nested) = pattern {
+                self.family(*term.agent, port, *nested.agent, nested.body.len());
+                self.collect_pattern(nested, true);
+            }
+        }
+    }
+
+    fn collect_term(&mut self, term: &'a ast::Term<'a>) {
+        if let ast::Term::Agent(agent) = term {
+            for (port, t) in agent.body.iter().enumerate() {
+                if let ast::Term::Agent(nested) = t {
+                    self.family(*agent.name, port, *nested.name, nested.body.len());
+                }
+                self.collect_term(t);
+            }
+        }
+    }
+
+    fn family(&mut self, parent: &'a str, port: usize, agent: &'a str, arity: usize) {
+        let family = self.families.entry((parent, port)).or_default();
+        if !family.iter().any(|(a, _)| *a == agent) {
+            family.push((agent, arity));
+        }
+    }
+
+    fn module(&mut self, module: &'a ast::Module<'a>) -> ast::Module<'a> {
+        let mut groups: HashMap<(&str, &str), Vec<&Span<ast::Rule>>> = HashMap::new();
+        let mut valid = vec![];
+        for rule in &module.rules {
+            let mut nested = rule.term_pair.right.body.iter().filter_map(|p| match p {
+                ast::Pattern::Agent(pattern) => Some(pattern),
+                ast::Pattern::Name(_) | ast::Pattern::Wildcard(_) => None,
+            });
+            if let Some(pattern) = nested.next() {
+                self.errors.push(TypeError::ReducerPattern { pattern });
+                continue;
+            }
+            let key = (*rule.term_pair.left.agent, *rule.term_pair.right.agent);
+            groups.entry(key).or_default().push(rule);
+            valid.push((key, rule));
+        }
+
+        let mut result = module.clone();
+        result.rules.clear();
+        for (key, rule) in valid {
+            let group = &groups[&key];
+            if group.iter().all(|rule| rule.term_pair.left.is_flat()) {
+                result.rules.push(rule.clone());
+            } else if std::ptr::eq(group[0], rule) {
+                self.group(group);
+                result.rules.append(&mut self.rules);
+            }
+        }
+        result
+    }
+
+    /// 编译同一对交互器的全部规则
+    fn group(&mut self, rules: &[&'a Span<'a, ast::Rule<'a>>]) {
+        let pair = &rules[0].term_pair;
+        let (constructor, reducer) = (&pair.left, &pair.right);
+        let arity = constructor.body.len();
+        let reducer_arity = reducer.body.len();
+
+        let mut rows = vec![];
+        for (index, rule) in rules.iter().enumerate() {
+            let (left, right) = (&rule.term_pair.left, &rule.term_pair.right);
+            let mismatch = [(left, constructor, arity), (right, reducer, reducer_arity)]
+                .into_iter()
+                .find(|(term, _, expected)| term.body.len() != *expected);
+            if let Some((term, first, expected)) = mismatch {
+                self.errors.push(TypeError::ArityMismatch {
+                    name: &term.agent,
+                    expected,
+                    found: term.body.len(),
+                    previous: Some(&first.agent),
+                });
+                continue;
+            }
+            rows.push(Row {
+                index,
+                rule,
+                cells: left
+                    .body
+                    .iter()
+                    .chain(&right.body)
+                    .map(Cell::Source)
+                    .collect(),
+                equations: vec![],
+            });
+        }
+
+        self.fresh = 0;
+        self.overlaps.clear();
+        self.state(State {
+            constructor: constructor.agent.clone(),
+            reducer: reducer.agent.clone(),
+            arity,
+            origins: (0..arity)
+                .map(|port| Some((*constructor.agent, port)))
+                .chain(std::iter::repeat_n(None, reducer_arity))
+                .collect(),
+            shape: (
+                Shape::agent(*constructor.agent, 0..arity),
+                Shape::agent(*reducer.agent, arity..arity + reducer_arity),
+            ),
+            rows,
+        });
+    }
+
+    fn state(&mut self, state: State<'a>) {
+        let slot = (0..state.origins.len()).find(|&slot| {
+            state
+                .rows
+                .iter()
+                .any(|row| row.cells[slot].agent().is_some())
+        });
+        match slot {
+            Some(slot) => self.dispatch(state, slot),
+            None => self.leaf(state),
+        }
+    }
+
+    /// 所有模式都已匹配，第一条规则生效，其余规则与它重叠
+    fn leaf(&mut self, state: State<'a>) {
+        let Some((first, rest)) = state.rows.split_first() else {
+            return;
+        };
+        for row in rest {
+            if self.overlaps.insert((first.index, row.index)) {
+                self.errors.push(TypeError::OverlappingPatterns {
+                    rule: &row.rule.term_pair,
+                    other: &first.rule.term_pair,
+                    value: state.value(None),
+                });
+            }
+        }
+
+        let patterns = first
+            .cells
+            .iter()
+            .map(|cell| cell.pattern().clone())
+            .collect();
+        let equations = first
+            .rule
+            .equations
+            .iter()
+            .chain(&first.equations)
+            .cloned()
+            .collect();
+        self.push_rule(first.rule, &state, patterns, equations);
+    }
+
+    /// 把第 `slot` 个端口交给辅助交互器，再分别匹配其上的各个构造函数
+    fn dispatch(&mut self, state: State<'a>, slot: usize) {
+        let at = state
+            .rows
+            .iter()
+            .find_map(|row| row.cells[slot].agent())
+            .unwrap();
+        let name = format!("{}/{}.{}", *state.reducer, *state.constructor, slot);
+        let aux = at.agent.with(self.names.alloc(name));
+
+        for row in &state.rows {
+            if let Some(name) = row.cells[slot].name() {
+                if let ast::Name::Out(_) = name.as_ref() {
+                    self.errors.push(TypeError::PolarityConflict {
+                        span: name.as_unit(),
+                        found: Polarity::Out,
+                        previous: Some(at.as_unit()),
+                    });
+                }
+            }
+        }
+        let vars: Vec<_> = (0..state.origins.len())
+            .map(|i| {
+                let polarity = match state.rows.iter().find_map(|row| row.cells[i].name()) {
+                    Some(name) if i != slot => Polarity::of_name(name),
+                    _ => Polarity::In,
+                };
+                self.fresh(polarity, at)
+            })
+            .collect();
+        let mut others = vars.clone();
+        let var = others.remove(slot);
+        let equation = at.with(ast::Equation {
+            left: ast::Term::Name(var),
+            right: ast::Term::Agent(at.with(ast::Agent {
+                name: aux.clone(),
+                body: others.into_iter().map(ast::Term::Name).collect(),
+            })),
+        });
+        let patterns = vars.into_iter().map(ast::Pattern::Name).collect();
+        self.push_rule(state.rows[0].rule, &state, patterns, vec![equation]);
+
+        for constructor in self.constructors(&state, slot) {
+            let rows = self.specialize(&state, slot, &constructor);
+            let mut origins = state.origins.clone();
+            origins.remove(slot);
+            origins.splice(
+                0..0,
+                (0..constructor.arity).map(|port| Some((*constructor.agent, port))),
+            );
+            let (agent, arity) = (*constructor.agent, constructor.arity);
+            self.state(State {
+                constructor: constructor.agent,
+                reducer: aux.clone(),
+                arity,
+                origins,
+                shape: (
+                    state.shape.0.expand(slot, agent, arity),
+                    state.shape.1.expand(slot, agent, arity),
+                ),
+                rows,
+            });
+        }
+    }
+
+    /// 第 `slot` 个端口上需要匹配的构造函数，变量匹配程序中这个端口上出现过的全部构造函数
+    fn constructors(&mut self, state: &State<'a>, slot: usize) -> Vec<Constructor<'a>> {
+        let mut constructors: Vec<Constructor> = vec![];
+        for row in &state.rows {
+            if let Some(pattern) = row.cells[slot].agent() {
+                if !constructors.iter().any(|c| *c.agent == *pattern.agent) {
+                    constructors.push(Constructor {
+                        agent: pattern.agent.clone(),
+                        arity: pattern.body.len(),
+                        pattern: Some(pattern),
+                    });
+                }
+            }
+        }
+
+        let family = state.origins[slot]
+            .and_then(|origin| self.families.get(&origin))
+            .into_iter()
+            .flatten()
+            .filter(|(agent, _)| !constructors.iter().any(|c| *c.agent == *agent))
+            .copied()
+            .collect::<Vec<_>>();
+        // 变量与通配符匹配其余的构造函数
+        let variable = state
+            .rows
+            .iter()
+            .map(|row| row.cells[slot].pattern())
+            .find(|pattern| !matches!(pattern, ast::Pattern::Agent(_)));
+        match variable {
+            Some(variable) => {
+                let span = variable.span();
+                constructors.extend(family.into_iter().map(|(agent, arity)| Constructor {
+                    agent: span.with(agent),
+                    arity,
+                    pattern: None,
+                }));
+            }
+            None => {
+                let span = state.rows[0].rule.term_pair.as_unit();
+                for (agent, arity) in family {
+                    self.warnings.push(Warning::NonExhaustivePatterns {
+                        span: span.clone(),
+                        value: state.value(Some((slot, agent, arity))),
+                    });
+                }
+            }
+        }
+        constructors
+    }
+
+    /// 第 `slot` 个端口匹配到 `constructor` 之后仍然可能匹配的规则
+    fn specialize(
+        &mut self,
+        state: &State<'a>,
+        slot: usize,
+        constructor: &Constructor<'a>,
+    ) -> Vec<Row<'a>> {
+        let mut rows = vec![];
+        for row in &state.rows {
+            let mut cells = row.cells.clone();
+            let cell = cells.remove(slot);
+            let mut equations = row.equations.clone();
+            let expanded: Vec<Cell> = match (cell.agent(), cell.pattern()) {
+                (Some(pattern), _) if *pattern.agent == *constructor.agent => {
+                    if pattern.body.len() != constructor.arity {
+                        self.errors.push(TypeError::ArityMismatch {
+                            name: &pattern.agent,
+                            expected: constructor.arity,
+                            found: pattern.body.len(),
+                            previous: constructor.pattern.map(|p| &p.agent),
+                        });
+                        continue;
+                    }
+                    pattern.body.iter().map(Cell::Source).collect()
+                }
+                (Some(_), _) => continue,
+                (None, ast::Pattern::Wildcard(span)) => (0..constructor.arity)
+                    .map(|_| Cell::Fresh(ast::Pattern::Wildcard(span.clone())))
+                    .collect(),
+                (None, ast::Pattern::Name(name)) => {
+                    // 变量匹配这个构造函数：展开为新的变量，再用方程重建原来的变量
+                    let polarities = match self.polarities.get(*constructor.agent) {
+                        Some(polarities) if polarities.len() == constructor.arity => {
+                            polarities.clone()
+                        }
+                        _ => vec![Polarity::In; constructor.arity],
+                    };
+                    let fresh = polarities
+                        .into_iter()
+                        .map(|polarity| self.fresh(polarity, name))
+                        .collect::<Vec<_>>();
+                    let (ast::Name::In(ident) | ast::Name::Out(ident)) = name.as_ref();
+                    equations.push(name.with(ast::Equation {
+                        left: ast::Term::Agent(name.with(ast::Agent {
+                            name: name.with(*constructor.agent),
+                            body: fresh.iter().cloned().map(ast::Term::Name).collect(),
+                        })),
+                        right: ast::Term::Name(name.with(ast::Name::Out(ident.clone()))),
+                    }));
+                    fresh
+                        .into_iter()
+                        .map(|name| Cell::Fresh(ast::Pattern::Name(name)))
+                        .collect()
+                }
+                (None, ast::Pattern::Agent(_)) => unreachable!(),
+            };
+            cells.splice(0..0, expanded);
+            rows.push(Row {
+                index: row.index,
+                rule: row.rule,
+                cells,
+                equations,
+            });
+        }
+        rows
+    }
+
+    /// 生成的变量，名称以数字开头，不会与源代码中的变量冲突
+    fn fresh<T>(&mut self, polarity: Polarity, at: &Span<'a, T>) -> Span<'a, ast::Name<'a>> {
+        let name = at.with(self.names.alloc(self.fresh.to_string()));
+        self.fresh += 1;
+        at.with(match polarity {
+            Polarity::In => ast::Name::In(name),
+            Polarity::Out => ast::Name::Out(name),
+        })
+    }
+
+    /// 以 `rule` 的位置生成当前状态的规则
+    fn push_rule(
+        &mut self,
+        rule: &Span<'a, ast::Rule<'a>>,
+        state: &State<'a>,
+        patterns: Vec<ast::Pattern<'a>>,
+        equations: Vec<Span<'a, ast::Equation<'a>>>,
+    ) {
+        let pair = &rule.term_pair;
+        let mut patterns = patterns.into_iter();
+        let left = pair.left.with(ast::RuleTerm {
+            agent: state.constructor.clone(),
+            body: patterns.by_ref().take(state.arity).collect(),
+        });
+        let right = pair.right.with(ast::RuleTerm {
+            agent: state.reducer.clone(),
+            body: patterns.collect(),
+        });
+        let term_pair = pair.with(ast::RuleTermPair { left, right });
+        self.rules.push(rule.with(ast::Rule {
+            term_pair,
+            equations,
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::parser;
+
+    /// 展开 `source` 中的嵌套模式，返回展开后的模块以及错误与警告的诊断代码和说明
+    fn expand(source: &str) -> (String, Vec<(&'static str, String)>) {
+        let module = parser::parse(source, "test.zz").unwrap().into_inner();
+        let modules = [module];
+        let names = ast::GeneratedNames::default();
+        let (mut errors, mut warnings) = (vec![], vec![]);
+        let desugared = desugar(&modules, &names, &mut errors, &mut warnings);
+        let diagnostics = errors
+            .iter()
+            .map(|e| e.to_diagnostic())
+            .chain(warnings.iter().map(|w| w.to_diagnostic()))
+            .map(|d| (d.code, d.label.unwrap()))
+            .collect();
+        (desugared[0].to_string(), diagnostics)
+    }
+
+    const MAIN: &str = "Main <| #r |> Cons(Nil, Cons(Nil, Nil)) -> Last(@r)\n";
+
+    #[test]
+    fn nested_patterns() {
+        let source = "Nil >> Last(@r) => @r <- Nil
+Cons(#x, Nil) >> Last(@r) => @r <- #x
+Cons(_, Cons(#y, #ys)) >> Last(@r) => Cons(#y, #ys) -> Last(@r)
+"
+        .to_string()
+            + MAIN;
+        let (desugared, diagnostics) = expand(&source);
+        assert_eq!(
+            desugared,
+            "Nil >> Last(@r) => Nil -> @r
+Cons(#0, #1) >> Last(@2) => #1 -> Last/Cons.1(#0, @2)
+Nil >> Last/Cons.1(#x, @r) => #x -> @r
+Cons(#y, #ys) >> Last/Cons.1(_, @r) => Cons(#y, #ys) -> Last(@r)
+"
+            .to_string()
+                + MAIN
+        );
+        assert_eq!(diagnostics, []);
+    }
+
+    #[test]
+    fn non_exhaustive() {
+        let source = "Nil >> Last(@r) => @r <- Nil
+Cons(#x, Nil) >> Last(@r) => @r <- #x
+"
+        .to_string()
+            + MAIN;
+        let (_, diagnostics) = expand(&source);
+        assert_eq!(
+            diagnostics,
+            [(
+                "non-exhaustive-patterns",
+                "no rule matches `Cons(_, Cons(_, _)) >> Last(_)`".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn overlapping() {
+        let source = "Cons(#x, Nil) >> Last(@r) => @r <- #x
+Cons(#x, #xs) >> Last(@r) => #xs -> Last(@r), #x -> Erase
+"
+        .to_string()
+            + MAIN;
+        let (desugared, diagnostics) = expand(&source);
+        // 端口上是变量的规则对每个构造函数各展开一次，并重建原来的变量；
+        // 两条规则都匹配的值由前一条规则处理
+        assert_eq!(
+            desugared,
+            "Cons(#0, #1) >> Last(@2) => #1 -> Last/Cons.1(#0, @2)
+Nil >> Last/Cons.1(#x, @r) => #x -> @r
+Cons(#3, #4) >> Last/Cons.1(#x, @r) => #xs -> Last(@r), #x -> Erase, Cons(#3, #4) -> @xs
+"
+            .to_string()
+                + MAIN
+        );
+        assert_eq!(
+            diagnostics,
+            [(
+                "overlapping-patterns",
+                "`Cons(_, Nil) >> Last(_)` is matched by this rule ...".to_string()
+            )]
+        );
+    }
+}
@@ -1 +1,336 @@
 //! 语义分析。
+//!
+//! 本模块对整个程序进行端口极性推断：为每个交互器推断一个签名，
+//! 记录其主端口和各个辅助端口的极性（输入 `#` 或输出 `@`）。
+//! 主端口为输入的交互器是构造函数，主端口为输出的交互器是规约函数。
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    backend::native,
+    frontend::{ast, check::TypeError},
+    utils::Span,
+};
+
+/// 端口极性
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Polarity {
+    /// 输入，即 `#x`
+    In,
+    /// 输出，即 `@x`
+    Out,
+}
+
+impl Display for Polarity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Polarity::In => write!(f, "input"),
+            Polarity::Out => write!(f, "output"),
+        }
+    }
+}
+
+impl Polarity {
+    /// 变量的极性
+    pub fn of_name(name: &ast::Name) -> Self {
+        match name {
+            ast::Name::In(_) => Polarity::In,
+            ast::Name::Out(_) => Polarity::Out,
+        }
+    }
+
+    /// 变量前缀
+    pub fn sigil(self) -> char {
+        match self {
+            Polarity::In => '#',
+            Polarity::Out => '@',
+        }
+    }
+}
+
+/// 交互器签名
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    /// 主端口极性，无法推断时为 `None`
+    pub principal: Option<Polarity>,
+    /// 辅助端口极性，无法推断时为 `None`
+    pub ports: Vec<Option<Polarity>>,
+}
+
+impl Signature {
+    /// 是否为构造函数
+    pub fn is_constructor(&self) -> bool {
+        self.principal == Some(Polarity::In)
+    }
+
+    /// 是否为规约函数
+    pub fn is_reducer(&self) -> bool {
+        self.principal == Some(Polarity::Out)
+    }
+}
+
+/// 全部交互器的签名
+pub type Signatures<'a> = HashMap<&'a str, Signature>;
+
+/// 是否为内置交互器
+pub fn is_builtin(name: &str) -> bool {
+    native::builtin_agents()
+        .iter()
+        .any(|builtin| builtin.name == name)
+}
+
+/// 极性变量的取值，以及确定该取值的位置（内置交互器没有位置）
+type Value<'a> = (Polarity, Option<Span<'a, ()>>);
+
+struct AgentVars<'a> {
+    name: Option<&'a Span<'a, &'a str>>,
+    principal: usize,
+    ports: Vec<usize>,
+}
+
+#[derive(Default)]
+struct Inference<'a> {
+    agents: HashMap<&'a str, AgentVars<'a>>,
+    parent: Vec<usize>,
+    values: Vec<Option<Value<'a>>>,
+    errors: Vec<TypeError<'a>>,
+}
+
+impl<'a> Inference<'a> {
+    fn new() -> Self {
+        let mut inference = Self::default();
+        for builtin in native::builtin_agents() {
+            let agent = AgentVars {
+                name: None,
+                principal: inference.fresh(Some((builtin.principal, None))),
+                ports: builtin
+                    .ports
+                    .iter()
+                    .map(|&port| inference.fresh(Some((port, None))))
+                    .collect(),
+            };
+            inference.agents.insert(builtin.name, agent);
+        }
+        inference
+    }
+
+    fn fresh(&mut self, value: Option<Value<'a>>) -> usize {
+        let var = self.parent.len();
+        self.parent.push(var);
+        self.values.push(value);
+        var
+    }
+
+    fn find(&mut self, var: usize) -> usize {
+        let parent = self.parent[var];
+        if parent == var {
+            return var;
+        }
+        let root = self.find(parent);
+        self.parent[var] = root;
+        root
+    }
+
+    fn value(&mut self, var: usize) -> Option<Polarity> {
+        let root = self.find(var);
+        self.values[root].as_ref().map(|(polarity, _)| *polarity)
+    }
+
+    /// 合并两个极性变量，`b` 是在 `at` 处新出现的约束
+    ///
+    /// 两者冲突时记录错误，保留原有的取值继续推断
+    fn unify(&mut self, a: usize, b: usize, at: Span<'a, ()>) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        match (&self.values[a], &self.values[b]) {
+            (Some((pa, previous)), Some((pb, _))) if pa != pb => {
+                self.errors.push(TypeError::PolarityConflict {
+                    span: at,
+                    found: *pb,
+                    previous: previous.clone(),
+                })
+            }
+            _ => {
+                self.parent[b] = a;
+                if self.values[a].is_none() {
+                    self.values[a] = self.values[b].take();
+                }
+            }
+        }
+    }
+
+    fn constrain(&mut self, var: usize, polarity: Polarity, span: Span<'a, ()>) {
+        let constant = self.fresh(Some((polarity, Some(span.clone()))));
+        self.unify(var, constant, span)
+    }
+
+    /// 查找交互器的极性变量，首次出现时创建
+    ///
+    /// 端口数量不符时记录错误，返回与其他出现无关的新变量
+    fn agent(&mut self, name: &'a Span<'a, &'a str>, arity: usize) -> (usize, Vec<usize>) {
+        if let Some(agent) = self.agents.get(name.as_ref()) {
+            if agent.ports.len() == arity {
+                return (agent.principal, agent.ports.clone());
+            }
+            self.errors.push(TypeError::ArityMismatch {
+                name,
+                expected: agent.ports.len(),
+                found: arity,
+                previous: agent.name,
+            });
+            let principal = self.fresh(None);
+            let ports = (0..arity).map(|_| self.fresh(None)).collect();
+            return (principal, ports);
+        }
+
+        let principal = self.fresh(None);
+        let ports = (0..arity).map(|_| self.fresh(None)).collect::<Vec<_>>();
+        self.agents.insert(
+            name,
+            AgentVars {
+                name: Some(name),
+                principal,
+                ports: ports.clone(),
+            },
+        );
+        (principal, ports)
+    }
+
+    /// 项的极性变量
+    fn term(&mut self, term: &'a ast::Term<'a>) -> usize {
+        match term {
+            ast::Term::Name(name) => {
+                let polarity = Polarity::of_name(name);
+                self.fresh(Some((polarity, Some(name.as_unit()))))
+            }
+            ast::Term::Int(value) => self.fresh(Some((Polarity::In, Some(value.as_unit())))),
+            ast::Term::Agent(agent) => {
+                let (principal, ports) = self.agent(&agent.name, agent.body.len());
+                for (port, term) in ports.into_iter().zip(&agent.body) {
+                    let var = self.term(term);
+                    self.unify(port, var, term.span());
+                }
+                principal
+            }
+            // 字面量在检查之前已经展开，只有语言服务器会对尚未展开的文件推导签名，
+            // 此时不知道字面量使用的交互器，只推导其中的各项
+            ast::Term::Literal(literal) => {
+                for term in literal.terms() {
+                    self.term(term);
+                }
+                self.fresh(None)
+            }
+        }
+    }
+
+    fn declaration(&mut self, declaration: &'a Span<'a, ast::Declaration<'a>>) {
+        let (_, ports) = self.agent(&declaration.agent, declaration.ports.len());
+        for (port, name) in ports.into_iter().zip(&declaration.ports) {
+            self.constrain(port, Polarity::of_name(name), name.as_unit());
+        }
+    }
+
+    fn rule_term(&mut self, term: &'a Span<'a, ast::RuleTerm<'a>>, polarity: Polarity) {
+        let (principal, ports) = self.agent(&term.agent, term.body.len());
+        self.constrain(principal, polarity, term.as_unit());
+        for (port, pattern) in ports.into_iter().zip(&term.body) {
+            match pattern {
+                ast::Pattern::Name(name) => {
+                    self.constrain(port, Polarity::of_name(name), name.as_unit())
+                }
+                // 通配符端口上的值交给 `Erase`，因此只能是输入
+                ast::Pattern::Wildcard(span) => self.constrain(port, Polarity::In, span.clone()),
+                ast::Pattern::Agent(nested) => self.constrain(port, Polarity::In, nested.as_unit()),
+            }
+        }
+    }
+
+    fn build(mut self) -> Signatures<'a> {
+        let agents = std::mem::take(&mut self.agents);
+        agents
+            .into_iter()
+            .map(|(name, agent)| {
+                let signature = Signature {
+                    principal: self.value(agent.principal),
+                    ports: agent.ports.iter().map(|&port| self.value(port)).collect(),
+                };
+                (name, signature)
+            })
+            .collect()
+    }
+}
+
+/// 推断整个程序中所有交互器的签名
+///
+/// 交互器声明确定端口数量与极性；规则左侧的两个项分别确定构造函数与规约函数，
+/// 交互器体确定各个端口的极性；方程左侧的项必须是输入，右侧的项必须是输出。
+/// 推断过程中的错误全部记录到 `errors` 中。
+pub fn infer_signatures<'a>(
+    module: &'a ast::Module<'a>,
+    errors: &mut Vec<TypeError<'a>>,
+) -> Signatures<'a> {
+    let mut inference = Inference::new();
+    let mut equations = vec![];
+
+    for declaration in &module.declarations {
+        inference.declaration(declaration);
+    }
+
+    for rule in &module.rules {
+        inference.rule_term(&rule.term_pair.left, Polarity::In);
+        inference.rule_term(&rule.term_pair.right, Polarity::Out);
+        for equation in &rule.equations {
+            let left = inference.term(&equation.left);
+            let right = inference.term(&equation.right);
+            equations.push((equation, left, right));
+        }
+    }
+
+    for net in &module.nets {
+        for equation in &net.equations {
+            let left = inference.term(&equation.left);
+            let right = inference.term(&equation.right);
+            equations.push((equation, left, right));
+        }
+        for interface in &net.interfaces {
+            let var = inference.term(interface);
+            inference.constrain(var, Polarity::In, interface.span());
+        }
+    }
+
+    for statement in &module.statements {
+        let left = inference.term(&statement.left);
+        let right = inference.term(&statement.right);
+        equations.push((statement, left, right));
+    }
+    for output in &module.outputs {
+        let var = inference.term(output);
+        inference.constrain(var, Polarity::In, output.span());
+    }
+
+    // 先根据交互器体推断，再检查方程两侧，以便给出更准确的错误信息
+    for (equation, left, right) in equations {
+        match (inference.value(left), inference.value(right)) {
+            (Some(Polarity::In), Some(Polarity::In)) => {
+                inference
+                    .errors
+                    .push(TypeError::ConnectsTwoInputs { equation });
+                continue;
+            }
+            (Some(Polarity::Out), Some(Polarity::Out)) => {
+                inference
+                    .errors
+                    .push(TypeError::ConnectsTwoOutputs { equation });
+                continue;
+            }
+            _ => {}
+        }
+        inference.constrain(left, Polarity::In, equation.left.span());
+        inference.constrain(right, Polarity::Out, equation.right.span());
+    }
+
+    errors.append(&mut inference.errors);
+    inference.build()
+}
@@ -0,0 +1,317 @@
+//! 语法糖。
+//!
+//! 语法糖指令指定字面量与输出结果使用的交互器：`sugar list(Cons, Nil)` 使 `[a, b | t]`
+//! 展开为 `Cons(a, Cons(b, t))`，没有 `| t` 时以 `Nil` 结尾；`sugar tuple(Tuple)` 使 `(a, b)`
+//! 展开为 `Tuple(a, b)`；`sugar number(S, Zero)` 使整数字面量 `2` 展开为 `S(S(Zero))`，
+//! 没有这条指令时整数字面量是内置的整数。
+//! 指令对全部文件有效，在改写为完整的名称之后、检查之前展开。
+
+use std::collections::HashMap;
+
+use annotate_snippets::snippet::AnnotationType;
+use thiserror::Error;
+
+use crate::diagnostic::{Diagnostic, Diagnostics, Severity};
+use crate::frontend::{ast, check::render_snippet};
+use crate::utils::Span;
+
+/// `sugar number` 下整数字面量的上限
+///
+/// 字面量展开为同样深度的嵌套项，之后的检查与构建都会递归地遍历它，过大的字面量会导致栈溢出。
+pub const MAX_NUMBER: u64 = 256;
+
+/// 语法糖错误。
+#[derive(Error, Debug)]
+#[allow(missing_docs)]
+pub enum SugarError<'a> {
+    #[error("conflicting `sugar {}` directives", .kind)]
+    Conflicting {
+        kind: ast::SugarKind,
+        sugar: Span<'a, ()>,
+        previous: Span<'a, ()>,
+    },
+
+    #[error("{} literal without a `sugar {}` directive", .kind, .kind)]
+    Undeclared {
+        kind: ast::SugarKind,
+        literal: Span<'a, ()>,
+    },
+
+    #[error("negative number `{}` under `sugar number`", .0.as_ref())]
+    Negative(Span<'a, i64>),
+
+    #[error("number `{}` is too large under `sugar number`", .0.as_ref())]
+    TooLarge(Span<'a, i64>),
+}
+
+impl<'a> SugarError<'a> {
+    /// 诊断代码
+    pub fn code(&self) -> &'static str {
+        match self {
+            SugarError::Conflicting { .. } => "conflicting-sugar",
+            SugarError::Undeclared { .. } => "undeclared-sugar",
+            SugarError::Negative(_) => "negative-number",
+            SugarError::TooLarge(_) => "number-too-large",
+        }
+    }
+
+    fn spans(&self) -> Vec<(Span<'a, ()>, String)> {
+        match self {
+            SugarError::Conflicting {
+                sugar, previous, ..
+            } => vec![
+                (
+                    sugar.clone(),
+                    "declared again with other agents".to_string(),
+                ),
+                (previous.clone(), "first declared here".to_string()),
+            ],
+            SugarError::Undeclared { kind, literal } => vec![(
+                literal.clone(),
+                format!("add a `sugar {}(...)` directive naming its agents", kind),
+            )],
+            SugarError::Negative(value) => vec![(
+                value.as_unit(),
+                "natural numbers cannot be negative".to_string(),
+            )],
+            SugarError::TooLarge(value) => vec![(
+                value.as_unit(),
+                format!(
+                    "numbers are expanded into at most {} successors",
+                    MAX_NUMBER
+                ),
+            )],
+        }
+    }
+
+    /// 将错误转换为可供显示的字符串。
+    pub fn to_snippet(&self) -> String {
+        render_snippet(&self.to_string(), AnnotationType::Error, &self.spans())
+    }
+
+    /// 将错误转换为结构化的诊断信息。
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::new(
+            self.code(),
+            Severity::Error,
+            self.to_string(),
+            self.spans(),
+            self.to_snippet(),
+        )
+    }
+}
+
+/// 按全部文件中的语法糖指令展开每个文件中的字面量
+pub fn desugar<'a>(mut modules: Vec<ast::Module<'a>>) -> Result<Vec<ast::Module<'a>>, Diagnostics> {
+    let mut desugar = Desugar {
+        sugars: HashMap::new(),
+        errors: vec![],
+    };
+    for module in &modules {
+        for sugar in &module.sugars {
+            desugar.collect(sugar);
+        }
+    }
+    for module in &mut modules {
+        desugar.module(module);
+    }
+
+    if !desugar.errors.is_empty() {
+        let errors = desugar.errors.iter().map(|e| e.to_diagnostic()).collect();
+        return Err(Diagnostics(errors));
+    }
+    Ok(modules)
+}
+
+struct Desugar<'a> {
+    /// 每种语法糖使用的交互器以及第一条指令的位置
+    sugars: HashMap<ast::SugarKind, (Vec<&'a str>, Span<'a, ()>)>,
+    errors: Vec<SugarError<'a>>,
+}
+
+impl<'a> Desugar<'a> {
+    fn collect(&mut self, sugar: &Span<'a, ast::Sugar<'a>>) {
+        let agents: Vec<&str> = sugar.agents.iter().map(|agent| *agent.as_ref()).collect();
+        match self.sugars.get(&sugar.kind) {
+            Some((previous, _)) if *previous == agents => {}
+            Some((_, previous)) => self.errors.push(SugarError::Conflicting {
+                kind: sugar.kind,
+                sugar: sugar.as_unit(),
+                previous: previous.clone(),
+            }),
+            None => {
+                self.sugars.insert(sugar.kind, (agents, sugar.as_unit()));
+            }
+        }
+    }
+
+    fn module(&mut self, module: &mut ast::Module<'a>) {
+        for rule in &mut module.rules {
+            for equation in &mut rule.equations {
+                self.equation(equation);
+            }
+        }
+        for net in &mut module.nets {
+            for interface in &mut net.interfaces {
+                self.term(interface);
+            }
+            for equation in &mut net.equations {
+                self.equation(equation);
+            }
+        }
+        for statement in &mut module.statements {
+            self.equation(statement);
+        }
+        for output in &mut module.outputs {
+            self.term(output);
+        }
+    }
+
+    fn equation(&mut self, equation: &mut ast::Equation<'a>) {
+        self.term(&mut equation.left);
+        self.term(&mut equation.right);
+    }
+
+    fn term(&mut self, term: &mut ast::Term<'a>) {
+        match term {
+            ast::Term::Agent(agent) => {
+                for t in &mut agent.body {
+                    self.term(t);
+                }
+            }
+            ast::Term::Literal(literal) => {
+                for t in literal.terms_mut() {
+                    self.term(t);
+                }
+                let kind = literal.kind();
+                match self.sugars.get(&kind) {
+                    Some((agents, _)) => *term = expand(literal, agents),
+                    None => self.errors.push(SugarError::Undeclared {
+                        kind,
+                        literal: literal.as_unit(),
+                    }),
+                }
+            }
+            ast::Term::Int(value) => {
+                let Some((agents, _)) = self.sugars.get(&ast::SugarKind::Number) else {
+                    return;
+                };
+                match u64::try_from(*value.as_ref()) {
+                    Ok(n) if n > MAX_NUMBER => {
+                        self.errors.push(SugarError::TooLarge(value.clone()))
+                    }
+                    Ok(n) => *term = number(value, n, agents),
+                    Err(_) => self.errors.push(SugarError::Negative(value.clone())),
+                }
+            }
+            ast::Term::Name(_) => {}
+        }
+    }
+}
+
+/// 将字面量展开为交互器
+///
+/// 生成的交互器名称指向字面量的括号，而不是其中可能含有限定名称的元素。
+fn expand<'a>(literal: &Span<'a, ast::Literal<'a>>, agents: &[&'a str]) -> ast::Term<'a> {
+    let open = Span::new(
+        (),
+        literal.filename,
+        literal.source,
+        literal.start,
+        literal.start + 1,
+    );
+    let close = Span::new(
+        (),
+        literal.filename,
+        literal.source,
+        literal.end - 1,
+        literal.end,
+    );
+    let agent = |name: &Span<'a, ()>, agent: &'a str, body| {
+        ast::Term::Agent(literal.with(ast::Agent {
+            name: name.with(agent),
+            body,
+        }))
+    };
+    match literal.as_ref() {
+        ast::Literal::List { elements, tail } => {
+            let tail = match tail {
+                Some(tail) => tail.as_ref().clone(),
+                None => agent(&close, agents[1], vec![]),
+            };
+            elements.iter().rev().fold(tail, |list, element| {
+                agent(&open, agents[0], vec![element.clone(), list])
+            })
+        }
+        ast::Literal::Tuple(elements) => agent(&open, agents[0], elements.clone()),
+    }
+}
+
+/// 将整数字面量展开为 `n` 个后继交互器与零
+fn number<'a>(value: &Span<'a, i64>, n: u64, agents: &[&'a str]) -> ast::Term<'a> {
+    let agent = |agent: &'a str, body| {
+        ast::Term::Agent(value.with(ast::Agent {
+            name: value.with(agent),
+            body,
+        }))
+    };
+    (0..n).fold(agent(agents[1], vec![]), |term, _| {
+        agent(agents[0], vec![term])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Options;
+    use crate::testing::backends;
+    use crate::Context;
+
+    const SUGARS: &str = "sugar number(S, Zero)
+sugar list(Cons, Nil)
+sugar tuple(T)
+S(#x) >> Add(#y, @w) => #x -> Add(#y, @z), @w <- S(#z)
+Zero  >> Add(#x, @y) => #x -> @y
+";
+
+    /// 编译出错时的诊断代码
+    fn error_codes(source: &str) -> Vec<&'static str> {
+        let Err(e) = Context::new().add_file("test.zz", source) else {
+            panic!("the program compiles");
+        };
+        let Diagnostics(errors) = e.downcast_ref::<Diagnostics>().unwrap();
+        errors.iter().map(|error| error.code).collect()
+    }
+
+    #[test]
+    fn printing() {
+        let source = format!(
+            "{SUGARS}Main <| #a, #b, #c, #d |>
+    2 -> Add(3, @a), @b <- [1, 2, [0] | Cons(3, Box)], @c <- ([], 4), @d <- S(S(Box))"
+        );
+        let output = backends::run(&source, Options::default());
+        assert_eq!(
+            output.stdout,
+            "5\n[1, 2, [0], 3 | Box]\n([], 4)\nS(S(Box))\n"
+        );
+    }
+
+    #[test]
+    fn largest_number() {
+        let source = format!("{SUGARS}Main <| #r |> {MAX_NUMBER} -> Add(0, @r)");
+        let output = backends::run(&source, Options::default());
+        assert_eq!(output.stdout, format!("{MAX_NUMBER}\n"));
+    }
+
+    #[test]
+    fn number_limits() {
+        let source = format!(
+            "{SUGARS}Main <| #a, #b |> @a <- Box({}), @b <- Box(-1)",
+            MAX_NUMBER + 1
+        );
+        assert_eq!(
+            error_codes(&source),
+            ["number-too-large", "negative-number"]
+        );
+    }
+}
@@ -6,21 +6,34 @@
 #![deny(unreachable_pub)]
 
 pub mod backend;
+pub mod debugger;
+pub mod diagnostic;
 pub mod frontend;
+pub mod lsp;
 pub mod options;
+pub mod testing;
 pub(crate) mod utils;
 
-use anyhow::Result;
+use anyhow::{Context as _, Result};
+use backend::interpreter::{Machine, RuntimeError};
 use backend::RuntimeBuilder;
 use backend::{optimize, target::Target};
-use frontend::{check, parser};
+use diagnostic::{Diagnostic, Diagnostics};
+use frontend::{ast, check, import, namespace, sugar};
 use options::Options;
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::PathBuf;
 
 /// 编译器上下文
 #[derive(Default)]
 pub struct Context {
     builder: RuntimeBuilder,
     options: Options,
+    warnings: Vec<Diagnostic>,
+    import_paths: Vec<PathBuf>,
+    /// 已经编译过的文件，每个文件只编译一次
+    loaded: HashSet<PathBuf>,
 }
 
 impl Context {
@@ -35,21 +48,52 @@ impl Context {
         self
     }
 
-    /// 编译源代码。
+    /// 添加查找被导入文件的目录，在导入它的文件所在的目录之后依次查找。
+    pub fn add_import_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.import_paths.push(path.into());
+        self
+    }
+
+    /// 编译源代码及其导入的文件。
+    ///
+    /// 出错时以 [`Diagnostics`] 报告文件中的全部错误，而不只是第一个。
+    /// 被导入的文件只提供声明、规则与网络，其中的入口网络会被忽略；
+    /// 之前编译过的文件参与检查，但不会被再次编译。
     pub fn add_file(mut self, filename: &str, source: &str) -> Result<Self> {
-        let module = match parser::parse(source, filename) {
-            Ok(module) => module,
-            Err(snippet) => anyhow::bail!("{}", snippet),
+        let sources = import::Sources::default();
+        let (files, modules): (Vec<_>, Vec<_>) =
+            import::load(filename, source, &self.import_paths, &sources)?
+                .into_iter()
+                .unzip();
+        let names = ast::GeneratedNames::default();
+        let modules = namespace::resolve(modules, &names);
+        let modules = sugar::desugar(modules)?;
+        let (modules, warnings) = match check::check_files(&modules, &names) {
+            Ok(checked) => checked,
+            Err(errors) => return Err(Diagnostics(errors).into()),
         };
-
-        if let Err(e) = check::check_module(&module) {
-            anyhow::bail!("{}", e.to_snippet());
+        for warning in warnings {
+            if !self.warnings.contains(&warning) {
+                self.warnings.push(warning);
+            }
         }
 
-        self.builder.module(module.into_inner())?;
+        for (file, module) in files.iter().zip(modules) {
+            if let Some(path) = &file.path {
+                if !self.loaded.insert(path.clone()) {
+                    continue;
+                }
+            }
+            self.builder.module(module)?;
+        }
         Ok(self)
     }
 
+    /// 编译过程中产生的警告。
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
     /// 输出到流。
     pub fn output_stream<T: Target>(self, output: impl std::io::Write) -> Result<()> {
         let mut runtime = self.builder.build()?;
@@ -68,17 +112,106 @@ impl Context {
         Ok(())
     }
 
-    /// 运行。
-    #[cfg(feature = "tinycc")]
+    /// 构建程序并在解释器中执行入口网络，尚未开始规约。
+    pub fn machine(self) -> Result<Machine> {
+        let mut runtime = self.builder.build()?;
+        optimize::optimize(&mut runtime);
+
+        let mut machine = Machine::with_stack_limit(runtime, self.options.max_stack_size)?;
+        machine.set_limits(self.options.max_reductions, self.options.max_agents);
+        if self.options.profile {
+            machine.set_profile(self.options.timing);
+        }
+        Ok(machine)
+    }
+
+    /// 在调试器中运行，从 `input` 读取命令并向 `output` 输出。
+    pub fn debug(self, input: impl std::io::BufRead, output: impl std::io::Write) -> Result<()> {
+        let machine = self.machine()?;
+        debugger::Debugger::new(machine, input, output).run()
+    }
+
+    /// 在解释器中运行，输出与 C 目标生成的程序一致。
+    ///
+    /// 结果写入标准输出，跟踪、效率与统计信息写入标准错误，参见 [`Context::run_with`]。
     pub fn run(self) -> Result<()> {
-        let mut output = std::io::Cursor::new(Vec::new());
-        self.output_stream::<backend::target::C>(&mut output)?;
-        let output = std::ffi::CString::new(output.into_inner())?;
+        self.run_with(std::io::stdout().lock(), std::io::stderr())
+    }
 
-        tinycc::Context::new(tinycc::OutputType::Memory)?
-            .compile_string(&output)?
-            .run(&[])?;
+    /// 在解释器中运行，结果写入 `stdout`，跟踪、错误、效率与统计信息写入 `stderr`。
+    ///
+    /// 计时只包括规约，使用的是挂钟时间。运行时错误与 C 目标一样在出错时写入 `stderr`，
+    /// 之后返回 [`RuntimeError`]；
+    /// 超出规约次数或交互器数量的上限时，在返回之前还会输出部分结果与统计信息。
+    pub fn run_with(self, mut stdout: impl Write, stderr: impl Write + 'static) -> Result<()> {
+        let options = self.options.clone();
+        let mut stderr = utils::Shared::new(stderr);
+        // 构建入口网络时也可能出错，例如方程栈溢出
+        let mut machine = match self.traced_machine(stderr.clone()) {
+            Ok(machine) => machine,
+            Err(e) => {
+                if let Some(e) = e.downcast_ref::<RuntimeError>() {
+                    e.report(&mut stderr)?;
+                }
+                return Err(e);
+            }
+        };
 
-        Ok(())
+        let start = std::time::Instant::now();
+        let result = machine.run();
+        let time = start.elapsed().as_secs_f64();
+        if let Err(e) = &result {
+            e.report(&mut stderr)?;
+            if !e.is_limit() {
+                return Ok(result?);
+            }
+        }
+
+        for &output in machine.outputs() {
+            let result = machine.display_result(output, options.result_format, options.max_depth);
+            writeln!(stdout, "{}", result)?;
+        }
+
+        if options.timing {
+            let reductions = machine.reductions();
+            writeln!(
+                stderr,
+                "\n[Reductions: {}, Wall time: {:.6}, R/s: {:.6}]",
+                reductions,
+                time,
+                reductions as f64 / time
+            )?;
+        }
+        if let Some(profile) = machine.profile() {
+            write!(stderr, "{}", profile.report(machine.program()))?;
+        }
+        Ok(result?)
+    }
+
+    /// 在解释器中运行至结束，返回每个输出的文本，与 [`Context::run`] 输出的各行一致。
+    pub fn evaluate(self) -> Result<Vec<String>> {
+        let options = self.options.clone();
+        let mut machine = self.traced_machine(std::io::stderr())?;
+        machine.run()?;
+        Ok(machine
+            .outputs()
+            .iter()
+            .map(|&output| machine.display_result(output, options.result_format, options.max_depth))
+            .collect())
+    }
+
+    fn traced_machine(self, stderr: impl Write + 'static) -> Result<Machine> {
+        let trace = self.options.trace;
+        let trace_file = self.options.trace_file.clone();
+        let mut machine = self.machine()?;
+        if trace {
+            machine.set_trace(stderr);
+        }
+        if let Some(path) = trace_file {
+            let file = std::fs::File::create(&path)
+                .with_context(|| format!("cannot open trace file {}", path.display()))?;
+            machine.set_json_trace(std::io::BufWriter::new(file));
+        }
+        Ok(machine)
     }
 }
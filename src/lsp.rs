@@ -0,0 +1,921 @@
+//! 语言服务器。
+//!
+//! 通过标准输入输出实现 Language Server Protocol，基于 [`parser`] 与 [`check`]
+//! 提供实时诊断、跳转到交互器的定义、查找交互器的全部规则与引用、
+//! 悬停显示交互器的签名，以及在规则或网络内重命名变量。
+//!
+//! 诊断连同导入的文件一起检查，跳转与重命名等只在当前文档内进行。
+//! 文档很小，每次请求都重新解析全文，不做增量分析。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        GotoDefinition, GotoImplementation, HoverRequest, PrepareRenameRequest, References, Rename,
+        Request as _,
+    },
+    DiagnosticRelatedInformation, DiagnosticSeverity, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, ImplementationProviderCapability, Location, MarkupContent, MarkupKind,
+    NumberOrString, OneOf, Position, PrepareRenameResponse, PublishDiagnosticsParams, Range,
+    RenameOptions, ServerCapabilities, TextDocumentPositionParams, TextDocumentSyncCapability,
+    TextDocumentSyncKind, TextEdit, Url, WorkspaceEdit,
+};
+
+use crate::{
+    diagnostic::{self, Diagnostic, Diagnostics},
+    frontend::{ast, check, import, namespace, parser, semantic, sugar},
+    utils::Span,
+};
+
+/// 在标准输入输出上运行语言服务器，直到客户端要求退出
+///
+/// `import_paths` 是查找被导入文件的目录，与 [`crate::Context::add_import_path`] 相同。
+pub fn run(import_paths: Vec<PathBuf>) -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(&connection, import_paths)?;
+    drop(connection);
+    io_threads.join()?;
+    Ok(())
+}
+
+/// 在给定的连接上完成初始化并处理消息
+pub fn serve(connection: &Connection, import_paths: Vec<PathBuf>) -> Result<()> {
+    let capabilities = ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        definition_provider: Some(OneOf::Left(true)),
+        implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+        references_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        rename_provider: Some(OneOf::Right(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        ..Default::default()
+    };
+    connection.initialize(serde_json::to_value(capabilities)?)?;
+
+    let mut server = Server {
+        connection,
+        documents: HashMap::new(),
+        import_paths,
+    };
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                server.request(request)?;
+            }
+            Message::Notification(notification) => server.notification(notification)?,
+            Message::Response(_) => {}
+        }
+    }
+    Ok(())
+}
+
+struct Server<'c> {
+    connection: &'c Connection,
+    /// 打开的文档
+    documents: HashMap<Url, String>,
+    /// 查找被导入文件的目录
+    import_paths: Vec<PathBuf>,
+}
+
+/// 请求处理失败时返回给客户端的错误
+type RequestError = (ErrorCode, String);
+
+impl<'c> Server<'c> {
+    fn notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish_diagnostics(uri)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // 只支持全量同步，最后一次修改即为全文
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    let uri = params.text_document.uri;
+                    self.documents.insert(uri.clone(), change.text);
+                    self.publish_diagnostics(uri)?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                let uri = params.text_document.uri;
+                self.documents.remove(&uri);
+                self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+                    uri,
+                    diagnostics: vec![],
+                    version: None,
+                })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn request(&mut self, request: Request) -> Result<()> {
+        let id = request.id.clone();
+        let result = match request.method.as_str() {
+            GotoDefinition::METHOD => self.handle::<GotoDefinition>(request, Self::definition),
+            GotoImplementation::METHOD => {
+                self.handle::<GotoImplementation>(request, Self::implementation)
+            }
+            References::METHOD => self.handle::<References>(request, Self::references),
+            HoverRequest::METHOD => self.handle::<HoverRequest>(request, Self::hover),
+            PrepareRenameRequest::METHOD => {
+                self.handle::<PrepareRenameRequest>(request, Self::prepare_rename)
+            }
+            Rename::METHOD => self.handle::<Rename>(request, Self::rename),
+            method => Err((
+                ErrorCode::MethodNotFound,
+                format!("unsupported request `{}`", method),
+            )),
+        };
+
+        let response = match result {
+            Ok(result) => Response::new_ok(id, result),
+            Err((code, message)) => Response::new_err(id, code as i32, message),
+        };
+        self.connection.sender.send(response.into())?;
+        Ok(())
+    }
+
+    fn handle<R: lsp_types::request::Request>(
+        &self,
+        request: Request,
+        handler: impl FnOnce(&Self, R::Params) -> Result<R::Result, RequestError>,
+    ) -> Result<serde_json::Value, RequestError> {
+        let params = serde_json::from_value(request.params)
+            .map_err(|e| (ErrorCode::InvalidParams, e.to_string()))?;
+        let result = handler(self, params)?;
+        serde_json::to_value(result).map_err(|e| (ErrorCode::InternalError, e.to_string()))
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) -> Result<()> {
+        let notification = Notification::new(N::METHOD.to_string(), params);
+        self.connection.sender.send(notification.into())?;
+        Ok(())
+    }
+
+    /// 与编译时一样连同导入的文件一起检查，只报告位于这个文档中的诊断
+    fn publish_diagnostics(&self, uri: Url) -> Result<()> {
+        let text = &self.documents[&uri];
+        let filename = match uri.to_file_path() {
+            Ok(path) => path.display().to_string(),
+            Err(()) => uri.to_string(),
+        };
+        let sources = import::Sources::default();
+        let diagnostics: Vec<Diagnostic> =
+            match import::load(&filename, text, &self.import_paths, &sources) {
+                Err(Diagnostics(errors)) => errors,
+                Ok(files) => {
+                    let modules = files.into_iter().map(|(_, module)| module).collect();
+                    let names = ast::GeneratedNames::default();
+                    let modules = namespace::resolve(modules, &names);
+                    match sugar::desugar(modules) {
+                        Err(Diagnostics(errors)) => errors,
+                        Ok(modules) => match check::check_files(&modules, &names) {
+                            Ok((_, warnings)) => warnings,
+                            Err(errors) => errors,
+                        },
+                    }
+                }
+            };
+
+        let index = LineIndex::new(text);
+        let diagnostics = diagnostics
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic
+                    .location
+                    .as_ref()
+                    .is_none_or(|location| location.file == filename)
+            })
+            .map(|diagnostic| to_lsp_diagnostic(&uri, &filename, &index, diagnostic))
+            .collect();
+        self.notify::<PublishDiagnostics>(PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        })
+    }
+
+    /// 解析光标所在的文档并交给 `f` 处理，文档无法解析时返回 `None`
+    fn with_document<T>(
+        &self,
+        position: &TextDocumentPositionParams,
+        f: impl FnOnce(&Document, usize) -> Option<T>,
+    ) -> Result<Option<T>, RequestError> {
+        let uri = &position.text_document.uri;
+        let Some(text) = self.documents.get(uri) else {
+            return Err((
+                ErrorCode::InvalidParams,
+                format!("document `{}` is not open", uri),
+            ));
+        };
+        let filename = uri.to_string();
+        let Ok(module) = parser::parse(text, &filename) else {
+            return Ok(None);
+        };
+        let document = Document {
+            uri,
+            index: LineIndex::new(text),
+            occurrences: occurrences(&module),
+            module: &module,
+        };
+        let offset = document.index.offset(position.position);
+        Ok(f(&document, offset))
+    }
+
+    /// 交互器的声明，没有声明时为第一条用到它的规则
+    fn definition(
+        &self,
+        params: lsp_types::GotoDefinitionParams,
+    ) -> Result<Option<GotoDefinitionResponse>, RequestError> {
+        self.with_document(&params.text_document_position_params, |document, offset| {
+            let Symbol::Agent(agent) = document.symbol_at(offset)? else {
+                return None;
+            };
+            let declaration = document
+                .module
+                .declarations
+                .iter()
+                .find(|declaration| *declaration.agent.as_ref() == agent)
+                .map(|declaration| declaration.agent.as_unit());
+            let span = declaration.or_else(|| {
+                document.rules_of(agent).next().map(|rule| {
+                    let pair = &rule.term_pair;
+                    if *pair.left.agent.as_ref() == agent {
+                        pair.left.agent.as_unit()
+                    } else {
+                        pair.right.agent.as_unit()
+                    }
+                })
+            })?;
+            Some(GotoDefinitionResponse::Scalar(document.location(&span)))
+        })
+    }
+
+    /// 交互器的全部规则
+    fn implementation(
+        &self,
+        params: lsp_types::request::GotoImplementationParams,
+    ) -> Result<Option<GotoDefinitionResponse>, RequestError> {
+        self.with_document(&params.text_document_position_params, |document, offset| {
+            let Symbol::Agent(agent) = document.symbol_at(offset)? else {
+                return None;
+            };
+            let locations = document
+                .rules_of(agent)
+                .map(|rule| document.location(&rule.as_unit()))
+                .collect();
+            Some(GotoDefinitionResponse::Array(locations))
+        })
+    }
+
+    /// 交互器或变量的全部出现
+    fn references(
+        &self,
+        params: lsp_types::ReferenceParams,
+    ) -> Result<Option<Vec<Location>>, RequestError> {
+        self.with_document(&params.text_document_position, |document, offset| {
+            let symbol = document.symbol_at(offset)?;
+            let locations = document
+                .occurrences
+                .iter()
+                .filter(|occurrence| occurrence.symbol == symbol)
+                .map(|occurrence| document.location(&occurrence.span))
+                .collect();
+            Some(locations)
+        })
+    }
+
+    /// 交互器的端口数量与极性
+    fn hover(&self, params: lsp_types::HoverParams) -> Result<Option<Hover>, RequestError> {
+        self.with_document(&params.text_document_position_params, |document, offset| {
+            let occurrence = document.occurrence_at(offset)?;
+            let Symbol::Agent(agent) = occurrence.symbol else {
+                return None;
+            };
+            let signatures = semantic::infer_signatures(document.module, &mut vec![]);
+            let signature = signatures.get(agent)?;
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: describe_agent(document.module, agent, signature),
+                }),
+                range: Some(document.index.range(&occurrence.span)),
+            })
+        })
+    }
+
+    fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> Result<Option<PrepareRenameResponse>, RequestError> {
+        self.with_document(&params, |document, offset| {
+            let occurrence = document.occurrence_at(offset)?;
+            match occurrence.symbol {
+                Symbol::Variable(..) => Some(PrepareRenameResponse::Range(
+                    document.index.range(&occurrence.span),
+                )),
+                Symbol::Agent(_) => None,
+            }
+        })
+    }
+
+    /// 在变量所在的规则、网络或顶级语句中重命名变量
+    fn rename(
+        &self,
+        params: lsp_types::RenameParams,
+    ) -> Result<Option<WorkspaceEdit>, RequestError> {
+        // 允许输入时带上变量的前缀
+        let new_name = params.new_name.trim_start_matches(['#', '@']);
+        if !is_ident(new_name) {
+            return Err((
+                ErrorCode::InvalidParams,
+                format!("`{}` is not a valid variable name", params.new_name),
+            ));
+        }
+
+        let edit = self.with_document(&params.text_document_position, |document, offset| {
+            let Symbol::Variable(scope, name) = document.symbol_at(offset)? else {
+                return None;
+            };
+            let taken = document.occurrences.iter().any(|occurrence| {
+                occurrence.symbol == Symbol::Variable(scope, new_name) && new_name != name
+            });
+            if taken {
+                return Some(Err((
+                    ErrorCode::InvalidParams,
+                    format!("variable `{}` already exists", new_name),
+                )));
+            }
+
+            let edits = document
+                .occurrences
+                .iter()
+                .filter(|occurrence| occurrence.symbol == Symbol::Variable(scope, name))
+                .map(|occurrence| TextEdit {
+                    range: document.index.range(&occurrence.span),
+                    new_text: new_name.to_string(),
+                })
+                .collect();
+            let changes = HashMap::from([(document.uri.clone(), edits)]);
+            Some(Ok(WorkspaceEdit::new(changes)))
+        })?;
+        edit.transpose()
+    }
+}
+
+/// `filename` 是 `uri` 对应的文件名，其他文件中的相关位置按路径转换为 URI
+fn to_lsp_diagnostic(
+    uri: &Url,
+    filename: &str,
+    index: &LineIndex,
+    diagnostic: &Diagnostic,
+) -> lsp_types::Diagnostic {
+    let range = |location: &diagnostic::Location| {
+        Range::new(index.position(location.start), index.position(location.end))
+    };
+    let related_location = |location: &diagnostic::Location| {
+        if location.file == filename {
+            return Some(Location::new(uri.clone(), range(location)));
+        }
+        // 其他文件的行列号不能用这个文档的 `index` 转换
+        let path = std::fs::canonicalize(&location.file).ok()?;
+        let source = std::fs::read_to_string(&path).ok()?;
+        let index = LineIndex::new(&source);
+        let range = Range::new(index.position(location.start), index.position(location.end));
+        Some(Location::new(Url::from_file_path(path).ok()?, range))
+    };
+    let message = match &diagnostic.label {
+        Some(label) => format!("{}\n{}", diagnostic.message, label),
+        None => diagnostic.message.clone(),
+    };
+    let related = diagnostic
+        .related
+        .iter()
+        .filter_map(|label| {
+            Some(DiagnosticRelatedInformation {
+                location: related_location(&label.location)?,
+                message: label.message.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    lsp_types::Diagnostic {
+        range: diagnostic.location.as_ref().map(range).unwrap_or_default(),
+        severity: Some(match diagnostic.severity {
+            diagnostic::Severity::Error => DiagnosticSeverity::ERROR,
+            diagnostic::Severity::Warning => DiagnosticSeverity::WARNING,
+        }),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some("zamuza".to_string()),
+        message,
+        related_information: (!related.is_empty()).then_some(related),
+        ..Default::default()
+    }
+}
+
+/// 以声明的形式展示交互器的签名
+fn describe_agent(module: &ast::Module, agent: &str, signature: &semantic::Signature) -> String {
+    let declaration = module
+        .declarations
+        .iter()
+        .find(|declaration| *declaration.agent.as_ref() == agent);
+    let ports = signature
+        .ports
+        .iter()
+        .enumerate()
+        .map(|(i, polarity)| {
+            let sigil = polarity.map(|p| p.sigil()).unwrap_or('?');
+            match declaration.and_then(|declaration| declaration.ports.get(i)) {
+                Some(port) => format!("{}{}", sigil, port.as_name()),
+                None => format!("{}{}", sigil, i + 1),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut text = String::from("```zamuza\nagent ");
+    text.push_str(agent);
+    if !ports.is_empty() {
+        text.push_str(&format!("({})", ports.join(", ")));
+    }
+    text.push_str("\n```\n");
+
+    let kind = match signature.principal {
+        Some(semantic::Polarity::In) => "constructor",
+        Some(semantic::Polarity::Out) => "reducer",
+        None => "unknown kind",
+    };
+    let builtin = if semantic::is_builtin(agent) {
+        "built-in "
+    } else {
+        ""
+    };
+    text.push_str(&format!(
+        "{}{}, arity {}",
+        builtin,
+        kind,
+        signature.ports.len()
+    ));
+    text
+}
+
+fn is_ident(name: &str) -> bool {
+    let body = name.trim_end_matches('\'');
+    let mut chars = body.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// 变量的作用域
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scope {
+    Rule(usize),
+    Net(usize),
+    TopLevel,
+}
+
+/// 名称所指的对象
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Symbol<'a> {
+    Agent(&'a str),
+    Variable(Scope, &'a str),
+}
+
+/// 名称的一次出现，位置不含变量的前缀
+struct Occurrence<'a> {
+    symbol: Symbol<'a>,
+    span: Span<'a, ()>,
+}
+
+fn occurrences<'a>(module: &'a ast::Module<'a>) -> Vec<Occurrence<'a>> {
+    fn agent<'a>(occurrences: &mut Vec<Occurrence<'a>>, name: &'a Span<'a, &'a str>) {
+        occurrences.push(Occurrence {
+            symbol: Symbol::Agent(name.as_ref()),
+            span: name.as_unit(),
+        })
+    }
+    fn variable<'a>(
+        occurrences: &mut Vec<Occurrence<'a>>,
+        scope: Scope,
+        name: &'a Span<'a, ast::Name<'a>>,
+    ) {
+        let (ast::Name::In(ident) | ast::Name::Out(ident)) = name.as_ref();
+        occurrences.push(Occurrence {
+            symbol: Symbol::Variable(scope, ident.as_ref()),
+            span: ident.as_unit(),
+        })
+    }
+    fn term<'a>(occurrences: &mut Vec<Occurrence<'a>>, scope: Scope, t: &'a ast::Term<'a>) {
+        match t {
+            ast::Term::Name(name) => variable(occurrences, scope, name),
+            ast::Term::Int(_) => {}
+            ast::Term::Agent(a) => {
+                agent(occurrences, &a.name);
+                for t in &a.body {
+                    term(occurrences, scope, t);
+                }
+            }
+            ast::Term::Literal(literal) => {
+                for t in literal.terms() {
+                    term(occurrences, scope, t);
+                }
+            }
+        }
+    }
+    fn rule_term<'a>(
+        occurrences: &mut Vec<Occurrence<'a>>,
+        scope: Scope,
+        t: &'a ast::RuleTerm<'a>,
+    ) {
+        agent(occurrences, &t.agent);
+        for pattern in &t.body {
+            match pattern {
+                ast::Pattern::Name(name) => variable(occurrences, scope, name),
+                ast::Pattern::Wildcard(_) => {}
+                ast::Pattern::Agent(t) => rule_term(occurrences, scope, t),
+            }
+        }
+    }
+    fn equations<'a>(
+        occurrences: &mut Vec<Occurrence<'a>>,
+        scope: Scope,
+        equations: &'a [Span<'a, ast::Equation<'a>>],
+    ) {
+        for equation in equations {
+            term(occurrences, scope, &equation.left);
+            term(occurrences, scope, &equation.right);
+        }
+    }
+
+    let mut occurrences = vec![];
+    for sugar in &module.sugars {
+        for name in &sugar.agents {
+            agent(&mut occurrences, name);
+        }
+    }
+    for declaration in &module.declarations {
+        agent(&mut occurrences, &declaration.agent);
+    }
+    for (i, rule) in module.rules.iter().enumerate() {
+        let scope = Scope::Rule(i);
+        for t in [&rule.term_pair.left, &rule.term_pair.right] {
+            rule_term(&mut occurrences, scope, t);
+        }
+        equations(&mut occurrences, scope, &rule.equations);
+    }
+    for (i, net) in module.nets.iter().enumerate() {
+        let scope = Scope::Net(i);
+        for interface in &net.interfaces {
+            term(&mut occurrences, scope, interface);
+        }
+        equations(&mut occurrences, scope, &net.equations);
+    }
+    equations(&mut occurrences, Scope::TopLevel, &module.statements);
+    for output in &module.outputs {
+        term(&mut occurrences, Scope::TopLevel, output);
+    }
+    occurrences
+}
+
+/// 解析后的文档
+struct Document<'a> {
+    uri: &'a Url,
+    index: LineIndex<'a>,
+    module: &'a ast::Module<'a>,
+    occurrences: Vec<Occurrence<'a>>,
+}
+
+impl<'a> Document<'a> {
+    fn occurrence_at(&self, offset: usize) -> Option<&Occurrence<'a>> {
+        self.occurrences
+            .iter()
+            .find(|occurrence| occurrence.span.start <= offset && offset <= occurrence.span.end)
+    }
+
+    fn symbol_at(&self, offset: usize) -> Option<Symbol<'a>> {
+        self.occurrence_at(offset)
+            .map(|occurrence| occurrence.symbol)
+    }
+
+    /// 左侧或右侧为 `agent` 的规则
+    fn rules_of<'s>(
+        &'s self,
+        agent: &'s str,
+    ) -> impl Iterator<Item = &'a Span<'a, ast::Rule<'a>>> + 's {
+        self.module.rules.iter().filter(move |rule| {
+            *rule.term_pair.left.agent.as_ref() == agent
+                || *rule.term_pair.right.agent.as_ref() == agent
+        })
+    }
+
+    fn location(&self, span: &Span<()>) -> Location {
+        Location::new(self.uri.clone(), self.index.range(span))
+    }
+}
+
+/// 字节偏移与 LSP 位置（行号与 UTF-16 列号）的转换
+struct LineIndex<'a> {
+    text: &'a str,
+    /// 每一行的起始偏移
+    lines: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    fn new(text: &'a str) -> Self {
+        let lines = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self { text, lines }
+    }
+
+    fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.text.len());
+        let line = self.lines.partition_point(|&start| start <= offset) - 1;
+        let character = self.text[self.lines[line]..offset].encode_utf16().count();
+        Position::new(line as u32, character as u32)
+    }
+
+    fn offset(&self, position: Position) -> usize {
+        let Some(&start) = self.lines.get(position.line as usize) else {
+            return self.text.len();
+        };
+        let mut character = 0;
+        for (i, c) in self.text[start..].char_indices() {
+            if character >= position.character as usize || c == '\n' {
+                return start + i;
+            }
+            character += c.len_utf16();
+        }
+        self.text.len()
+    }
+
+    /// 片段的范围，不含末尾的空白
+    fn range(&self, span: &Span<()>) -> Range {
+        let end = span.start + self.text[span.start..span.end].trim_end().len();
+        Range::new(self.position(span.start), self.position(end))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lsp_server::RequestId;
+    use lsp_types::{
+        notification::Initialized,
+        request::{Initialize, Shutdown},
+        DidOpenTextDocumentParams, HoverParams, RenameParams, TextDocumentIdentifier,
+        TextDocumentItem,
+    };
+
+    const NAT: &str = "agent Zero
+agent S(#pred)
+
+S(#x) >> Add(#y, @w) => #x -> Add(#y, @z), @w <- S(#z)
+Zero >> Add(#x, @y) => #x -> @y
+
+Main <| #r |>
+    S(Zero) -> Add(S(Zero), @r)
+";
+
+    /// 通过内存中的连接与语言服务器通信的客户端
+    struct Client {
+        connection: Connection,
+        server: Option<std::thread::JoinHandle<()>>,
+        uri: Url,
+        text: String,
+        next_id: i32,
+    }
+
+    impl Client {
+        /// 启动服务器，完成初始化并打开文档
+        fn open(text: &str) -> Self {
+            let (server, connection) = Connection::memory();
+            let server = std::thread::spawn(move || serve(&server, vec![]).unwrap());
+            let mut client = Client {
+                connection,
+                server: Some(server),
+                uri: Url::parse("file:///nonexistent/test.zz").unwrap(),
+                text: text.to_string(),
+                next_id: 0,
+            };
+            client.request::<Initialize>(Default::default()).unwrap();
+            client.notify::<Initialized>(lsp_types::InitializedParams {});
+            client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem::new(
+                    client.uri.clone(),
+                    "zamuza".to_string(),
+                    0,
+                    text.to_string(),
+                ),
+            });
+            client
+        }
+
+        fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.to_string(), params);
+            self.connection.sender.send(notification.into()).unwrap();
+        }
+
+        /// 下一条服务器发来的消息
+        fn receive(&self) -> Message {
+            self.connection
+                .receiver
+                .recv_timeout(std::time::Duration::from_secs(10))
+                .unwrap()
+        }
+
+        fn request<R: lsp_types::request::Request>(
+            &mut self,
+            params: R::Params,
+        ) -> Result<R::Result, String> {
+            let id = RequestId::from(self.next_id);
+            self.next_id += 1;
+            let request = Request::new(id.clone(), R::METHOD.to_string(), params);
+            self.connection.sender.send(request.into()).unwrap();
+            loop {
+                let Message::Response(response) = self.receive() else {
+                    continue;
+                };
+                assert_eq!(response.id, id);
+                return match response.error {
+                    Some(error) => Err(error.message),
+                    None => Ok(serde_json::from_value(response.result.unwrap()).unwrap()),
+                };
+            }
+        }
+
+        fn diagnostics(&self) -> Vec<lsp_types::Diagnostic> {
+            let Message::Notification(notification) = self.receive() else {
+                panic!("expected diagnostics");
+            };
+            assert_eq!(notification.method, PublishDiagnostics::METHOD);
+            let params: PublishDiagnosticsParams =
+                serde_json::from_value(notification.params).unwrap();
+            params.diagnostics
+        }
+
+        /// 文档中第 `nth` 个 `needle` 的起始位置
+        fn position(&self, needle: &str, nth: usize) -> TextDocumentPositionParams {
+            let (offset, _) = self.text.match_indices(needle).nth(nth).unwrap();
+            TextDocumentPositionParams::new(
+                TextDocumentIdentifier::new(self.uri.clone()),
+                LineIndex::new(&self.text).position(offset),
+            )
+        }
+
+        /// 重命名第 `nth` 个变量 `name`，`name` 带有前缀
+        fn rename(&mut self, name: &str, nth: usize, new_name: &str) -> Result<Vec<Range>, String> {
+            // 变量的位置不含前缀
+            let mut position = self.position(name, nth);
+            position.position.character += 1;
+            let edit = self.request::<Rename>(RenameParams {
+                text_document_position: position,
+                new_name: new_name.to_string(),
+                work_done_progress_params: Default::default(),
+            })?;
+            let mut changes = edit.unwrap().changes.unwrap();
+            let edits = changes.remove(&self.uri).unwrap();
+            let new_name = new_name.trim_start_matches(['#', '@']);
+            assert!(edits.iter().all(|edit| edit.new_text == new_name));
+            Ok(edits.into_iter().map(|edit| edit.range).collect())
+        }
+    }
+
+    impl Drop for Client {
+        fn drop(&mut self) {
+            if std::thread::panicking() {
+                return;
+            }
+            self.request::<Shutdown>(()).unwrap();
+            self.notify::<lsp_types::notification::Exit>(());
+            self.server.take().unwrap().join().unwrap();
+        }
+    }
+
+    fn line(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    #[test]
+    fn diagnostics() {
+        let client = Client::open(NAT);
+        assert!(client.diagnostics().is_empty());
+
+        let client = Client::open("Main <| #r |> Foo(@x) -> @r\n");
+        let diagnostics = client.diagnostics();
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(
+            diagnostic.code,
+            Some(NumberOrString::String("variable-count".to_string()))
+        );
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::ERROR));
+        assert_eq!(diagnostic.range, line(0, 18, 20));
+    }
+
+    #[test]
+    fn definition() {
+        let mut client = Client::open(NAT);
+        client.diagnostics();
+        let response = client
+            .request::<GotoDefinition>(lsp_types::GotoDefinitionParams {
+                text_document_position_params: client.position("S(Zero)", 0),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap();
+        let Some(GotoDefinitionResponse::Scalar(location)) = response else {
+            panic!("expected a single location, got {:?}", response);
+        };
+        assert_eq!(location.range, line(1, 6, 7));
+
+        // 没有声明的交互器跳转到第一条规则
+        let response = client
+            .request::<GotoDefinition>(lsp_types::GotoDefinitionParams {
+                text_document_position_params: client.position("Add(S", 0),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap();
+        let Some(GotoDefinitionResponse::Scalar(location)) = response else {
+            panic!("expected a single location, got {:?}", response);
+        };
+        assert_eq!(location.range, line(3, 9, 12));
+    }
+
+    #[test]
+    fn implementation() {
+        let mut client = Client::open(NAT);
+        client.diagnostics();
+        let response = client
+            .request::<GotoImplementation>(lsp_types::request::GotoImplementationParams {
+                text_document_position_params: client.position("Add", 0),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+            })
+            .unwrap();
+        let Some(GotoDefinitionResponse::Array(locations)) = response else {
+            panic!("expected a list of locations, got {:?}", response);
+        };
+        let lines: Vec<u32> = locations.iter().map(|l| l.range.start.line).collect();
+        assert_eq!(lines, [3, 4]);
+    }
+
+    #[test]
+    fn hover() {
+        let text = include_str!("../examples/qsort.zz");
+        let mut client = Client::open(text);
+        assert!(client.diagnostics().is_empty());
+        let hover = client
+            .request::<HoverRequest>(HoverParams {
+                text_document_position_params: client.position("Append(#l", 0),
+                work_done_progress_params: Default::default(),
+            })
+            .unwrap()
+            .unwrap();
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("expected markup, got {:?}", hover.contents);
+        };
+        assert!(contents.value.contains("Append(#"), "{}", contents.value);
+    }
+
+    #[test]
+    fn rename_collision() {
+        let mut client = Client::open(NAT);
+        client.diagnostics();
+        let error = client.rename("#y", 0, "x").unwrap_err();
+        assert_eq!(error, "variable `x` already exists");
+        // 另一条规则中的同名变量不冲突
+        assert!(client.rename("#y", 0, "#v").is_ok());
+    }
+
+    #[test]
+    fn rename_across_scopes() {
+        let mut client = Client::open(NAT);
+        client.diagnostics();
+        // 两条规则中都有 `x`，只重命名光标所在的规则
+        let ranges = client.rename("#x", 2, "@n").unwrap();
+        assert_eq!(ranges, [line(4, 13, 14), line(4, 24, 25)]);
+    }
+}
@@ -1,20 +1,32 @@
 use anyhow::Result;
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use std::io::Read;
+use std::io::{IsTerminal, Read};
+use zamuza::backend::interpreter::RuntimeError;
 use zamuza::backend::target;
+use zamuza::diagnostic::Diagnostics;
+use zamuza::options::Snapshot;
+use zamuza::testing;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    /// How to print errors and warnings
+    #[clap(long, global = true, default_value = "human")]
+    message_format: MessageFormat,
+
+    /// Directory to search for imported files after the importing file's own
+    /// directory, may be given multiple times
+    #[clap(short = 'I', long = "import-path", global = true)]
+    import_paths: Vec<std::path::PathBuf>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Run a program
     #[command(visible_alias = "r")]
-    #[cfg(feature = "tinycc")]
     Run {
         /// Source file, pass "-" to read from stdin
         #[clap(value_parser)]
@@ -44,9 +56,60 @@ enum Commands {
         #[clap(short, long, default_value = "c")]
         format: OutputFormat,
 
+        /// Draw the net at runtime instead of the program, after the given
+        /// number of reductions or at the end ("end"); only for `-f dot`
+        #[clap(long, value_parser = parse_snapshot, num_args = 0..=1, default_missing_value = "end")]
+        snapshot: Option<Snapshot>,
+
+        /// Memory allocator of the generated runtime
+        #[clap(long, default_value = "pool")]
+        allocator: Allocator,
+
         #[clap(flatten)]
         options: Options,
     },
+    /// Step through a program interactively
+    #[command(visible_alias = "d")]
+    Debug {
+        /// Source file
+        #[clap(value_parser)]
+        inputs: Vec<clio::Input>,
+    },
+    /// Format source files in place
+    Fmt {
+        /// Source file, pass "-" to format stdin to stdout
+        #[clap(value_parser)]
+        inputs: Vec<clio::Input>,
+
+        /// Only check whether the files are formatted, exit with 1 if not
+        #[clap(long)]
+        check: bool,
+    },
+    /// Run programs and compare their output with the expected output
+    Test {
+        /// Test files or directories to search for `.zz` files, defaults to
+        /// the current directory
+        paths: Vec<std::path::PathBuf>,
+
+        /// Write the actual output as the expected output
+        #[clap(long)]
+        bless: bool,
+
+        /// Maximum size of the runtime stack, unlimited by default
+        #[clap(long)]
+        max_stack_size: Option<usize>,
+
+        /// Fail a test after this many reductions, unlimited by default
+        #[clap(long)]
+        max_reductions: Option<usize>,
+
+        /// Fail a test when more agents than this are alive, unlimited by
+        /// default
+        #[clap(long)]
+        max_agents: Option<usize>,
+    },
+    /// Start a language server on stdin and stdout
+    Lsp,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
@@ -56,72 +119,356 @@ enum OutputFormat {
     /// Executable
     #[cfg(feature = "tinycc")]
     Exe,
+    /// Graphviz DOT graph
+    Dot,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum MessageFormat {
+    /// Colored snippets
+    Human,
+    /// One JSON object per line on stderr
+    Json,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum ResultFormat {
+    /// `Cons(1, Nil)`
+    Text,
+    /// S-expressions, `(Cons 1 Nil)`
+    Sexp,
+    /// One JSON value per result, `{"agent":"Cons","ports":[1,...]}`
+    Json,
+}
+
+impl From<ResultFormat> for zamuza::options::ResultFormat {
+    fn from(format: ResultFormat) -> Self {
+        match format {
+            ResultFormat::Text => Self::Text,
+            ResultFormat::Sexp => Self::Sexp,
+            ResultFormat::Json => Self::Json,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+enum Allocator {
+    /// Size-class free lists carved out of large chunks
+    Pool,
+    /// Plain malloc and free
+    Malloc,
+}
+
+impl From<Allocator> for zamuza::options::Allocator {
+    fn from(allocator: Allocator) -> Self {
+        match allocator {
+            Allocator::Pool => Self::Pool,
+            Allocator::Malloc => Self::Malloc,
+        }
+    }
 }
 
 #[derive(Args)]
 struct Options {
-    /// Runtime stack size
+    /// Initial capacity of the runtime stack of the compiled program, which
+    /// grows on demand; only used by `compile`
     #[clap(long, default_value = "1024")]
     stack_size: usize,
 
+    /// Maximum size of the runtime stack, unlimited by default
+    #[clap(long)]
+    max_stack_size: Option<usize>,
+
+    /// Stop with exit status 3 after this many reductions, unlimited by default
+    #[clap(long)]
+    max_reductions: Option<usize>,
+
+    /// Stop with exit status 3 when more agents than this are alive, unlimited
+    /// by default
+    #[clap(long)]
+    max_agents: Option<usize>,
+
     /// Trace reduction
     #[clap(long)]
     trace: bool,
 
+    /// Write each interaction to this file as a line of JSON
+    #[clap(long, value_name = "PATH")]
+    trace_file: Option<std::path::PathBuf>,
+
     /// Output timing information
     #[clap(long)]
     timing: bool,
+
+    /// How to print the results
+    #[clap(long, default_value = "text")]
+    output_format: ResultFormat,
+
+    /// Print subterms deeper than this as "..."
+    #[clap(long, default_value = "1000")]
+    max_depth: usize,
+
+    /// Output how often each rule fired and the peak stack depth and number of
+    /// live agents; with `--timing`, also the time spent in each rule
+    #[clap(long)]
+    profile: bool,
 }
 
 impl From<Options> for zamuza::options::Options {
     fn from(options: Options) -> Self {
         Self {
             stack_size: options.stack_size,
+            max_stack_size: options.max_stack_size,
+            max_reductions: options.max_reductions,
+            max_agents: options.max_agents,
+            allocator: zamuza::options::Allocator::Pool,
             trace: options.trace,
+            trace_file: options.trace_file,
             timing: options.timing,
+            profile: options.profile,
+            result_format: options.output_format.into(),
+            max_depth: options.max_depth,
+            snapshot: None,
         }
     }
 }
 
+fn parse_snapshot(s: &str) -> Result<Snapshot, String> {
+    match s {
+        "end" => Ok(Snapshot::End),
+        _ => s
+            .parse()
+            .map(Snapshot::After)
+            .map_err(|_| format!("expected a reduction count or \"end\", found \"{}\"", s)),
+    }
+}
+
 fn get_filename(input: &clio::Input) -> String {
     if input.is_std() {
         "<stdin>".to_string()
     } else {
-        input.path().to_string()
+        input.path().display().to_string()
+    }
+}
+
+/// Read and check all inputs, then print the warnings
+///
+/// With `--message-format json`, compile errors are printed as JSON and the
+/// process exits instead of returning them.
+fn add_inputs(
+    mut context: zamuza::Context,
+    inputs: Vec<clio::Input>,
+    import_paths: &[std::path::PathBuf],
+    message_format: MessageFormat,
+) -> Result<zamuza::Context> {
+    for path in import_paths {
+        context = context.add_import_path(path);
+    }
+    for mut input in inputs {
+        let filename = get_filename(&input);
+        let mut program = String::new();
+        input.read_to_string(&mut program)?;
+        context = context
+            .add_file(&filename, &program)
+            .map_err(|e| report_compile_error(e, message_format))?;
+    }
+    for warning in context.warnings() {
+        match message_format {
+            MessageFormat::Human => eprintln!("{}", warning),
+            MessageFormat::Json => eprintln!("{}", warning.to_json()),
+        }
+    }
+    Ok(context)
+}
+
+/// With `--message-format json`, print compile errors as JSON and exit;
+/// otherwise pass the error on
+fn report_compile_error(e: anyhow::Error, message_format: MessageFormat) -> anyhow::Error {
+    if let (MessageFormat::Json, Some(Diagnostics(errors))) = (message_format, e.downcast_ref()) {
+        for error in errors {
+            eprintln!("{}", error.to_json());
+        }
+        std::process::exit(1);
+    }
+    e
+}
+
+/// Format the inputs in place, or report the unformatted ones with `--check`
+fn format_inputs(
+    inputs: Vec<clio::Input>,
+    check: bool,
+    message_format: MessageFormat,
+) -> Result<()> {
+    let mut unformatted = 0;
+    for mut input in inputs {
+        let filename = get_filename(&input);
+        let mut program = String::new();
+        input.read_to_string(&mut program)?;
+        let formatted =
+            zamuza::frontend::format::format(&program, &filename).map_err(|errors| {
+                let errors = errors.iter().map(|error| error.to_diagnostic()).collect();
+                report_compile_error(Diagnostics(errors).into(), message_format)
+            })?;
+
+        if check {
+            if formatted != program {
+                eprintln!("{} is not formatted", filename);
+                unformatted += 1;
+            }
+        } else if input.is_std() {
+            print!("{}", formatted);
+        } else if formatted != program {
+            std::fs::write(input.path().as_os_str(), formatted)?;
+        }
+    }
+    if unformatted > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Wrap `text` in an ANSI colour code when `colour` is set
+fn paint(text: &str, code: u8, colour: bool) -> String {
+    if colour {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Run the golden tests and report like `cargo test`, exit with 1 on failure
+///
+/// The labels are coloured only when `colour` is set.
+fn run_tests(
+    paths: Vec<std::path::PathBuf>,
+    bless: bool,
+    options: zamuza::options::Options,
+    import_paths: &[std::path::PathBuf],
+    colour: bool,
+) -> Result<()> {
+    let (ok, failed) = (paint("ok", 32, colour), paint("FAILED", 31, colour));
+    let paths = if paths.is_empty() {
+        vec![".".into()]
+    } else {
+        paths
+    };
+    let files = testing::discover(&paths)?;
+
+    println!("running {} tests", files.len());
+    let (mut passed, mut ignored, mut blessed) = (0, 0, 0);
+    let mut failures = vec![];
+    for path in files {
+        let mut test = testing::Golden::load(path)?;
+        let path = test.path();
+        let name = path.strip_prefix(".").unwrap_or(path).display().to_string();
+        let expected = test.expected()?;
+        let context = import_paths.iter().fold(
+            zamuza::Context::new().set_options(options.clone()),
+            |context, path| context.add_import_path(path),
+        );
+        let actual = test.run(context);
+
+        match expected {
+            Some(expected) if expected.lines().eq(actual.lines()) => {
+                println!("test {} ... {}", name, ok);
+                passed += 1;
+            }
+            _ if bless => match test.bless(&actual) {
+                Ok(()) => {
+                    println!("test {} ... {}", name, paint("blessed", 33, colour));
+                    blessed += 1;
+                }
+                Err(e) => {
+                    println!("test {} ... {}", name, failed);
+                    failures.push((name, format!("{}\n", e)));
+                }
+            },
+            Some(expected) => {
+                println!("test {} ... {}", name, failed);
+                failures.push((name, testing::diff(&expected, &actual)));
+            }
+            None => {
+                println!(
+                    "test {} ... {}, no expected output",
+                    name,
+                    paint("ignored", 33, colour)
+                );
+                ignored += 1;
+            }
+        }
     }
+
+    if !failures.is_empty() {
+        println!("\nfailures:");
+        for (name, diff) in &failures {
+            println!("\n---- {} ----\n{}", name, diff);
+        }
+        println!("failures:");
+        for (name, _) in &failures {
+            println!("    {}", name);
+        }
+    }
+
+    let result = if failures.is_empty() { ok } else { failed };
+    println!(
+        "\ntest result: {}. {} passed; {} failed; {} ignored; {} blessed",
+        result,
+        passed,
+        failures.len(),
+        ignored,
+        blessed
+    );
+    if !failures.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// Exit status when a reduction or agent limit is exceeded, the same as the
+/// generated C program's
+const LIMIT_EXIT_STATUS: i32 = 3;
+
+/// Exit on a runtime error like the generated C program does; the message has
+/// already been written to stderr by `Context::run`
+fn exit_on_runtime_error(e: anyhow::Error) -> Result<()> {
+    let Some(e) = e.downcast_ref::<RuntimeError>() else {
+        return Err(e);
+    };
+    std::process::exit(if e.is_limit() { LIMIT_EXIT_STATUS } else { 1 });
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
     match args.command {
-        #[cfg(feature = "tinycc")]
         Commands::Run { inputs, options } => {
-            let mut context = zamuza::Context::new().set_options(options.into());
+            let context = zamuza::Context::new().set_options(options.into());
 
-            for mut input in inputs {
-                let filename = get_filename(&input);
-                let mut program = String::new();
-                input.read_to_string(&mut program)?;
-                context = context.add_file(&filename, &program)?;
-            }
+            let context = add_inputs(context, inputs, &args.import_paths, args.message_format)?;
 
-            context.run()?;
+            if let Err(e) = context.run() {
+                exit_on_runtime_error(e)?;
+            }
         }
         Commands::Compile {
             inputs,
             output,
             format,
+            snapshot,
+            allocator,
             options,
         } => {
-            let mut context = zamuza::Context::new().set_options(options.into());
-
-            for mut input in inputs {
-                let filename = get_filename(&input);
-                let mut program = String::new();
-                input.read_to_string(&mut program)?;
-                context = context.add_file(&filename, &program)?;
+            if snapshot.is_some() && format != OutputFormat::Dot {
+                anyhow::bail!("`--snapshot` is only supported with `-f dot`");
             }
+            let options = zamuza::options::Options {
+                snapshot,
+                allocator: allocator.into(),
+                ..options.into()
+            };
+            let context = zamuza::Context::new().set_options(options);
+
+            let context = add_inputs(context, inputs, &args.import_paths, args.message_format)?;
 
             if output.is_file() {
                 // write to file
@@ -129,6 +476,7 @@ fn main() -> Result<()> {
                     #[cfg(feature = "tinycc")]
                     OutputFormat::Exe => context.output_file::<target::Exe>(output.as_os_str())?,
                     OutputFormat::C => context.output_file::<target::C>(output.as_os_str())?,
+                    OutputFormat::Dot => context.output_file::<target::Dot>(output.as_os_str())?,
                 }
             } else {
                 // write to stream
@@ -137,9 +485,36 @@ fn main() -> Result<()> {
                     #[cfg(feature = "tinycc")]
                     OutputFormat::Exe => context.output_stream::<target::Exe>(output)?,
                     OutputFormat::C => context.output_stream::<target::C>(output)?,
+                    OutputFormat::Dot => context.output_stream::<target::Dot>(output)?,
                 }
             }
         }
+        Commands::Debug { inputs } => {
+            let context = zamuza::Context::new();
+
+            let context = add_inputs(context, inputs, &args.import_paths, args.message_format)?;
+
+            context.debug(std::io::stdin().lock(), std::io::stdout())?;
+        }
+        Commands::Fmt { inputs, check } => format_inputs(inputs, check, args.message_format)?,
+        Commands::Test {
+            paths,
+            bless,
+            max_stack_size,
+            max_reductions,
+            max_agents,
+        } => {
+            let options = zamuza::options::Options {
+                max_stack_size,
+                max_reductions,
+                max_agents,
+                ..Default::default()
+            };
+            let colour =
+                args.message_format == MessageFormat::Human && std::io::stdout().is_terminal();
+            run_tests(paths, bless, options, &args.import_paths, colour)?
+        }
+        Commands::Lsp => zamuza::lsp::run(args.import_paths)?,
     };
 
     Ok(())
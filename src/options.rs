@@ -3,20 +3,78 @@
 /// 运行时选项。
 #[derive(Clone, Debug)]
 pub struct Options {
-    /// 运行时栈大小。
+    /// 生成的 C 程序中运行时栈的初始容量，栈会按需增长。解释器不使用此项。
     pub stack_size: usize,
+    /// 运行时栈大小的上限，`None` 表示不限制。
+    pub max_stack_size: Option<usize>,
+    /// 规约次数的上限，`None` 表示不限制。
+    pub max_reductions: Option<usize>,
+    /// 存活交互器数量的上限，`None` 表示不限制。
+    pub max_agents: Option<usize>,
     /// 跟踪规约过程。
     pub trace: bool,
+    /// 将每次相互作用以 JSON 行的格式写入的文件。
+    pub trace_file: Option<std::path::PathBuf>,
     /// 输出效率信息。
     pub timing: bool,
+    /// 输出每条规则的应用次数以及方程栈与交互器数量的峰值，同时计时时还输出每条规则花费的时间。
+    pub profile: bool,
+    /// C 运行时使用的内存分配器。
+    pub allocator: Allocator,
+    /// 绘制运行时网络的时机，仅用于 DOT 目标。
+    pub snapshot: Option<Snapshot>,
+    /// 结果的输出格式。
+    pub result_format: ResultFormat,
+    /// 输出结果时的最大深度，更深的部分显示为 `...`。
+    pub max_depth: usize,
+}
+
+/// 结果的输出格式。
+///
+/// 被多次引用的子项与环只输出一次，以 `#1=` 标记，之后以 `#1#` 引用；JSON 中分别为 `"label"` 字段与 `{"ref": 1}`。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultFormat {
+    /// `Cons(1, Nil)`
+    Text,
+    /// S 表达式 `(Cons 1 Nil)`
+    Sexp,
+    /// JSON，交互器为 `{"agent": "Cons", "ports": [...]}`，变量为 `{"name": "x_17"}`
+    Json,
+}
+
+/// C 运行时使用的内存分配器。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Allocator {
+    /// 按大小分类的空闲链表，从大块内存中分配
+    Pool,
+    /// 直接使用 `malloc` 与 `free`
+    Malloc,
+}
+
+/// 绘制运行时网络的时机。
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Snapshot {
+    /// 规约结束后
+    End,
+    /// 规约次数达到给定值后
+    After(usize),
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             stack_size: 1024,
+            max_stack_size: None,
+            max_reductions: None,
+            max_agents: None,
             trace: false,
+            trace_file: None,
             timing: false,
+            profile: false,
+            allocator: Allocator::Pool,
+            snapshot: None,
+            result_format: ResultFormat::Text,
+            max_depth: 1000,
         }
     }
 }
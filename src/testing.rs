@@ -0,0 +1,370 @@
+//! 黄金输出测试。
+//!
+//! 每个 `.zz` 文件是一个测试，期望的输出来自文件中的指令注释：
+//!
+//! ```text
+//! /* expect: S(S(S(Zero))) */
+//! ```
+//!
+//! 每条指令对应输出的一行，按出现顺序排列。没有指令时使用同名的 `.out` 文件。
+//! 编译或运行出错时，实际输出是 `error: ` 加上错误信息与行列号，每个错误一行，
+//! 因此出错的程序同样可以测试。
+
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+use crate::diagnostic::{Diagnostic, Diagnostics};
+use crate::Context;
+
+const DIRECTIVE: &str = "expect:";
+
+/// 递归查找路径中的 `.zz` 文件，忽略隐藏的文件与目录
+///
+/// 直接给出的文件总会包含在内。结果按路径排序。
+pub fn discover(paths: &[PathBuf]) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = vec![];
+    for path in paths {
+        if path.is_dir() {
+            discover_dir(path, &mut files)?;
+        } else {
+            files.push(path.clone());
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+fn discover_dir(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let hidden = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            discover_dir(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "zz") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// 一个测试
+#[derive(Clone, Debug)]
+pub struct Golden {
+    path: PathBuf,
+    source: String,
+    /// 各条指令中期望输出所在的范围
+    directives: Vec<Range<usize>>,
+}
+
+/// 无法更新期望输出
+#[derive(Debug, thiserror::Error)]
+#[error("{path} has {directives} `expect` directives but the program printed {lines} lines, update them by hand")]
+pub struct BlessError {
+    path: String,
+    directives: usize,
+    lines: usize,
+}
+
+impl Golden {
+    /// 读取测试文件
+    pub fn load(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let source = std::fs::read_to_string(&path)?;
+        let directives = directives(&source);
+        Ok(Self {
+            path,
+            source,
+            directives,
+        })
+    }
+
+    /// 测试文件的路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// 同名的 `.out` 文件
+    pub fn out_path(&self) -> PathBuf {
+        self.path.with_extension("out")
+    }
+
+    /// 期望的输出，没有指令也没有 `.out` 文件时返回 `None`
+    pub fn expected(&self) -> std::io::Result<Option<String>> {
+        if !self.directives.is_empty() {
+            let mut expected = String::new();
+            for range in &self.directives {
+                for line in self.source[range.clone()].trim().lines() {
+                    expected.push_str(line.trim());
+                    expected.push('\n');
+                }
+            }
+            return Ok(Some(expected));
+        }
+        match std::fs::read_to_string(self.out_path()) {
+            Ok(expected) => Ok(Some(expected)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 在给定的上下文中编译并在解释器中运行，返回实际的输出
+    pub fn run(&self, context: Context) -> String {
+        let filename = self.path.display().to_string();
+        let result = context
+            .add_file(&filename, &self.source)
+            .and_then(Context::evaluate);
+
+        let lines = match result {
+            Ok(outputs) => outputs,
+            Err(e) => match e.downcast_ref::<Diagnostics>() {
+                Some(Diagnostics(errors)) => errors.iter().map(error_line).collect(),
+                None => vec![format!("error: {}", e)],
+            },
+        };
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// 以实际的输出更新期望输出
+    ///
+    /// 文件中有指令时逐条替换，此时输出的行数必须与指令数相同；否则写入 `.out` 文件。
+    pub fn bless(&mut self, actual: &str) -> Result<()> {
+        if self.directives.is_empty() {
+            std::fs::write(self.out_path(), actual)?;
+            return Ok(());
+        }
+
+        let lines: Vec<&str> = actual.lines().collect();
+        if lines.len() != self.directives.len() {
+            return Err(BlessError {
+                path: self.path.display().to_string(),
+                directives: self.directives.len(),
+                lines: lines.len(),
+            }
+            .into());
+        }
+
+        let mut source = String::new();
+        let mut last = 0;
+        for (range, line) in self.directives.iter().zip(lines) {
+            source.push_str(&self.source[last..range.start]);
+            source.push(' ');
+            source.push_str(line);
+            source.push(' ');
+            last = range.end;
+        }
+        source.push_str(&self.source[last..]);
+
+        if source != self.source {
+            std::fs::write(&self.path, &source)?;
+            self.directives = directives(&source);
+            self.source = source;
+        }
+        Ok(())
+    }
+}
+
+/// 编译错误的单行形式，不含文件名，测试移动位置后依然有效
+fn error_line(error: &Diagnostic) -> String {
+    match (&error.location, &error.label) {
+        (Some(location), Some(label)) => format!(
+            "error: {} at {}:{}: {}",
+            error.message, location.line, location.column, label
+        ),
+        (Some(location), None) => format!(
+            "error: {} at {}:{}",
+            error.message, location.line, location.column
+        ),
+        (None, _) => format!("error: {}", error.message),
+    }
+}
+
+/// 查找所有 `/* expect: ... */` 指令，返回冒号之后到注释结束之前的范围
+fn directives(source: &str) -> Vec<Range<usize>> {
+    let mut directives = vec![];
+    let mut rest = 0;
+    while let Some(start) = source[rest..].find("/*") {
+        let content = rest + start + 2;
+        let Some(len) = source[content..].find("*/") else {
+            break;
+        };
+        let end = content + len;
+        let text = &source[content..end];
+        let trimmed = text.trim_start();
+        if let Some(stripped) = trimmed.strip_prefix(DIRECTIVE) {
+            directives.push(end - stripped.len()..end);
+        }
+        rest = end + 2;
+    }
+    directives
+}
+
+/// 逐行比较期望与实际的输出
+///
+/// 相同的行以空格开头，只在期望中的行以 `-` 开头，只在实际中的行以 `+` 开头。
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+
+    // 最长公共子序列，lcs[i][j] 是 expected[i..] 与 actual[j..] 的结果
+    let mut lcs = vec![vec![0usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut diff = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            diff.push_str(&format!(" {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push_str(&format!("-{}\n", expected[i]));
+            i += 1;
+        } else {
+            diff.push_str(&format!("+{}\n", actual[j]));
+            j += 1;
+        }
+    }
+    diff
+}
+
+/// 分别在解释器与编译得到的 C 程序中运行同一个程序，供测试比较两者的行为
+#[cfg(test)]
+pub(crate) mod backends {
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::path::Path;
+    use std::process::Command;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::backend::interpreter::RuntimeError;
+    use crate::backend::target;
+    use crate::options::Options;
+    use crate::Context;
+
+    /// 一次运行的输出
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Output {
+        pub(crate) stdout: String,
+        pub(crate) stderr: String,
+        /// 退出状态，超出上限时为 3
+        pub(crate) status: i32,
+        /// 设置了 `trace_file` 时写入的内容
+        pub(crate) trace_file: Option<String>,
+    }
+
+    /// 在两个后端中运行，断言输出完全一致，返回解释器的输出
+    ///
+    /// `options.trace_file` 只表示是否写入跟踪文件，实际的路径位于临时目录中。
+    pub(crate) fn run(source: &str, options: Options) -> Output {
+        let dir = temp_dir();
+        let interpreted = interpret(source, options.clone(), &dir);
+        let compiled = compile(source, options, &dir);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(interpreted, compiled, "the interpreter and C disagree");
+        interpreted
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "zamuza-test-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn context(source: &str, mut options: Options, trace_file: &Path) -> Context {
+        if options.trace_file.is_some() {
+            options.trace_file = Some(trace_file.to_path_buf());
+        }
+        Context::new()
+            .set_options(options)
+            .add_file("test.zz", source)
+            .unwrap()
+    }
+
+    fn read_trace(path: &Path, options: &Options) -> Option<String> {
+        options
+            .trace_file
+            .as_ref()
+            .map(|_| std::fs::read_to_string(path).unwrap())
+    }
+
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn interpret(source: &str, options: Options, dir: &Path) -> Output {
+        let trace_file = dir.join("interpreter.jsonl");
+        let (mut stdout, stderr) = (vec![], Buffer::default());
+        let result =
+            context(source, options.clone(), &trace_file).run_with(&mut stdout, stderr.clone());
+        let status = match result {
+            Ok(()) => 0,
+            Err(e) => match e.downcast_ref::<RuntimeError>() {
+                Some(e) if e.is_limit() => 3,
+                Some(_) => 1,
+                None => panic!("{e}"),
+            },
+        };
+        let stderr = stderr.0.borrow().clone();
+        Output {
+            stdout: String::from_utf8(stdout).unwrap(),
+            stderr: String::from_utf8(stderr).unwrap(),
+            status,
+            trace_file: read_trace(&trace_file, &options),
+        }
+    }
+
+    fn compile(source: &str, options: Options, dir: &Path) -> Output {
+        let trace_file = dir.join("c.jsonl");
+        let (c, exe) = (dir.join("main.c"), dir.join("main"));
+        context(source, options.clone(), &trace_file)
+            .output_file::<target::C>(&c)
+            .unwrap();
+        let cc = Command::new("cc")
+            .arg("-w")
+            .arg(&c)
+            .arg("-o")
+            .arg(&exe)
+            .status()
+            .unwrap();
+        assert!(cc.success(), "cannot compile the generated C program");
+
+        let output = Command::new(&exe).output().unwrap();
+        Output {
+            stdout: String::from_utf8(output.stdout).unwrap(),
+            stderr: String::from_utf8(output.stderr).unwrap(),
+            status: output.status.code().unwrap_or(-1),
+            trace_file: read_trace(&trace_file, &options),
+        }
+    }
+}
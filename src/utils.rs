@@ -1,4 +1,10 @@
-use std::{fmt::Display, ops::Deref};
+use std::{
+    cell::RefCell,
+    fmt::Display,
+    io::Write,
+    ops::{Deref, DerefMut},
+    rc::Rc,
+};
 
 use annotate_snippets::snippet::{AnnotationType, Slice, SourceAnnotation};
 
@@ -29,6 +35,12 @@ impl<'a, T> Deref for Span<'a, T> {
     }
 }
 
+impl<'a, T> DerefMut for Span<'a, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
 impl<'a, T> AsRef<T> for Span<'a, T> {
     fn as_ref(&self) -> &T {
         &self.inner
@@ -56,37 +68,39 @@ impl<'a, T> Span<'a, T> {
         self.inner
     }
 
+    /// 仅保留位置信息。
+    pub fn as_unit(&self) -> Span<'a, ()> {
+        self.with(())
+    }
+
+    /// 保留位置信息，替换内部的值。
+    pub fn with<U>(&self, inner: U) -> Span<'a, U> {
+        Span::new(inner, self.filename, self.source, self.start, self.end)
+    }
+
     pub fn lines(&self) -> Option<LinesInfo<'a>> {
-        let mut lines = self.source.split_inclusive('\n').enumerate();
-
-        let mut start = 0;
-        let mut line_start = 0;
-        for (i, line) in lines.by_ref() {
-            let next = start + line.len();
-            if next > self.start {
-                line_start = i + 1;
-                break;
-            }
-            start = next;
-        }
-        if line_start == 0 {
+        if self.start > self.source.len() || self.end > self.source.len() {
             return None;
         }
 
-        let mut end = start;
-        for (_, line) in lines {
-            let next = end + line.len();
-            if next > self.end {
-                break;
-            }
-            end = next;
-        }
+        // 语法中可选部分之前的空白会被计入片段，显示时去掉
+        let span_end = self.start + self.source[self.start..self.end].trim_end().len();
+
+        let start = self.source[..self.start]
+            .rfind('\n')
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let end = self.source[span_end..]
+            .find('\n')
+            .map(|i| span_end + i)
+            .unwrap_or(self.source.len());
+        let line_start = self.source[..start].matches('\n').count() + 1;
 
         Some(LinesInfo {
             filename: self.filename,
             source: &self.source[start..end],
             line_start,
-            range: (self.start - start, self.end - start),
+            range: (self.start - start, span_end - start),
         })
     }
 }
@@ -119,3 +133,28 @@ impl<'a> LinesInfo<'a> {
         }
     }
 }
+
+/// 可以复制的输出流，所有副本按写入的先后顺序写入同一个流
+pub(crate) struct Shared<W>(Rc<RefCell<W>>);
+
+impl<W> Shared<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self(Rc::new(RefCell::new(inner)))
+    }
+}
+
+impl<W> Clone for Shared<W> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<W: Write> Write for Shared<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
@@ -309,14 +309,14 @@ pub struct RelocatedContext {
 
 impl RelocatedContext {
     /// get a pointer to a generated function
-    pub fn get_symbol(&self, name: &CStr) -> Option<Symbol> {
+    pub fn get_symbol(&self, name: &CStr) -> Option<Symbol<'_>> {
         let addr = unsafe { bindings::tcc_get_symbol(self.inner, name.as_ptr()) };
         let addr = NonNull::new(addr)?;
         Some(unsafe { Symbol::new(addr) })
     }
 
     /// list all symbols
-    pub fn list_symbols(&self) -> Vec<(&CStr, Symbol)> {
+    pub fn list_symbols(&self) -> Vec<(&CStr, Symbol<'_>)> {
         let mut symbols: Vec<(&CStr, Symbol)> = Vec::new();
 
         extern "C" fn symbol_callback(ctx: *mut c_void, name: *const c_char, val: *const c_void) {